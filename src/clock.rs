@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// A source for the current instant.
+///
+/// Everything in sundial that needs to know "now" (a missing DTSTART, scoping results
+/// from today) reads it from a `Clock`, so callers can pin it and get reproducible
+/// schedules.
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The default clock which reads the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that is stuck at a single instant, handy for tests and for replaying
+/// what a schedule looked like at a given point in time.
+///
+/// Example:
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use sundial::{Clock, FixedClock};
+/// let clock = FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0));
+/// assert_eq!(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0), clock.now());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedClock {
+    now: DateTime<Utc>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> FixedClock {
+        FixedClock { now }
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+}
+
+/// The clock a rule reads "now" from, see `RRule::with_clock`. Clocks can't be compared,
+/// so any two rule clocks compare equal.
+#[derive(Clone)]
+pub(crate) struct RuleClock(Arc<dyn Clock + Send + Sync>);
+
+impl RuleClock {
+    pub(crate) fn new<C: Clock + Send + Sync + 'static>(clock: C) -> RuleClock {
+        RuleClock(Arc::new(clock))
+    }
+}
+
+impl Default for RuleClock {
+    fn default() -> RuleClock {
+        RuleClock::new(SystemClock)
+    }
+}
+
+impl Clock for RuleClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.now()
+    }
+}
+
+impl Debug for RuleClock {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("RuleClock")
+    }
+}
+
+impl PartialEq for RuleClock {
+    fn eq(&self, _other: &RuleClock) -> bool {
+        true
+    }
+}
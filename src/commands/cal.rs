@@ -0,0 +1,147 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::HashSet;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Months printed next to each other, like `cal -3`
+const MONTHS_PER_ROW: usize = 3;
+
+/// Every day cell, and so every month, is this wide
+const DAY_WIDTH: usize = 3;
+const MONTH_WIDTH: usize = DAY_WIDTH * 7;
+
+/// How the occurrence days are marked in the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    /// Reverse video, for terminals
+    Ansi,
+    /// A `*` in front of the day, for when the output is piped or redirected
+    Marker,
+}
+
+/// The first day of the month `months` after the month of `date`
+pub fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    let month_index = date.year() * 12 + date.month0() as i32 + months as i32;
+    NaiveDate::from_ymd(
+        month_index.div_euclid(12),
+        month_index.rem_euclid(12) as u32 + 1,
+        1,
+    )
+}
+
+/// Renders `months` month grids starting at the month of `start`, with the days in
+/// `occurrences` highlighted and `week_start` as the first column
+pub fn render_months(
+    start: NaiveDate,
+    months: u32,
+    week_start: Weekday,
+    occurrences: &HashSet<NaiveDate>,
+    highlight: Highlight,
+) -> String {
+    let grids: Vec<Vec<String>> = (0..months)
+        .map(|month| render_month(add_months(start, month), week_start, occurrences, highlight))
+        .collect();
+
+    let mut lines: Vec<String> = Vec::new();
+    for row in grids.chunks(MONTHS_PER_ROW) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        for line in 0..row[0].len() {
+            let joined = row
+                .iter()
+                .map(|grid| grid[line].as_str())
+                .collect::<Vec<&str>>()
+                .join("  ");
+            lines.push(joined.trim_end().to_string());
+        }
+    }
+    lines.join("\n")
+}
+
+/// Renders a single month as lines of `MONTH_WIDTH` visible characters: the title, the
+/// weekday names and always six weeks so months can be placed next to each other
+fn render_month(
+    first_day: NaiveDate,
+    week_start: Weekday,
+    occurrences: &HashSet<NaiveDate>,
+    highlight: Highlight,
+) -> Vec<String> {
+    let title = format!(
+        "{} {}",
+        MONTH_NAMES[first_day.month0() as usize],
+        first_day.year()
+    );
+    let mut lines = vec![format!("{:^width$}", title, width = MONTH_WIDTH)];
+
+    let mut weekday = week_start;
+    let mut header = String::new();
+    for _ in 0..7 {
+        header.push_str(&format!(
+            "{:>width$}",
+            weekday_short_name(weekday),
+            width = DAY_WIDTH
+        ));
+        weekday = weekday.succ();
+    }
+    lines.push(header);
+
+    let leading_days =
+        (first_day.weekday().num_days_from_monday() + 7 - week_start.num_days_from_monday()) % 7;
+    let mut week = " ".repeat(DAY_WIDTH * leading_days as usize);
+    let mut column = leading_days;
+    let mut day = first_day;
+    while day.month() == first_day.month() {
+        week.push_str(&render_day(day, occurrences.contains(&day), highlight));
+        column += 1;
+        if column == 7 {
+            lines.push(week);
+            week = String::new();
+            column = 0;
+        }
+        day = day.succ();
+    }
+    if column > 0 {
+        week.push_str(&" ".repeat(DAY_WIDTH * (7 - column) as usize));
+        lines.push(week);
+    }
+    while lines.len() < 8 {
+        lines.push(" ".repeat(MONTH_WIDTH));
+    }
+    lines
+}
+
+fn render_day(day: NaiveDate, is_occurrence: bool, highlight: Highlight) -> String {
+    match (is_occurrence, highlight) {
+        (false, _) => format!("{:>width$}", day.day(), width = DAY_WIDTH),
+        (true, Highlight::Ansi) => format!(" \u{1b}[7m{:>2}\u{1b}[0m", day.day()),
+        (true, Highlight::Marker) => {
+            format!("{:>width$}", format!("*{}", day.day()), width = DAY_WIDTH)
+        }
+    }
+}
+
+fn weekday_short_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Mo",
+        Weekday::Tue => "Tu",
+        Weekday::Wed => "We",
+        Weekday::Thu => "Th",
+        Weekday::Fri => "Fr",
+        Weekday::Sat => "Sa",
+        Weekday::Sun => "Su",
+    }
+}
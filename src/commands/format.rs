@@ -0,0 +1,154 @@
+use chrono::{DateTime, Datelike, FixedOffset, SecondsFormat, Utc, Weekday};
+use sundial::{format_ics_calendar, format_ics_date};
+
+/// The formats the iter dates can be printed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    Lines,
+    Ics,
+    Table,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "lines" => Some(OutputFormat::Lines),
+            "ics" => Some(OutputFormat::Ics),
+            "table" => Some(OutputFormat::Table),
+            _ => None,
+        }
+    }
+}
+
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+fn rfc3339(date: &DateTime<FixedOffset>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn utc_rfc3339(date: &DateTime<FixedOffset>) -> String {
+    date.with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// One RFC 3339 timestamp per line
+pub fn render_lines(dates: &[DateTime<FixedOffset>]) -> String {
+    dates
+        .iter()
+        .map(rfc3339)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// A CSV with a header row and the 1 based index, timestamp, weekday and UTC timestamp
+/// of every date
+pub fn render_csv(dates: &[DateTime<FixedOffset>]) -> String {
+    let mut rows = vec![String::from("index,datetime,weekday,utc")];
+    for (index, date) in dates.iter().enumerate() {
+        rows.push(format!(
+            "{},{},{},{}",
+            index + 1,
+            rfc3339(date),
+            weekday_name(date.weekday()),
+            utc_rfc3339(date)
+        ));
+    }
+    rows.join("\n")
+}
+
+/// An aligned table for reading the dates in a terminal
+pub fn render_table(dates: &[DateTime<FixedOffset>]) -> String {
+    let header = ["#", "Weekday", "Date", "Time", "Offset", "UTC"];
+    let mut rows: Vec<Vec<String>> = vec![header.iter().map(|cell| cell.to_string()).collect()];
+    for (index, date) in dates.iter().enumerate() {
+        rows.push(vec![
+            (index + 1).to_string(),
+            weekday_name(date.weekday()).to_string(),
+            date.format("%Y-%m-%d").to_string(),
+            date.format("%H:%M:%S").to_string(),
+            date.format("%:z").to_string(),
+            utc_rfc3339(date),
+        ]);
+    }
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths.iter())
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    // numbers are right aligned, everything else left aligned
+                    if column == 0 {
+                        format!("{:>width$}", cell, width = width)
+                    } else {
+                        format!("{:<width$}", cell, width = width)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// A VCALENDAR with one VEVENT per date, `stamp` is the DTSTAMP of every event
+pub fn render_ics_events(dates: &[DateTime<FixedOffset>], stamp: DateTime<Utc>) -> String {
+    let events = dates
+        .iter()
+        .enumerate()
+        .map(|(index, date)| {
+            let start = format_ics_date(&date.with_timezone(&Utc));
+            (
+                "VEVENT",
+                vec![
+                    format!("UID:{}-{}@sundial", start, index + 1),
+                    format!("DTSTAMP:{}", format_ics_date(&stamp)),
+                    format!("DTSTART:{}", start),
+                ],
+            )
+        })
+        .collect();
+    format_ics_calendar(events)
+}
+
+/// A VCALENDAR with a single VEVENT carrying the rule. `first` is the first occurrence in
+/// the rule's timezone, which is written as a local time when the rule has a `tzid`.
+pub fn render_ics_rrule(
+    first: &DateTime<FixedOffset>,
+    tzid: Option<&str>,
+    rrule: &str,
+    stamp: DateTime<Utc>,
+) -> String {
+    let start = format_ics_date(&first.with_timezone(&Utc));
+    let dtstart = match tzid {
+        Some(tzid) => format!("DTSTART;TZID={}:{}", tzid, first.format("%Y%m%dT%H%M%S")),
+        None => format!("DTSTART:{}", start),
+    };
+    format_ics_calendar(vec![(
+        "VEVENT",
+        vec![
+            format!("UID:{}-rrule@sundial", start),
+            format!("DTSTAMP:{}", format_ics_date(&stamp)),
+            dtstart,
+            format!("RRULE:{}", rrule),
+        ],
+    )])
+}
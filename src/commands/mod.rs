@@ -0,0 +1,5 @@
+//! The larger cli subcommands and output formats, the argument handling lives in `main.rs`
+
+pub mod cal;
+pub mod format;
+pub mod repl;
@@ -0,0 +1,257 @@
+//! `sundial repl`, a rule is typed once and then tweaked a part at a time, with the
+//! occurrences every edit adds and removes shown as a diff
+
+use chrono::{DateTime, SecondsFormat};
+use chrono_tz::Tz;
+use std::cmp::Ordering;
+use sundial::{parse_rrule, validate_rrule};
+
+pub const HELP: &str = "\
+<rrule>             start over with a new rule, e.g. FREQ=DAILY;BYHOUR=9;BYMINUTE=0
+set PART=VALUE      set parts of the rule, e.g. set BYHOUR=9 or set BYHOUR=9;BYMINUTE=30
+unset PART          remove parts of the rule, e.g. unset COUNT
+next [N]            list the next N occurrences, and keep showing N of them
+show                print the parsed rule and its occurrences
+help                print this help
+quit                leave the repl";
+
+/// Most occurrences `next` lists
+pub const MAX_COUNT: usize = 1000;
+
+/// The rule being edited and the occurrences last shown for it
+pub struct Session {
+    /// The normalized rule, `None` until one is typed
+    rule: Option<String>,
+    count: usize,
+    occurrences: Vec<DateTime<Tz>>,
+}
+
+/// A rule that parsed and validated, with its first occurrences
+struct Evaluated {
+    rule: String,
+    json: String,
+    occurrences: Vec<DateTime<Tz>>,
+}
+
+impl Session {
+    /// A session listing `count` occurrences
+    pub fn new(count: usize) -> Session {
+        Session {
+            rule: None,
+            count,
+            occurrences: Vec::new(),
+        }
+    }
+
+    /// Runs a line of input and returns what to print, `None` when the session is over.
+    /// A rule or edit that fails to parse or validate leaves the session as it was.
+    pub fn execute(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        let reply = match command.to_lowercase().as_str() {
+            "" => Ok(String::new()),
+            "quit" | "exit" => return None,
+            "help" => Ok(HELP.to_string()),
+            "set" => self.set(argument),
+            "unset" => self.unset(argument),
+            "next" => self.next(argument),
+            "show" => self.show(),
+            _ if line.contains('=') => self.load(line),
+            _ => Err(format!(
+                "unknown command {:?}, type help to list the commands",
+                command
+            )),
+        };
+        Some(reply.unwrap_or_else(|err| format!("error: {}", err)))
+    }
+
+    fn load(&mut self, rule: &str) -> Result<String, String> {
+        let evaluated = self.evaluate(rule)?;
+        let reply = format!(
+            "{}\n{}",
+            evaluated.json,
+            list_occurrences(&evaluated.occurrences)
+        );
+        self.update(evaluated);
+        Ok(reply)
+    }
+
+    fn set(&mut self, argument: &str) -> Result<String, String> {
+        if argument.is_empty() {
+            return Err(String::from("expected PART=VALUE after set"));
+        }
+        let mut parts = self.parts();
+        for assignment in argument.split(';').filter(|part| !part.trim().is_empty()) {
+            let (name, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| format!("expected PART=VALUE, found {:?}", assignment.trim()))?;
+            let name = name.trim().to_uppercase();
+            let part = format!("{}={}", name, value.trim());
+            match parts.iter().position(|(existing, _)| *existing == name) {
+                Some(index) => parts[index] = (name, part),
+                None => parts.push((name, part)),
+            }
+        }
+        self.edit(parts)
+    }
+
+    fn unset(&mut self, argument: &str) -> Result<String, String> {
+        let mut parts = self.parts();
+        let names: Vec<String> = argument
+            .split(|c: char| c.is_whitespace() || c == ';' || c == ',')
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_uppercase())
+            .collect();
+        if names.is_empty() {
+            return Err(String::from("expected the name of a part after unset"));
+        }
+        for name in names.iter() {
+            let index = parts
+                .iter()
+                .position(|(existing, _)| existing == name)
+                .ok_or_else(|| format!("the rule has no {} part", name))?;
+            parts.remove(index);
+        }
+        self.edit(parts)
+    }
+
+    fn next(&mut self, argument: &str) -> Result<String, String> {
+        if !argument.is_empty() {
+            self.count = argument
+                .parse::<usize>()
+                .ok()
+                .filter(|count| (1..=MAX_COUNT).contains(count))
+                .ok_or_else(|| format!("{} is not a number from 1 to {}", argument, MAX_COUNT))?;
+        }
+        let evaluated = self.evaluate(self.current_rule()?)?;
+        let reply = list_occurrences(&evaluated.occurrences);
+        self.update(evaluated);
+        Ok(reply)
+    }
+
+    fn show(&mut self) -> Result<String, String> {
+        let rule = self.current_rule()?.to_string();
+        self.load(&rule)
+    }
+
+    /// Applies an edit, printing the new rule and how its occurrences changed
+    fn edit(&mut self, parts: Vec<(String, String)>) -> Result<String, String> {
+        let rule = parts
+            .into_iter()
+            .map(|(_, part)| part)
+            .collect::<Vec<String>>()
+            .join(";");
+        if rule.is_empty() {
+            return Err(String::from("the edit leaves the rule empty"));
+        }
+        let evaluated = self.evaluate(&rule)?;
+        let reply = format!(
+            "{}\n{}",
+            evaluated.rule,
+            diff_occurrences(&self.occurrences, &evaluated.occurrences)
+        );
+        self.update(evaluated);
+        Ok(reply)
+    }
+
+    fn evaluate(&self, rule: &str) -> Result<Evaluated, String> {
+        let rrule = parse_rrule(rule).map_err(|err| format!("{} in rrule {}", err, rule))?;
+        validate_rrule(&rrule).map_err(|err| err.to_string())?;
+        let normalized = rrule.to_rrule_string();
+        // the count given to the iterator takes the place of COUNT, so the smaller one wins
+        let count = match part_value(&normalized, "COUNT") {
+            Some(count) => count.parse::<usize>().unwrap_or(0).min(self.count),
+            None => self.count,
+        };
+        Ok(Evaluated {
+            json: rrule.to_json_pretty(),
+            occurrences: rrule.get_all_iter_dates(&count.to_string(), ""),
+            rule: normalized,
+        })
+    }
+
+    fn update(&mut self, evaluated: Evaluated) {
+        self.rule = Some(evaluated.rule);
+        self.occurrences = evaluated.occurrences;
+    }
+
+    fn current_rule(&self) -> Result<&str, String> {
+        self.rule.as_deref().ok_or_else(|| {
+            String::from("there is no rule yet, type one such as FREQ=DAILY;BYHOUR=9")
+        })
+    }
+
+    /// The parts of the current rule with their names, in the normalized order
+    fn parts(&self) -> Vec<(String, String)> {
+        self.rule
+            .as_deref()
+            .unwrap_or("")
+            .split(';')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let name = part.split('=').next().unwrap_or(part);
+                (name.to_string(), part.to_string())
+            })
+            .collect()
+    }
+}
+
+/// The value of the part `name` in a normalized rule
+fn part_value<'r>(rule: &'r str, name: &str) -> Option<&'r str> {
+    rule.split(';').find_map(|part| {
+        part.split_once('=')
+            .filter(|(part_name, _)| *part_name == name)
+            .map(|(_, value)| value)
+    })
+}
+
+fn format_occurrence(date: &DateTime<Tz>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn list_occurrences(occurrences: &[DateTime<Tz>]) -> String {
+    if occurrences.is_empty() {
+        return String::from("no occurrences");
+    }
+    occurrences
+        .iter()
+        .map(format_occurrence)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Merges the sorted occurrences before and after an edit, prefixing the ones the edit
+/// removed with `-`, the ones it added with `+` and the ones it kept with a space.
+/// Occurrences are compared as instants so changing the TZID alone keeps them.
+fn diff_occurrences(before: &[DateTime<Tz>], after: &[DateTime<Tz>]) -> String {
+    let mut lines = Vec::new();
+    let (mut added, mut removed) = (0, 0);
+    let (mut old, mut new) = (before.iter().peekable(), after.iter().peekable());
+    loop {
+        let ordering = match (old.peek(), new.peek()) {
+            (Some(old_date), Some(new_date)) => old_date.cmp(new_date),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+        match ordering {
+            Ordering::Less => {
+                removed += 1;
+                lines.push(format!("- {}", format_occurrence(old.next().unwrap())));
+            }
+            Ordering::Greater => {
+                added += 1;
+                lines.push(format!("+ {}", format_occurrence(new.next().unwrap())));
+            }
+            Ordering::Equal => {
+                old.next();
+                lines.push(format!("  {}", format_occurrence(new.next().unwrap())));
+            }
+        }
+    }
+    lines.push(format!("{} added, {} removed", added, removed));
+    lines.join("\n")
+}
@@ -0,0 +1,81 @@
+use crate::{Event, OccurrenceSpan};
+use chrono::{DateTime, Duration, Utc};
+
+/// Two occurrences that share some time, `first` is the one that starts first, or the one
+/// of the earlier source when both start together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict<S> {
+    pub first: (S, OccurrenceSpan),
+    pub second: (S, OccurrenceSpan),
+    /// How much time the occurrences share
+    pub overlap: Duration,
+}
+
+/// Finds every pair of occurrences of `events` that share some time, among the occurrences
+/// that have any time at or after `after` and before `before`. Each event comes with a
+/// source id like in `merge`, and occurrences of the same event that overlap each other
+/// conflict too. Occurrences that only touch, one ending when the other starts, and
+/// occurrences without length don't conflict. The conflicts are in the order their first
+/// occurrences start.
+///
+/// Example:
+/// ```
+/// use chrono::{Duration, TimeZone, Utc};
+/// use sundial::{convert_to_rrule, find_conflicts, Event};
+/// let bakery = convert_to_rrule("FREQ=WEEKLY;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let dairy = convert_to_rrule("FREQ=DAILY;BYHOUR=10;BYMINUTE=30;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let conflicts = find_conflicts(
+///     &[
+///         ("bakery", Event::with_duration(&bakery, "PT2H").unwrap()),
+///         ("dairy", Event::with_duration(&dairy, "PT1H").unwrap()),
+///     ],
+///     Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+///     Utc.ymd(2019, 4, 21).and_hms(0, 0, 0),
+/// );
+/// assert_eq!(1, conflicts.len());
+/// assert_eq!("2019-04-16T10:30:00+10:00", conflicts[0].second.1.start.to_rfc3339());
+/// assert_eq!(Duration::minutes(30), conflicts[0].overlap);
+/// ```
+pub fn find_conflicts<S: Clone>(
+    events: &[(S, Event)],
+    after: DateTime<Utc>,
+    before: DateTime<Utc>,
+) -> Vec<Conflict<S>> {
+    let mut spans: Vec<(usize, OccurrenceSpan)> = events
+        .iter()
+        .enumerate()
+        .flat_map(|(index, (_, event))| {
+            event
+                .spans_between(after, before)
+                .into_iter()
+                .map(move |span| (index, span))
+        })
+        .collect();
+    spans.sort_by_key(|(index, span)| (span.start.with_timezone(&Utc), *index));
+
+    // the occurrences that haven't ended by the start of the one being looked at, every
+    // one of them overlaps it unless it has no length
+    let mut conflicts = Vec::new();
+    let mut ongoing: Vec<(usize, OccurrenceSpan)> = Vec::new();
+    for (index, span) in spans {
+        ongoing.retain(|(_, other)| other.end > span.start);
+        for (other_index, other) in ongoing.iter() {
+            let overlap = other.end.min(span.end).signed_duration_since(span.start);
+            if overlap > Duration::zero() {
+                conflicts.push(Conflict {
+                    first: (events[*other_index].0.clone(), *other),
+                    second: (events[index].0.clone(), span),
+                    overlap,
+                });
+            }
+        }
+        ongoing.push((index, span));
+    }
+    conflicts.sort_by_key(|conflict| {
+        (
+            conflict.first.1.start.with_timezone(&Utc),
+            conflict.second.1.start.with_timezone(&Utc),
+        )
+    });
+    conflicts
+}
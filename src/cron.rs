@@ -0,0 +1,268 @@
+use crate::fields::{join_day_ranges, join_field, CalendarFields};
+use crate::{validate_rrule, RRule};
+use chrono::Weekday;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Weekdays in cron order, where 0 and 7 are both Sunday
+const CRON_WEEKDAYS: [Weekday; 8] = [
+    Weekday::Sun,
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+const CRON_MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const CRON_WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Returned when a cron expression can't be turned into a rule or a rule can't be
+/// expressed in cron, the message says why
+#[derive(Debug, Clone)]
+pub struct CronError {
+    message: String,
+}
+
+impl CronError {
+    fn new<S: Into<String>>(message: S) -> CronError {
+        CronError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for CronError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "cron conversion error: {}", self.message)
+    }
+}
+
+impl Error for CronError {
+    fn description(&self) -> &str {
+        "cron conversion error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// A cron field, either `*` or the values it lists
+#[derive(Debug, PartialEq)]
+enum CronField {
+    Any,
+    Values(Vec<u32>),
+}
+
+impl CronField {
+    /// The values of the field as a calendar field, none for `*`
+    fn into_values(self) -> Vec<u32> {
+        match self {
+            CronField::Any => Vec::new(),
+            CronField::Values(values) => values,
+        }
+    }
+}
+
+impl RRule<'static> {
+    /// Builds a rule from a five field cron expression (minute, hour, day of the month,
+    /// month and day of the week) evaluated in the timezone `tzid`, UTC when it is empty.
+    /// A leading `CRON_TZ=` or `TZ=` in the expression takes precedence over `tzid`, and
+    /// the `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` macros are accepted.
+    /// Lists, ranges and steps such as `*/15` become the list of values they match.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::RRule;
+    /// let rrule = RRule::from_cron("30 9 * * 1-5", "Australia/Sydney").unwrap();
+    /// assert_eq!(
+    ///     "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;BYSECOND=0;TZID=Australia/Sydney",
+    ///     rrule.to_rrule_string()
+    /// );
+    ///
+    /// let rrule = RRule::from_cron("*/15 * * * *", "").unwrap();
+    /// assert_eq!("FREQ=HOURLY;BYMINUTE=0,15,30,45;BYSECOND=0", rrule.to_rrule_string());
+    /// ```
+    pub fn from_cron(expression: &str, tzid: &str) -> Result<RRule<'static>, CronError> {
+        let mut tzid = tzid;
+        let mut fields: Vec<&str> = expression.split_whitespace().collect();
+        if let Some(first) = fields.first() {
+            if let Some(cron_tz) = first
+                .strip_prefix("CRON_TZ=")
+                .or_else(|| first.strip_prefix("TZ="))
+            {
+                tzid = cron_tz;
+                fields.remove(0);
+            }
+        }
+        let fields = match fields.as_slice() {
+            ["@yearly"] | ["@annually"] => vec!["0", "0", "1", "1", "*"],
+            ["@monthly"] => vec!["0", "0", "1", "*", "*"],
+            ["@weekly"] => vec!["0", "0", "*", "*", "0"],
+            ["@daily"] | ["@midnight"] => vec!["0", "0", "*", "*", "*"],
+            ["@hourly"] => vec!["0", "*", "*", "*", "*"],
+            [macro_name] if macro_name.starts_with('@') => {
+                return Err(CronError::new(format!("unknown macro {:?}", macro_name)))
+            }
+            fields if fields.len() == 5 => fields.to_vec(),
+            fields => {
+                return Err(CronError::new(format!(
+                    "expected 5 fields (minute, hour, day of month, month, day of week), found {}",
+                    fields.len()
+                )))
+            }
+        };
+
+        let minute = parse_field(fields[0], "minute", 0, 59, &[])?;
+        let hour = parse_field(fields[1], "hour", 0, 23, &[])?;
+        let month_day = parse_field(fields[2], "day of month", 1, 31, &[])?;
+        let month = parse_field(fields[3], "month", 1, 12, &CRON_MONTH_NAMES)?;
+        let weekday = parse_field(fields[4], "day of week", 0, 7, &CRON_WEEKDAY_NAMES)?;
+
+        if fields[2] != "*" && fields[2] != "?" && weekday != CronField::Any {
+            return Err(CronError::new(
+                "cron runs when either the day of month or the day of week matches, a rule can't restrict both",
+            ));
+        }
+        let calendar_fields = CalendarFields {
+            seconds: vec![0],
+            minutes: minute.into_values(),
+            hours: hour.into_values(),
+            month_days: month_day.into_values(),
+            months: month.into_values(),
+            weekdays: weekday
+                .into_values()
+                .iter()
+                .map(|weekday| CRON_WEEKDAYS[*weekday as usize])
+                .collect(),
+            tzid: tzid.to_string(),
+        };
+
+        let rrule = calendar_fields.into_rrule().map_err(CronError::new)?;
+        validate_rrule(&rrule).map_err(|error| CronError::new(error.to_string()))?;
+        Ok(rrule)
+    }
+}
+
+impl<'a> RRule<'a> {
+    /// Builds the five field cron expression the rule occurs on, prefixed with `CRON_TZ=`
+    /// when the rule has a TZID. DTSTART only provides the time of day, or the day for
+    /// weekly and coarser rules without BY parts, as cron has no start date. Rules cron
+    /// can't express, such as ones with an INTERVAL, COUNT or UNTIL, return an error
+    /// saying why.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30").unwrap();
+    /// assert_eq!("30 9 * * 1-5", rrule.to_cron().unwrap());
+    ///
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO").unwrap();
+    /// assert!(rrule.to_cron().is_err());
+    /// ```
+    pub fn to_cron(&self) -> Result<String, CronError> {
+        let fields = self.calendar_fields("cron").map_err(CronError::new)?;
+        if fields.seconds.iter().any(|second| *second != 0) {
+            return Err(CronError::new(
+                "cron has a precision of a minute, the rule runs at a second other than 0",
+            ));
+        }
+
+        let field = |values: &[u32]| join_field(values, "-", |value| value.to_string());
+        let mut weekdays: Vec<u32> = fields
+            .weekdays
+            .iter()
+            .map(|weekday| weekday.num_days_from_sunday())
+            .collect();
+        weekdays.sort_unstable();
+        weekdays.dedup();
+        let weekday = if weekdays.is_empty() {
+            String::from("*")
+        } else {
+            join_day_ranges(&weekdays, "-", |day| day.to_string())
+        };
+
+        let expression = format!(
+            "{} {} {} {} {}",
+            field(&fields.minutes),
+            field(&fields.hours),
+            field(&fields.month_days),
+            field(&fields.months),
+            weekday
+        );
+        if fields.tzid.is_empty() {
+            Ok(expression)
+        } else {
+            Ok(format!("CRON_TZ={} {}", fields.tzid, expression))
+        }
+    }
+}
+
+/// Parses a cron field made of `*`, numbers, names, `a-b` ranges, `*/n` and `a-b/n` steps
+/// and lists of those
+fn parse_field(
+    field: &str,
+    name: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+) -> Result<CronField, CronError> {
+    if field == "*" || field == "?" {
+        return Ok(CronField::Any);
+    }
+    let invalid = || {
+        CronError::new(format!(
+            "invalid {} field {:?}, values are {}-{}",
+            name, field, min, max
+        ))
+    };
+    let value = |value: &str| -> Result<u32, CronError> {
+        let lower = value.to_lowercase();
+        let number = match names.iter().position(|name| *name == lower) {
+            // month names start at 1, weekday names at 0
+            Some(position) => position as u32 + min.min(1),
+            None => value.parse().map_err(|_| invalid())?,
+        };
+        if number < min || number > max {
+            Err(invalid())
+        } else {
+            Ok(number)
+        }
+    };
+
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            None => {
+                let start = value(range)?;
+                (start, if step > 1 { max } else { start })
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    if values.len() as u32 == max - min + 1 {
+        // `*/1` or a range over every value
+        return Ok(CronField::Any);
+    }
+    Ok(CronField::Values(values))
+}
@@ -0,0 +1,220 @@
+use crate::dst::resolve_local;
+use crate::holidays::{MAX_DROPPED_OCCURRENCES, MAX_SHIFT_DAYS};
+use crate::{Clock, DstPolicy, GapPolicy, HolidayCalendar, RRule};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use std::iter::Peekable;
+
+/// When orders for a delivery close, relative to the delivery
+pub enum CutoffOffset<'o> {
+    /// A fixed amount of time before the delivery, e.g. 36 hours.
+    Duration(Duration),
+    /// A number of business days before the day of the delivery at a local time of day in
+    /// the delivery's timezone, e.g. 2 business days before at 14:00. 0 days is the day
+    /// of the delivery.
+    BusinessDays {
+        days: u32,
+        time: NaiveTime,
+        calendar: &'o dyn HolidayCalendar,
+    },
+    /// The last occurrence of another rule strictly before the delivery, e.g. a weekly
+    /// order run on Mondays at 14:00 for the deliveries later that week. Without a DTSTART
+    /// the other rule is looked back for a year or one of its INTERVALs, whichever is
+    /// longer, so deliveries in the first gap of a rule skipping more than that, such as
+    /// one on February 29, have no cutoff.
+    PreviousOccurrence(&'o RRule<'o>),
+}
+
+/// The occurrences of a delivery rule paired with the order cutoff derived from each of
+/// them by a `CutoffOffset`.
+///
+/// Example:
+/// ```
+/// use chrono::{NaiveTime, TimeZone, Utc};
+/// use sundial::{convert_to_rrule, CutoffOffset, DerivedSchedule, InMemoryHolidayCalendar};
+/// let deliveries = convert_to_rrule("FREQ=WEEKLY;BYDAY=TU,FR;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190401T000000").unwrap();
+/// let calendar = InMemoryHolidayCalendar::default();
+/// let schedule = DerivedSchedule::new(
+///     &deliveries,
+///     CutoffOffset::BusinessDays {
+///         days: 2,
+///         time: NaiveTime::from_hms(14, 0, 0),
+///         calendar: &calendar,
+///     },
+/// );
+/// // an order on Thursday morning misses Friday's cutoff on Wednesday afternoon
+/// let (cutoff, delivery) = schedule
+///     .delivery_for_order(Utc.ymd(2019, 4, 3).and_hms(23, 0, 0))
+///     .unwrap();
+/// assert_eq!("2019-04-05T14:00:00+11:00", cutoff.to_rfc3339());
+/// assert_eq!("2019-04-09T06:00:00+10:00", delivery.to_rfc3339());
+/// ```
+pub struct DerivedSchedule<'r, 'a> {
+    delivery: &'r RRule<'a>,
+    offset: CutoffOffset<'r>,
+}
+
+impl<'r, 'a> DerivedSchedule<'r, 'a> {
+    pub fn new(delivery: &'r RRule<'a>, offset: CutoffOffset<'r>) -> DerivedSchedule<'r, 'a> {
+        DerivedSchedule { delivery, offset }
+    }
+
+    /// Iterates the `(cutoff, delivery)` pairs of the deliveries that follow `start_date`,
+    /// with the cutoffs in the delivery's timezone. COUNT and UNTIL bound the deliveries and
+    /// the other rule of a `PreviousOccurrence` cutoff, deliveries without a cutoff are left
+    /// out and the pairs end with the other rule's occurrences.
+    pub fn iter_from(
+        &self,
+        start_date: DateTime<Tz>,
+    ) -> impl Iterator<Item = (DateTime<Tz>, DateTime<Tz>)> + '_ {
+        let cutoffs = match self.offset {
+            CutoffOffset::PreviousOccurrence(rrule) => {
+                // without a DTSTART the other rule starts far enough back to have an
+                // occurrence before the first delivery, a year covers its BYMONTH and
+                // BYMONTHDAY skipping months
+                let lookback = start_date.with_timezone(&rrule.timezone())
+                    - rrule.interval_length().max(Duration::days(366));
+                Some(
+                    rrule
+                        .bounded_iter_dates_from(rrule.start_date(lookback))
+                        .peekable(),
+                )
+            }
+            _ => None,
+        };
+        CutoffPairs {
+            schedule: self,
+            deliveries: self.delivery.bounded_iter_dates_from(start_date),
+            cutoffs,
+            previous_cutoff: None,
+        }
+    }
+
+    /// The `(cutoff, delivery)` pairs of the deliveries at or after `after` and strictly
+    /// before `before`, like `RRule::get_all_iter_dates_between` the count only includes
+    /// the deliveries inside the window. Without DTSTART the deliveries start from `after`,
+    /// or from the delivery rule's clock when there is no `after` either.
+    pub fn get_all_cutoffs_between(
+        &self,
+        count_from_args: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Vec<(DateTime<Tz>, DateTime<Tz>)> {
+        let now = after.unwrap_or_else(|| self.delivery.clock.now());
+        let start_date = self
+            .delivery
+            .start_date(now.with_timezone(&self.delivery.timezone()));
+        let count = self.delivery.count(count_from_args);
+
+        let mut pairs = Vec::new();
+        for (cutoff, delivery) in self.iter_from(start_date) {
+            let instant = delivery.with_timezone(&Utc);
+            if pairs.len() >= count || before.is_some_and(|before| instant >= before) {
+                break;
+            }
+            if after.is_none_or(|after| instant >= after) {
+                pairs.push((cutoff, delivery));
+            }
+        }
+        pairs
+    }
+
+    /// The first delivery an order placed at `placed_at` makes, the one with the earliest
+    /// cutoff at or after it, as a `(cutoff, delivery)` pair. `None` when the deliveries
+    /// or cutoffs end before one is found.
+    pub fn delivery_for_order(
+        &self,
+        placed_at: DateTime<Utc>,
+    ) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        let start_date = self
+            .delivery
+            .start_date(placed_at.with_timezone(&self.delivery.timezone()));
+        // a delivery never has a cutoff after a later delivery's, so the first match wins
+        self.iter_from(start_date)
+            .find(|(cutoff, _)| cutoff.with_timezone(&Utc) >= placed_at)
+    }
+
+    /// The cutoff `days` business days before the day of `delivery`
+    fn business_day_cutoff(
+        &self,
+        delivery: DateTime<Tz>,
+        days: u32,
+        time: NaiveTime,
+        calendar: &dyn HolidayCalendar,
+    ) -> Option<DateTime<Tz>> {
+        let mut day: NaiveDate = self.delivery.local_date(&delivery).date();
+        let mut remaining = days;
+        let mut searched = 0;
+        while remaining > 0 {
+            day = day.pred();
+            searched += 1;
+            if searched > MAX_SHIFT_DAYS * i64::from(days) {
+                return None;
+            }
+            if calendar.is_business_day(day) {
+                remaining -= 1;
+            }
+        }
+        // a cutoff always exists, so one falling in a gap is moved the RFC way even when
+        // the rule skips occurrences in gaps
+        let policy = DstPolicy {
+            gap: GapPolicy::Rfc,
+            ..self.delivery.dst_policy
+        };
+        resolve_local(&self.delivery.zone(), &day.and_time(time), policy)
+            .map(|cutoff| cutoff.with_timezone(&delivery.timezone()))
+    }
+}
+
+struct CutoffPairs<'s, 'r, 'a> {
+    schedule: &'s DerivedSchedule<'r, 'a>,
+    deliveries: Box<dyn Iterator<Item = DateTime<Tz>> + 's>,
+    cutoffs: Option<Peekable<Box<dyn Iterator<Item = DateTime<Tz>> + 's>>>,
+    previous_cutoff: Option<DateTime<Tz>>,
+}
+
+impl<'s, 'r, 'a> CutoffPairs<'s, 'r, 'a> {
+    /// The last occurrence of the other rule before `delivery`, `None` when there is none
+    /// or the other rule has no occurrences after it
+    fn previous_occurrence(&mut self, delivery: DateTime<Tz>) -> Option<Option<DateTime<Tz>>> {
+        let cutoffs = self.cutoffs.as_mut()?;
+        loop {
+            match cutoffs.peek() {
+                Some(cutoff) if *cutoff < delivery => self.previous_cutoff = cutoffs.next(),
+                Some(_) => return Some(self.previous_cutoff),
+                None => return None,
+            }
+        }
+    }
+}
+
+impl<'s, 'r, 'a> Iterator for CutoffPairs<'s, 'r, 'a> {
+    type Item = (DateTime<Tz>, DateTime<Tz>);
+
+    fn next(&mut self) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        // deliveries without a cutoff are dropped like shifted occurrences without a business day
+        for _ in 0..MAX_DROPPED_OCCURRENCES {
+            let delivery = self.deliveries.next()?;
+            let timezone = delivery.timezone();
+            let cutoff = match self.schedule.offset {
+                CutoffOffset::Duration(duration) => {
+                    Some((delivery - duration).with_timezone(&timezone))
+                }
+                CutoffOffset::BusinessDays {
+                    days,
+                    time,
+                    calendar,
+                } => self
+                    .schedule
+                    .business_day_cutoff(delivery, days, time, calendar),
+                CutoffOffset::PreviousOccurrence(_) => self
+                    .previous_occurrence(delivery)?
+                    .map(|cutoff| cutoff.with_timezone(&timezone)),
+            };
+            if let Some(cutoff) = cutoff {
+                return Some((cutoff, delivery));
+            }
+        }
+        None
+    }
+}
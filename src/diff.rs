@@ -0,0 +1,199 @@
+use crate::{parse_rrule, RRule};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// The occurrences `diff` compares, the ones at or after `after` and strictly before
+/// `before`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffWindow {
+    pub after: DateTime<Utc>,
+    pub before: DateTime<Utc>,
+    /// How far apart a removed and an added occurrence can be to be reported as one moved
+    /// occurrence instead, one day by default
+    pub max_move: Duration,
+}
+
+impl DiffWindow {
+    pub fn new(after: DateTime<Utc>, before: DateTime<Utc>) -> DiffWindow {
+        DiffWindow {
+            after,
+            before,
+            max_move: Duration::days(1),
+        }
+    }
+}
+
+/// A rule part that differs between the two rules, named and valued like in `RRule::to_json`.
+/// `old` is `None` for a part only the new rule has and `new` for one only the old rule has.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartChange {
+    pub part: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// An occurrence of the old rule that the new rule has at another time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovedOccurrence {
+    pub from: DateTime<Tz>,
+    pub to: DateTime<Tz>,
+}
+
+/// What changed between two rules, returned by `diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleDiff {
+    /// The parts that differ, by part name
+    pub parts: Vec<PartChange>,
+    /// Occurrences only the new rule has
+    pub added: Vec<DateTime<Tz>>,
+    /// Occurrences only the old rule has
+    pub removed: Vec<DateTime<Tz>>,
+    /// Occurrences of the old rule the new rule has at another time, by the old time
+    pub moved: Vec<MovedOccurrence>,
+}
+
+impl ScheduleDiff {
+    /// Whether the occurrences in the window are the same, even if the rules are written
+    /// differently
+    pub fn occurrences_unchanged(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Compares two rules, part by part and by the occurrences they have in `window`.
+/// Occurrences are compared as instants, so changing the TZID alone doesn't change them.
+/// A removed occurrence and an added one at most `window.max_move` apart are paired up in
+/// order as a moved occurrence. An occurrence moved into or out of the window is only an
+/// added or removed one, as the other side of the move isn't in it.
+///
+/// Example:
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use sundial::{convert_to_rrule, diff, DiffWindow};
+/// let old = convert_to_rrule("FREQ=WEEKLY;BYDAY=MO,TH;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let new = convert_to_rrule("FREQ=WEEKLY;BYDAY=TU,TH;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let window = DiffWindow::new(
+///     Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+///     Utc.ymd(2019, 4, 21).and_hms(0, 0, 0),
+/// );
+/// let changes = diff(&old, &new, window);
+/// assert_eq!("byDay", changes.parts[0].part);
+/// assert_eq!("2019-04-15T06:00:00+10:00", changes.moved[0].from.to_rfc3339());
+/// assert_eq!("2019-04-16T06:00:00+10:00", changes.moved[0].to.to_rfc3339());
+/// assert!(changes.added.is_empty() && changes.removed.is_empty());
+/// ```
+pub fn diff(old: &RRule, new: &RRule, window: DiffWindow) -> ScheduleDiff {
+    let old_dates = window_dates(old, &window);
+    let new_dates = window_dates(new, &window);
+
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut old_iter, mut new_iter) = (
+        old_dates.into_iter().peekable(),
+        new_dates.into_iter().peekable(),
+    );
+    loop {
+        let ordering = match (old_iter.peek(), new_iter.peek()) {
+            (Some(old_date), Some(new_date)) => old_date.cmp(new_date),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+        match ordering {
+            Ordering::Less => removed.extend(old_iter.next()),
+            Ordering::Greater => added.extend(new_iter.next()),
+            Ordering::Equal => {
+                old_iter.next();
+                new_iter.next();
+            }
+        }
+    }
+
+    let (removed, added, moved) = pair_moves(removed, added, window.max_move);
+    ScheduleDiff {
+        parts: diff_parts(old, new),
+        added,
+        removed,
+        moved,
+    }
+}
+
+/// The occurrences of the rule in the window, up to its COUNT and UNTIL
+fn window_dates(rrule: &RRule, window: &DiffWindow) -> Vec<DateTime<Tz>> {
+    rrule
+        .iter_dates_after(Some(window.after))
+        .take_while(|date| date.with_timezone(&Utc) < window.before)
+        .collect()
+}
+
+/// Pairs removed and added occurrences at most `max_move` apart, both lists are sorted so
+/// the earliest unpaired ones are tried first
+fn pair_moves(
+    removed: Vec<DateTime<Tz>>,
+    added: Vec<DateTime<Tz>>,
+    max_move: Duration,
+) -> (Vec<DateTime<Tz>>, Vec<DateTime<Tz>>, Vec<MovedOccurrence>) {
+    let (mut unpaired_removed, mut unpaired_added, mut moved) =
+        (Vec::new(), Vec::new(), Vec::new());
+    let (mut removed, mut added) = (removed.into_iter().peekable(), added.into_iter().peekable());
+    loop {
+        match (removed.peek(), added.peek()) {
+            (Some(from), Some(to))
+                if (*to - *from).num_seconds().abs() <= max_move.num_seconds() =>
+            {
+                moved.push(MovedOccurrence {
+                    from: removed.next().unwrap(),
+                    to: added.next().unwrap(),
+                });
+            }
+            (Some(from), Some(to)) if from < to => unpaired_removed.extend(removed.next()),
+            (Some(_), Some(_)) => unpaired_added.extend(added.next()),
+            (Some(_), None) => unpaired_removed.extend(removed.next()),
+            (None, Some(_)) => unpaired_added.extend(added.next()),
+            (None, None) => break,
+        }
+    }
+    (unpaired_removed, unpaired_added, moved)
+}
+
+/// The parts that differ between the normalized rules, so parts written differently with
+/// the same meaning, e.g. BYHOUR=06 and BYHOUR=6, aren't reported
+fn diff_parts(old: &RRule, new: &RRule) -> Vec<PartChange> {
+    let old_parts = normalized_parts(old);
+    let mut new_parts = normalized_parts(new);
+
+    let mut changes = Vec::new();
+    for (part, old_value) in old_parts.into_iter() {
+        match new_parts.remove(&part) {
+            Some(new_value) if new_value == old_value => {}
+            new_value => changes.push(PartChange {
+                part,
+                old: Some(old_value),
+                new: new_value,
+            }),
+        }
+    }
+    for (part, new_value) in new_parts.into_iter() {
+        changes.push(PartChange {
+            part,
+            old: None,
+            new: Some(new_value),
+        });
+    }
+    changes.sort_by(|a, b| a.part.cmp(&b.part));
+    changes
+}
+
+/// The parts of the rule as `to_json` names them, after normalizing the rule
+fn normalized_parts(rrule: &RRule) -> Map<String, Value> {
+    let normalized = rrule.to_rrule_string();
+    let parts = match parse_rrule(&normalized) {
+        Ok(normalized) => serde_json::to_value(&normalized),
+        Err(_) => serde_json::to_value(rrule),
+    };
+    match parts {
+        Ok(Value::Object(parts)) => parts,
+        _ => Map::new(),
+    }
+}
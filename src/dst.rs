@@ -0,0 +1,95 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Timelike};
+
+/// What to do with an occurrence whose local time does not exist because the clocks
+/// were moved forward, e.g. 02:30 on the day daylight saving starts in Melbourne.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GapPolicy {
+    /// Interpret the local time using the UTC offset in effect before the gap, as
+    /// RFC 5545 does for DATE-TIME values, so 02:30 becomes 03:30.
+    Rfc,
+    /// Move the occurrence to the first valid local time after the gap, so 02:30 becomes 03:00.
+    ShiftForward,
+    /// Drop the occurrence altogether.
+    Skip,
+}
+
+/// Which instant to use for an occurrence whose local time happens twice because the
+/// clocks were moved back, e.g. 01:30 on the day daylight saving ends in New York.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverlapPolicy {
+    /// The first of the two instants, i.e. the one still in daylight saving time.
+    Earliest,
+    /// The second of the two instants.
+    Latest,
+}
+
+/// How local times that fall in a daylight saving transition are turned into instants.
+///
+/// Example:
+/// ```
+/// use sundial::{convert_to_rrule, DstPolicy, GapPolicy, OverlapPolicy};
+/// let rrule = convert_to_rrule("FREQ=DAILY;BYHOUR=2;BYMINUTE=30;TZID=Australia/Melbourne")
+///     .unwrap()
+///     .with_dst_policy(DstPolicy {
+///         gap: GapPolicy::Skip,
+///         overlap: OverlapPolicy::Latest,
+///     });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DstPolicy {
+    pub gap: GapPolicy,
+    pub overlap: OverlapPolicy,
+}
+
+impl Default for DstPolicy {
+    fn default() -> DstPolicy {
+        DstPolicy {
+            gap: GapPolicy::Rfc,
+            overlap: OverlapPolicy::Earliest,
+        }
+    }
+}
+
+/// Binds a local wall clock time to `timezone` following `policy`.
+/// Returns `None` when the local time falls in a gap and the policy says to skip it.
+pub(crate) fn resolve_local<T: TimeZone>(
+    timezone: &T,
+    local: &NaiveDateTime,
+    policy: DstPolicy,
+) -> Option<DateTime<T>> {
+    match timezone.from_local_datetime(local) {
+        LocalResult::Single(date) => Some(date),
+        LocalResult::Ambiguous(earliest, latest) => match policy.overlap {
+            OverlapPolicy::Earliest => Some(earliest.min(latest)),
+            OverlapPolicy::Latest => Some(earliest.max(latest)),
+        },
+        LocalResult::None => match policy.gap {
+            GapPolicy::Skip => None,
+            GapPolicy::Rfc => {
+                let offset_before = offset_before_gap(timezone, local);
+                Some(timezone.from_utc_datetime(&(*local - offset_before)))
+            }
+            GapPolicy::ShiftForward => {
+                // walk forward a minute at a time until we are out of the gap
+                let mut shifted = local.with_second(0).unwrap().with_nanosecond(0).unwrap();
+                loop {
+                    shifted += Duration::minutes(1);
+                    if let Some(date) = timezone.from_local_datetime(&shifted).earliest() {
+                        return Some(date);
+                    }
+                }
+            }
+        },
+    }
+}
+
+/// Finds the UTC offset that was in effect right before the gap containing `local`
+fn offset_before_gap<T: TimeZone>(timezone: &T, local: &NaiveDateTime) -> Duration {
+    let mut before = *local;
+    loop {
+        before -= Duration::minutes(30);
+        if let Some(date) = timezone.from_local_datetime(&before).latest() {
+            return Duration::seconds(i64::from(date.offset().fix().local_minus_utc()));
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use crate::dst::resolve_local;
+use crate::{DstPolicy, GapPolicy, RRule};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Returned when the DURATION or DTEND of an event is invalid, the message says why
+#[derive(Debug, Clone)]
+pub struct EventError {
+    message: String,
+}
+
+impl EventError {
+    fn new<S: Into<String>>(message: S) -> EventError {
+        EventError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "event error: {}", self.message)
+    }
+}
+
+impl Error for EventError {
+    fn description(&self) -> &str {
+        "event error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// How long each occurrence of an event lasts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventLength {
+    /// A DURATION such as `P1DT2H`. Like in RFC 5545 the days are calendar days, added to
+    /// the local date of the start so they keep its time of day across a daylight saving
+    /// transition, and the rest is exact time added after them.
+    Duration { days: i64, time: Duration },
+    /// The wall clock time from DTSTART to DTEND, so a 09:00 to 11:00 window ends at 11:00
+    /// local time on every day, including the days the clocks change.
+    WallClock(Duration),
+}
+
+impl EventLength {
+    /// Parses an RFC 5545 DURATION such as `PT2H`, `P1DT12H` or `P2W`, negative durations
+    /// aren't supported
+    pub fn parse_duration(duration: &str) -> Result<EventLength, EventError> {
+        let invalid = || {
+            EventError::new(format!(
+                "invalid duration {:?}, expected one such as PT2H, P1DT12H or P2W",
+                duration
+            ))
+        };
+        let rest = duration.trim().trim_start_matches('+');
+        if rest.starts_with('-') {
+            return Err(EventError::new(format!(
+                "the duration {} is negative, an occurrence can't end before it starts",
+                duration
+            )));
+        }
+        let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+        let (date, time) = match rest.split_once('T') {
+            Some((date, time)) if !time.is_empty() => (date, Some(time)),
+            Some(_) => return Err(invalid()),
+            None => (rest, None),
+        };
+        if date.is_empty() && time.is_none() {
+            return Err(invalid());
+        }
+
+        let mut days = 0;
+        for (value, unit) in duration_values(date).ok_or_else(invalid)? {
+            days += match unit {
+                'W' => value * 7,
+                'D' => value,
+                _ => return Err(invalid()),
+            };
+        }
+        let mut exact = Duration::zero();
+        for (value, unit) in duration_values(time.unwrap_or("")).ok_or_else(invalid)? {
+            exact = exact
+                + match unit {
+                    'H' => Duration::hours(value),
+                    'M' => Duration::minutes(value),
+                    'S' => Duration::seconds(value),
+                    _ => return Err(invalid()),
+                };
+        }
+        Ok(EventLength::Duration { days, time: exact })
+    }
+
+    /// The length from the DTSTART of `rrule` to `dtend`, given in the rrule date format
+    /// like DTSTART (`20190416T010000`, UTC with or without a trailing `Z`)
+    pub fn from_dtend(rrule: &RRule, dtend: &str) -> Result<EventLength, EventError> {
+        let dtstart = rrule
+            .dtstart_instant()
+            .ok_or_else(|| EventError::new("DTEND needs a rule with a DTSTART"))?;
+        let dtend = NaiveDateTime::parse_from_str(dtend.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+            .map_err(|_| EventError::new(format!("invalid DTEND {:?}", dtend)))?;
+        // DTEND is UTC, the length is the one between the wall clock times of both
+        let timezone = rrule.zone();
+        let dtstart = dtstart.with_timezone(&timezone).naive_local();
+        let dtend = DateTime::<Utc>::from_utc(dtend, Utc)
+            .with_timezone(&timezone)
+            .naive_local();
+        let length = dtend - dtstart;
+        if length < Duration::zero() {
+            return Err(EventError::new("DTEND is before DTSTART"));
+        }
+        Ok(EventLength::WallClock(length))
+    }
+
+    /// The most an occurrence can last, a day longer than the nominal length to allow for
+    /// daylight saving transitions
+    fn max_length(&self) -> Duration {
+        match *self {
+            EventLength::Duration { days, time } => Duration::days(days + 1) + time,
+            EventLength::WallClock(length) => length + Duration::days(1),
+        }
+    }
+}
+
+/// Formats an exact duration as an iCalendar DURATION, e.g. `PT1H30M` or `P1DT2H`, with
+/// whole days of 24 hours. Negative durations and fractions of a second aren't supported.
+pub fn format_ics_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    let (days, hours, minutes, seconds) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60,
+    );
+    let mut formatted = String::from("P");
+    if days > 0 {
+        formatted.push_str(&format!("{}D", days));
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+        formatted.push('T');
+        if hours > 0 {
+            formatted.push_str(&format!("{}H", hours));
+        }
+        if minutes > 0 {
+            formatted.push_str(&format!("{}M", minutes));
+        }
+        if seconds > 0 || (hours == 0 && minutes == 0) {
+            formatted.push_str(&format!("{}S", seconds));
+        }
+    }
+    formatted
+}
+
+/// Formats an instant as an iCalendar UTC DATE-TIME, e.g. `20190416T010000Z`
+pub fn format_ics_date(date: &DateTime<Utc>) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Wraps calendar components, each a name such as `VEVENT` with its content lines, in a
+/// VCALENDAR. Lines are CRLF terminated as RFC 5545 requires.
+///
+/// Example:
+/// ```
+/// use sundial::format_ics_calendar;
+/// let ics = format_ics_calendar(vec![("VEVENT", vec![String::from("UID:1@sundial")])]);
+/// assert!(ics.contains("BEGIN:VEVENT\r\nUID:1@sundial\r\nEND:VEVENT\r\n"));
+/// ```
+pub fn format_ics_calendar(components: Vec<(&str, Vec<String>)>) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//ordermentum//sundial//EN"),
+    ];
+    for (name, content) in components {
+        lines.push(format!("BEGIN:{}", name));
+        lines.extend(content);
+        lines.push(format!("END:{}", name));
+    }
+    lines.push(String::from("END:VCALENDAR"));
+    lines
+        .iter()
+        .map(|line| format!("{}\r\n", line))
+        .collect::<String>()
+}
+
+/// Joins folded content lines, i.e. lines starting with a space or tab continue the
+/// previous line. Blank lines are dropped.
+pub(crate) fn unfold_ics_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        let line = line.trim();
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+/// `<number><unit>` pairs such as `1D` or `2H30M`
+fn duration_values(values: &str) -> Option<Vec<(i64, char)>> {
+    let mut pairs = Vec::new();
+    let mut number = String::new();
+    for c in values.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            pairs.push((number.parse().ok()?, c));
+            number.clear();
+        }
+    }
+    if number.is_empty() {
+        Some(pairs)
+    } else {
+        None
+    }
+}
+
+/// When an occurrence of an event starts and ends, the end isn't part of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OccurrenceSpan {
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+}
+
+impl OccurrenceSpan {
+    /// Whether `instant` is at or after the start and before the end
+    pub fn contains(&self, instant: DateTime<Utc>) -> bool {
+        self.start.with_timezone(&Utc) <= instant && instant < self.end.with_timezone(&Utc)
+    }
+
+    /// Whether the occurrence has any time at or after `after` and before `before`, an
+    /// occurrence without length does when it starts in it
+    pub fn overlaps(&self, after: DateTime<Utc>, before: DateTime<Utc>) -> bool {
+        let (start, end) = (self.start.with_timezone(&Utc), self.end.with_timezone(&Utc));
+        start < before && (end > after || (start == end && start >= after))
+    }
+
+    pub fn length(&self) -> Duration {
+        self.end.signed_duration_since(self.start)
+    }
+}
+
+/// A rule whose occurrences last a while, like a VEVENT with an RRULE and a DURATION or
+/// DTEND, e.g. a delivery window from 09:00 to 11:00 every Tuesday.
+///
+/// Example:
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use sundial::{convert_to_rrule, Event};
+/// let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let event = Event::with_duration(&rrule, "PT2H").unwrap();
+/// let span = event.span_at(Utc.ymd(2019, 4, 16).and_hms(0, 30, 0)).unwrap();
+/// assert_eq!("2019-04-16T09:00:00+10:00", span.start.to_rfc3339());
+/// assert_eq!("2019-04-16T11:00:00+10:00", span.end.to_rfc3339());
+/// assert!(!event.contains(Utc.ymd(2019, 4, 16).and_hms(1, 0, 0)));
+/// ```
+pub struct Event<'r, 'a> {
+    rrule: &'r RRule<'a>,
+    length: EventLength,
+}
+
+impl<'r, 'a> Event<'r, 'a> {
+    pub fn new(rrule: &'r RRule<'a>, length: EventLength) -> Event<'r, 'a> {
+        Event { rrule, length }
+    }
+
+    /// An event lasting an RFC 5545 DURATION, see `EventLength::parse_duration`
+    pub fn with_duration(
+        rrule: &'r RRule<'a>,
+        duration: &str,
+    ) -> Result<Event<'r, 'a>, EventError> {
+        Ok(Event::new(rrule, EventLength::parse_duration(duration)?))
+    }
+
+    /// An event from the DTSTART of the rule to `dtend`, see `EventLength::from_dtend`
+    pub fn with_end(rrule: &'r RRule<'a>, dtend: &str) -> Result<Event<'r, 'a>, EventError> {
+        Ok(Event::new(rrule, EventLength::from_dtend(rrule, dtend)?))
+    }
+
+    pub fn length(&self) -> EventLength {
+        self.length
+    }
+
+    /// The span of the occurrence starting at `start`. An end in a daylight saving gap is
+    /// moved the RFC way and one in an overlap follows the rule's `DstPolicy`.
+    pub fn span_of(&self, start: DateTime<Tz>) -> OccurrenceSpan {
+        let timezone = start.timezone();
+        let zone = self.rrule.zone();
+        let local_start = self.rrule.local_date(&start);
+        let policy = DstPolicy {
+            gap: GapPolicy::Rfc,
+            ..self.rrule.dst_policy
+        };
+        let local_end = |length: Duration| {
+            resolve_local(&zone, &(local_start + length), policy)
+                .map(|end| end.with_timezone(&timezone))
+                .unwrap_or(start + length)
+        };
+        let end = match self.length {
+            EventLength::Duration { days: 0, time } => start + time,
+            EventLength::Duration { days, time } => local_end(Duration::days(days)) + time,
+            EventLength::WallClock(length) => local_end(length),
+        };
+        OccurrenceSpan {
+            start,
+            // adding a duration keeps the offset of the start, so the end gets the one in
+            // effect at its own instant
+            end: end.max(start).with_timezone(&timezone),
+        }
+    }
+
+    /// Iterates the spans of the occurrences starting at or after `after`, up to the COUNT
+    /// and UNTIL of the rule, like `RRule::iter_dates_after`
+    pub fn iter_spans_after(
+        &self,
+        after: Option<DateTime<Utc>>,
+    ) -> impl Iterator<Item = OccurrenceSpan> + '_ {
+        self.rrule
+            .iter_dates_after(after)
+            .map(move |start| self.span_of(start))
+    }
+
+    /// The spans of the occurrences that have any time at or after `after` and before
+    /// `before`, including the ones that started before `after` and haven't ended yet
+    pub fn spans_between(
+        &self,
+        after: DateTime<Utc>,
+        before: DateTime<Utc>,
+    ) -> Vec<OccurrenceSpan> {
+        self.iter_spans_after(Some(after - self.length.max_length()))
+            .take_while(|span| span.start.with_timezone(&Utc) < before)
+            .filter(|span| span.overlaps(after, before))
+            .collect()
+    }
+
+    /// The span of the occurrence `instant` is inside, the earliest one when occurrences
+    /// overlap
+    pub fn span_at(&self, instant: DateTime<Utc>) -> Option<OccurrenceSpan> {
+        self.iter_spans_after(Some(instant - self.length.max_length()))
+            .take_while(|span| span.start.with_timezone(&Utc) <= instant)
+            .find(|span| span.contains(instant))
+    }
+
+    /// Whether `instant` is inside an occurrence
+    pub fn contains(&self, instant: DateTime<Utc>) -> bool {
+        self.span_at(instant).is_some()
+    }
+}
@@ -0,0 +1,292 @@
+use crate::text::NUMBERS;
+use crate::{chrono_weekday_to_rrule_byday, split_byday, DateForm, RRule};
+use chrono::{Datelike, NaiveDateTime, TimeZone, Timelike, Utc, Weekday};
+
+/// The fixed calendar fields a rule occurs on, the common ground of rules, cron expressions
+/// and systemd calendar events. Each field lists the values it matches, an empty field
+/// matches every value, an empty `weekdays` every day and an empty `tzid` is UTC.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct CalendarFields {
+    /// Empty when the rule doesn't say and it is taken as 0
+    pub seconds: Vec<u32>,
+    pub minutes: Vec<u32>,
+    pub hours: Vec<u32>,
+    pub month_days: Vec<u32>,
+    pub months: Vec<u32>,
+    pub weekdays: Vec<Weekday>,
+    pub tzid: String,
+}
+
+impl CalendarFields {
+    /// Builds the rule occurring on the fields, the frequency comes from the coarsest time
+    /// field matching every value
+    pub fn into_rrule(self) -> Result<RRule<'static>, String> {
+        let frequency = if self.minutes.is_empty() {
+            "MINUTELY"
+        } else if self.hours.is_empty() {
+            "HOURLY"
+        } else if !self.month_days.is_empty() {
+            "MONTHLY"
+        } else if !self.weekdays.is_empty() && self.months.is_empty() {
+            // weekly rules don't iterate BYMONTH, daily ones do
+            "WEEKLY"
+        } else {
+            "DAILY"
+        };
+        if !self.month_days.is_empty() && !self.weekdays.is_empty() {
+            return Err(String::from(
+                "sundial can't restrict both the day of month and the day of week",
+            ));
+        }
+        if !self.month_days.is_empty() && (self.minutes.is_empty() || self.hours.is_empty()) {
+            return Err(String::from(
+                "sundial can't restrict the day of month of an hourly or minutely rule",
+            ));
+        }
+        let mut rrule = RRule::new();
+        rrule.frequency = frequency.to_string();
+        let mut weekdays = self.weekdays;
+        weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+        weekdays.dedup();
+        rrule.by_day = weekdays
+            .into_iter()
+            .map(chrono_weekday_to_rrule_byday)
+            .collect();
+        rrule.by_month = numbers(&self.months);
+        rrule.by_month_day = numbers(&self.month_days);
+        rrule.by_hour = numbers(&self.hours);
+        rrule.by_minute = numbers(&self.minutes);
+        rrule.by_second = if self.seconds.is_empty() {
+            vec![number(0)]
+        } else {
+            numbers(&self.seconds)
+        };
+        rrule.tzid = self.tzid;
+        Ok(rrule)
+    }
+}
+
+/// A number from the static table, the fields are range checked before they get here
+pub(crate) fn number(value: u32) -> &'static str {
+    NUMBERS[value as usize]
+}
+
+fn numbers(values: &[u32]) -> Vec<&'static str> {
+    values.iter().map(|value| number(*value)).collect()
+}
+
+impl<'a> RRule<'a> {
+    /// The calendar fields of the rule, for formats that can only match fixed times.
+    /// `target` names the format in the errors explaining what it can't express.
+    pub(crate) fn calendar_fields(&self, target: &str) -> Result<CalendarFields, String> {
+        let interval: u32 = self.interval.parse().unwrap_or(1);
+        if self.frequency == "FORTNIGHTLY" || interval > 1 {
+            return Err(format!(
+                "the rule repeats every {} {}, {} matches fixed times and can't skip occurrences",
+                if self.frequency == "FORTNIGHTLY" {
+                    2
+                } else {
+                    interval
+                },
+                self.frequency_unit(),
+                target
+            ));
+        }
+        if !self.count.is_empty() || !self.until.is_empty() {
+            return Err(format!(
+                "{} schedules never end, the rule's COUNT or UNTIL can't be expressed",
+                target
+            ));
+        }
+        if !self.by_year_day.is_empty() {
+            return Err(format!("{} has no field for BYYEARDAY", target));
+        }
+
+        let dtstart = self.dtstart_in_timezone();
+        let mut fields = CalendarFields {
+            seconds: self.time_part(&self.by_second, dtstart.map(|d| d.second())),
+            months: part_values(&self.by_month),
+            weekdays: self.plain_weekdays(target)?,
+            ..CalendarFields::default()
+        };
+        if self.zone.is_some() {
+            return Err(format!(
+                "{} only knows IANA timezones, {:?} is only known to the rule's TimeZoneProvider",
+                target, self.tzid
+            ));
+        }
+        if !self.tzid.is_empty() {
+            fields.tzid = self.timezone().name().to_string();
+        }
+
+        match self.frequency.as_str() {
+            "MINUTELY" => {
+                fields.hours = part_values(&self.by_hour);
+            }
+            "HOURLY" => {
+                fields.minutes = self.required_time_part(
+                    &self.by_minute,
+                    "BYMINUTE",
+                    dtstart.map(|d| d.minute()),
+                )?;
+                fields.hours = part_values(&self.by_hour);
+            }
+            "YEARLY" | "MONTHLY" | "WEEKLY" | "DAILY" => {
+                fields.minutes = self.required_time_part(
+                    &self.by_minute,
+                    "BYMINUTE",
+                    dtstart.map(|d| d.minute()),
+                )?;
+                fields.hours =
+                    self.required_time_part(&self.by_hour, "BYHOUR", dtstart.map(|d| d.hour()))?;
+            }
+            "SECONDLY" => {
+                return Err(format!("{} can't express a SECONDLY rule", target));
+            }
+            frequency => return Err(format!("unsupported frequency {:?}", frequency)),
+        }
+
+        match self.frequency.as_str() {
+            "YEARLY" => {
+                // yearly rules repeat on the day of DTSTART
+                let dtstart = dtstart.ok_or_else(|| {
+                    String::from("a YEARLY rule needs a DTSTART for the day it repeats on")
+                })?;
+                fields.month_days = vec![dtstart.day()];
+                fields.months = vec![dtstart.month()];
+                fields.weekdays.clear();
+            }
+            "MONTHLY" => {
+                fields.month_days = part_values(&self.by_month_day);
+                if fields.month_days.is_empty() {
+                    fields.month_days.push(dtstart.map(|d| d.day()).ok_or_else(|| {
+                        String::from(
+                            "a MONTHLY rule needs a BYMONTHDAY or DTSTART for the day it repeats on",
+                        )
+                    })?);
+                }
+                fields.weekdays.clear();
+            }
+            "WEEKLY" => {
+                if !self.by_month.is_empty() {
+                    return Err(String::from(
+                        "sundial doesn't iterate BYMONTH in WEEKLY rules",
+                    ));
+                }
+                if fields.weekdays.is_empty() {
+                    let dtstart = dtstart.ok_or_else(|| {
+                        String::from(
+                            "a WEEKLY rule needs a BYDAY or DTSTART for the day it repeats on",
+                        )
+                    })?;
+                    fields.weekdays.push(dtstart.weekday());
+                }
+            }
+            _ => {}
+        }
+        Ok(fields)
+    }
+
+    fn frequency_unit(&self) -> &'static str {
+        match self.frequency.as_str() {
+            "YEARLY" => "years",
+            "MONTHLY" => "months",
+            "WEEKLY" | "FORTNIGHTLY" => "weeks",
+            "DAILY" => "days",
+            "HOURLY" => "hours",
+            "MINUTELY" => "minutes",
+            _ => "seconds",
+        }
+    }
+
+    /// DTSTART as a wall clock time in the rule's timezone
+    fn dtstart_in_timezone(&self) -> Option<NaiveDateTime> {
+        let dtstart = self.local_dtstart()?;
+        if self.dtstart_form == DateForm::Zoned {
+            // DTSTART;TZID= is kept as a local time, followed by the abbreviation of a
+            // chrono-tz zone
+            Some(dtstart)
+        } else {
+            Some(
+                Utc.from_utc_datetime(&dtstart)
+                    .with_timezone(&self.zone())
+                    .naive_local(),
+            )
+        }
+    }
+
+    /// The values of a time part, from the rule or else DTSTART
+    fn time_part(&self, values: &[&str], from_dtstart: Option<u32>) -> Vec<u32> {
+        match part_values(values) {
+            values if values.is_empty() => from_dtstart.into_iter().collect(),
+            values => values,
+        }
+    }
+
+    fn required_time_part(
+        &self,
+        values: &[&str],
+        name: &str,
+        from_dtstart: Option<u32>,
+    ) -> Result<Vec<u32>, String> {
+        match self.time_part(values, from_dtstart) {
+            values if values.is_empty() => Err(format!(
+                "the rule has no {} or DTSTART, so the time depends on when it is evaluated",
+                name
+            )),
+            values => Ok(values),
+        }
+    }
+
+    fn plain_weekdays(&self, target: &str) -> Result<Vec<Weekday>, String> {
+        self.by_day
+            .iter()
+            .map(|by_day| match split_byday(by_day) {
+                Some((0, weekday)) => Ok(weekday),
+                _ => Err(format!(
+                    "{} can't express an ordinal weekday such as {}",
+                    target, by_day
+                )),
+            })
+            .collect()
+    }
+}
+
+/// The values of a part, validated rules only have numbers in them
+fn part_values(values: &[&str]) -> Vec<u32> {
+    values
+        .iter()
+        .filter_map(|value| value.parse().ok())
+        .collect()
+}
+
+/// Joins the values of a field, sorted and with runs of three or more values as ranges,
+/// `*` when the field matches every value
+pub(crate) fn join_field(values: &[u32], range: &str, name: impl Fn(u32) -> String) -> String {
+    if values.is_empty() {
+        return String::from("*");
+    }
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    values.dedup();
+    join_day_ranges(&values, range, name)
+}
+
+/// Joins sorted day numbers, runs of three or more days become `start{range}end`
+pub(crate) fn join_day_ranges(days: &[u32], range: &str, name: impl Fn(u32) -> String) -> String {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for day in days {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == *day => *end = *day,
+            _ => runs.push((*day, *day)),
+        }
+    }
+    runs.iter()
+        .map(|(start, end)| match end - start {
+            0 => name(*start),
+            1 => format!("{},{}", name(*start), name(*end)),
+            _ => format!("{}{}{}", name(*start), range, name(*end)),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
@@ -0,0 +1,225 @@
+use crate::{format_ics_calendar, format_ics_date, format_ics_duration, Event, OccurrenceSpan};
+use chrono::{DateTime, Duration, Utc};
+
+/// How far past `after` `next_free_slot` looks before giving up, unless the constraints
+/// say otherwise
+const MAX_SLOT_SEARCH_DAYS: i64 = 366;
+
+/// How far past `after` the first round of the slot search looks, every round after that
+/// looks twice as far
+const FIRST_SLOT_SEARCH_DAYS: i64 = 7;
+
+/// A stretch of time from `start` to `end`, the end not included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Period {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Period {
+    pub fn length(&self) -> Duration {
+        self.end.signed_duration_since(self.start)
+    }
+}
+
+impl From<OccurrenceSpan> for Period {
+    fn from(span: OccurrenceSpan) -> Period {
+        Period {
+            start: span.start.with_timezone(&Utc),
+            end: span.end.with_timezone(&Utc),
+        }
+    }
+}
+
+/// The busy and free periods in a window, returned by `free_busy`. Busy periods are the
+/// occurrences of the busy events with the overlapping and touching ones merged, free
+/// periods are the time between them, both cut to the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreeBusy {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub busy: Vec<Period>,
+    pub free: Vec<Period>,
+}
+
+impl FreeBusy {
+    /// Builds an iCalendar VCALENDAR with a VFREEBUSY component listing the busy periods,
+    /// any time in the window not listed is free. `stamp` is its DTSTAMP.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use sundial::{convert_to_rrule, free_busy, Event};
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+    /// let busy = [Event::with_duration(&rrule, "PT2H").unwrap()];
+    /// let free_busy = free_busy(
+    ///     &busy,
+    ///     Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+    ///     Utc.ymd(2019, 4, 21).and_hms(0, 0, 0),
+    /// );
+    /// let ics = free_busy.to_ics(Utc.ymd(2019, 4, 1).and_hms(0, 0, 0));
+    /// assert!(ics.contains("FREEBUSY;FBTYPE=BUSY:20190415T230000Z/PT2H\r\n"));
+    /// ```
+    pub fn to_ics(&self, stamp: DateTime<Utc>) -> String {
+        let mut lines = vec![
+            format!(
+                "UID:{}-{}-freebusy@sundial",
+                format_ics_date(&self.start),
+                format_ics_date(&self.end)
+            ),
+            format!("DTSTAMP:{}", format_ics_date(&stamp)),
+            format!("DTSTART:{}", format_ics_date(&self.start)),
+            format!("DTEND:{}", format_ics_date(&self.end)),
+        ];
+        // a property per period keeps the lines short enough to not need folding
+        for period in self.busy.iter() {
+            lines.push(format!(
+                "FREEBUSY;FBTYPE=BUSY:{}/{}",
+                format_ics_date(&period.start),
+                format_ics_duration(period.length())
+            ));
+        }
+        format_ics_calendar(vec![("VFREEBUSY", lines)])
+    }
+}
+
+/// Limits on the slots `next_free_slot` finds
+#[derive(Clone, Copy, Default)]
+pub struct SlotConstraints<'c> {
+    /// When slots can be, e.g. 09:00 to 17:00 on weekdays as a weekly rule with a PT8H
+    /// duration. A slot has to fit in one occurrence, or in occurrences that follow each
+    /// other without a gap. Any time can have a slot when there are no working hours.
+    pub working_hours: Option<&'c Event<'c, 'c>>,
+    /// When the slot has to end by, a year after `after` by default
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// The busy and free periods at or after `after` and before `before`, with the occurrences
+/// of every one of the `busy` events as busy time
+pub fn free_busy(busy: &[Event], after: DateTime<Utc>, before: DateTime<Utc>) -> FreeBusy {
+    let busy = merged_periods(busy, after, before);
+    let mut free = Vec::new();
+    let mut cursor = after;
+    for period in busy.iter() {
+        if period.start > cursor {
+            free.push(Period {
+                start: cursor,
+                end: period.start,
+            });
+        }
+        cursor = cursor.max(period.end);
+    }
+    if cursor < before {
+        free.push(Period {
+            start: cursor,
+            end: before,
+        });
+    }
+    FreeBusy {
+        start: after,
+        end: before,
+        busy,
+        free,
+    }
+}
+
+/// The earliest period lasting `duration`, starting at or after `after`, that none of the
+/// `busy` events occur in and that fits the `constraints`. `None` when there is none before
+/// the constraints' `before`.
+///
+/// Example:
+/// ```
+/// use chrono::{Duration, TimeZone, Utc};
+/// use sundial::{convert_to_rrule, next_free_slot, Event, SlotConstraints};
+/// let deliveries = convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let busy = [Event::with_duration(&deliveries, "PT3H").unwrap()];
+/// let weekdays = convert_to_rrule("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=8;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let working_hours = Event::with_duration(&weekdays, "PT9H").unwrap();
+/// // on a Saturday, the first two free working hours are on Monday after the delivery
+/// let slot = next_free_slot(
+///     &busy,
+///     Utc.ymd(2019, 4, 13).and_hms(0, 0, 0),
+///     Duration::hours(2),
+///     SlotConstraints { working_hours: Some(&working_hours), before: None },
+/// )
+/// .unwrap();
+/// assert_eq!("2019-04-15T12:00:00+10:00", slot.start.with_timezone(&chrono_tz::Australia::Melbourne).to_rfc3339());
+/// ```
+pub fn next_free_slot(
+    busy: &[Event],
+    after: DateTime<Utc>,
+    duration: Duration,
+    constraints: SlotConstraints,
+) -> Option<Period> {
+    let limit = constraints
+        .before
+        .unwrap_or_else(|| after + Duration::days(MAX_SLOT_SEARCH_DAYS));
+    // occurrences are only computed for a window, so a slot is only taken once it ends in
+    // the window, and the window grows until one does
+    let mut horizon = Duration::days(FIRST_SLOT_SEARCH_DAYS);
+    loop {
+        let before = (after + horizon).min(limit);
+        let free = free_busy(busy, after, before).free;
+        let available = match constraints.working_hours {
+            Some(working_hours) => intersect(
+                &free,
+                &merged_periods(std::slice::from_ref(working_hours), after, before),
+            ),
+            None => free,
+        };
+        if let Some(period) = available.iter().find(|period| period.length() >= duration) {
+            return Some(Period {
+                start: period.start,
+                end: period.start + duration,
+            });
+        }
+        if before >= limit {
+            return None;
+        }
+        horizon = horizon * 2;
+    }
+}
+
+/// The occurrences of the events in the window merged into sorted periods that neither
+/// overlap nor touch, cut to the window
+fn merged_periods(events: &[Event], after: DateTime<Utc>, before: DateTime<Utc>) -> Vec<Period> {
+    let mut periods: Vec<Period> = events
+        .iter()
+        .flat_map(|event| event.spans_between(after, before))
+        .map(Period::from)
+        .filter(|period| period.end > period.start)
+        .collect();
+    periods.sort_by_key(|period| period.start);
+
+    let mut merged: Vec<Period> = Vec::new();
+    for period in periods {
+        let period = Period {
+            start: period.start.max(after),
+            end: period.end.min(before),
+        };
+        match merged.last_mut() {
+            Some(last) if period.start <= last.end => last.end = last.end.max(period.end),
+            _ => merged.push(period),
+        }
+    }
+    merged
+}
+
+/// The time that is in both sorted lists of periods
+fn intersect(first: &[Period], second: &[Period]) -> Vec<Period> {
+    let mut periods = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < first.len() && j < second.len() {
+        let start = first[i].start.max(second[j].start);
+        let end = first[i].end.min(second[j].end);
+        if start < end {
+            periods.push(Period { start, end });
+        }
+        if first[i].end < second[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    periods
+}
@@ -0,0 +1,486 @@
+use crate::dst::resolve_local;
+use crate::event::unfold_ics_lines;
+use crate::RRule;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How far from an occurrence a business day is looked for before giving up on it
+pub(crate) const MAX_SHIFT_DAYS: i64 = 366;
+
+/// How many occurrences in a row can be dropped before the iteration gives up, so a
+/// calendar without business days doesn't iterate forever
+pub(crate) const MAX_DROPPED_OCCURRENCES: usize = 1000;
+
+/// The days a business is closed on, public holidays of a region and weekends.
+///
+/// Example:
+/// ```
+/// use chrono::NaiveDate;
+/// use sundial::{HolidayCalendar, InMemoryHolidayCalendar};
+/// let calendar = InMemoryHolidayCalendar::new(vec![NaiveDate::from_ymd(2019, 12, 25)]);
+/// assert!(calendar.is_holiday(NaiveDate::from_ymd(2019, 12, 25)));
+/// assert!(!calendar.is_business_day(NaiveDate::from_ymd(2019, 12, 28)));
+/// assert!(calendar.is_business_day(NaiveDate::from_ymd(2019, 12, 27)));
+/// ```
+pub trait HolidayCalendar {
+    fn is_holiday(&self, date: NaiveDate) -> bool;
+
+    /// Saturdays and Sundays unless the calendar says otherwise
+    fn is_weekend(&self, date: NaiveDate) -> bool {
+        date.weekday() == Weekday::Sat || date.weekday() == Weekday::Sun
+    }
+
+    fn is_business_day(&self, date: NaiveDate) -> bool {
+        !self.is_weekend(date) && !self.is_holiday(date)
+    }
+
+    /// A value that changes whenever the holidays do, so caches of shifted occurrences
+    /// such as `MaterializedSchedule` know to drop them. Calendars that never change can
+    /// keep the default.
+    fn version(&self) -> u64 {
+        0
+    }
+}
+
+/// A holiday calendar holding its dates in memory, built from a list of dates or parsed
+/// from an iCalendar or CSV export.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InMemoryHolidayCalendar {
+    dates: BTreeSet<NaiveDate>,
+}
+
+impl InMemoryHolidayCalendar {
+    pub fn new<I: IntoIterator<Item = NaiveDate>>(dates: I) -> InMemoryHolidayCalendar {
+        InMemoryHolidayCalendar {
+            dates: dates.into_iter().collect(),
+        }
+    }
+
+    pub fn add(&mut self, date: NaiveDate) {
+        self.dates.insert(date);
+    }
+
+    /// The holidays in date order
+    pub fn dates(&self) -> impl Iterator<Item = &NaiveDate> {
+        self.dates.iter()
+    }
+
+    /// Reads the days of the VEVENTs in an iCalendar file, such as the public holiday
+    /// calendars governments publish. An all day event spanning several days, with a
+    /// DTEND after the day after DTSTART, adds all of its days. Recurring events aren't
+    /// supported as holiday calendars list every date.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use sundial::{HolidayCalendar, InMemoryHolidayCalendar};
+    /// let calendar = InMemoryHolidayCalendar::parse_ics(
+    ///     "BEGIN:VCALENDAR\n\
+    ///      BEGIN:VEVENT\n\
+    ///      DTSTART;VALUE=DATE:20191225\n\
+    ///      DTEND;VALUE=DATE:20191227\n\
+    ///      SUMMARY:Christmas and Boxing Day\n\
+    ///      END:VEVENT\n\
+    ///      END:VCALENDAR",
+    /// )
+    /// .unwrap();
+    /// assert!(calendar.is_holiday(NaiveDate::from_ymd(2019, 12, 26)));
+    /// assert!(!calendar.is_holiday(NaiveDate::from_ymd(2019, 12, 27)));
+    /// ```
+    pub fn parse_ics(ics: &str) -> Result<InMemoryHolidayCalendar, HolidayParseError> {
+        let mut calendar = InMemoryHolidayCalendar::default();
+        let mut event: Option<(Option<NaiveDate>, Option<NaiveDate>)> = None;
+        for line in unfold_ics_lines(ics) {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name, value.trim()),
+                None => continue,
+            };
+            // parameters such as VALUE=DATE or TZID=... don't change the day
+            let name = name.split(';').next().unwrap_or(name).to_uppercase();
+            match (name.as_str(), event.as_mut()) {
+                ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                    event = Some((None, None))
+                }
+                ("DTSTART", Some((start, _))) => *start = Some(parse_ics_date(value)?),
+                ("DTEND", Some((_, end))) => *end = Some(parse_ics_date(value)?),
+                ("RRULE", Some(_)) => {
+                    return Err(HolidayParseError::new(
+                        "recurring holiday events aren't supported, the calendar has to list every date",
+                    ))
+                }
+                ("END", Some((start, end))) if value.eq_ignore_ascii_case("VEVENT") => {
+                    let start = start.ok_or_else(|| {
+                        HolidayParseError::new("found a VEVENT without a DTSTART")
+                    })?;
+                    calendar.add(start);
+                    let mut day = start.succ();
+                    while end.is_some_and(|end| day < end) {
+                        calendar.add(day);
+                        day = day.succ();
+                    }
+                    event = None;
+                }
+                _ => {}
+            }
+        }
+        Ok(calendar)
+    }
+
+    /// Reads a CSV file with a `date,region,name` row per holiday, where the region and
+    /// name columns are optional and a header row is skipped. When `region` is given only
+    /// the rows for that region, or without one, are kept, so a single file can hold
+    /// the holidays of several regions. Dates are written as `2019-12-25` or `20191225`.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use sundial::{HolidayCalendar, InMemoryHolidayCalendar};
+    /// let csv = "date,region,name\n\
+    ///            2019-11-05,au-vic,Melbourne Cup\n\
+    ///            2019-12-25,,Christmas Day\n";
+    /// let calendar = InMemoryHolidayCalendar::parse_csv(csv, Some("au-nsw")).unwrap();
+    /// assert!(!calendar.is_holiday(NaiveDate::from_ymd(2019, 11, 5)));
+    /// assert!(calendar.is_holiday(NaiveDate::from_ymd(2019, 12, 25)));
+    /// ```
+    pub fn parse_csv(
+        csv: &str,
+        region: Option<&str>,
+    ) -> Result<InMemoryHolidayCalendar, HolidayParseError> {
+        let mut calendar = InMemoryHolidayCalendar::default();
+        for (index, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut columns = line
+                .split(',')
+                .map(|column| column.trim().trim_matches('"'));
+            let date = columns.next().unwrap_or("");
+            let date = match parse_date(date) {
+                Some(date) => date,
+                None if index == 0 => continue,
+                None => {
+                    return Err(HolidayParseError::new(format!(
+                        "invalid date {:?} on line {}",
+                        date,
+                        index + 1
+                    )))
+                }
+            };
+            let row_region = columns.next().unwrap_or("");
+            if region.is_none_or(|region| {
+                row_region.is_empty() || row_region.eq_ignore_ascii_case(region)
+            }) {
+                calendar.add(date);
+            }
+        }
+        Ok(calendar)
+    }
+}
+
+impl HolidayCalendar for InMemoryHolidayCalendar {
+    fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.dates.contains(&date)
+    }
+
+    /// A hash of the dates, so calendars with the same holidays have the same version
+    fn version(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.dates.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A holiday calendar read from an iCalendar (`.ics`) or CSV (`.csv`) file, see
+/// `InMemoryHolidayCalendar::parse_ics` and `InMemoryHolidayCalendar::parse_csv` for
+/// the formats. Files with another extension are read as iCalendar when they start with
+/// `BEGIN:VCALENDAR` and as CSV otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHolidayCalendar {
+    path: PathBuf,
+    region: Option<String>,
+    calendar: InMemoryHolidayCalendar,
+}
+
+impl FileHolidayCalendar {
+    /// Reads the holidays in the file, keeping only the ones of `region` in CSV files.
+    /// iCalendar files have no regions, so giving a `region` with one is an error.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        region: Option<&str>,
+    ) -> Result<FileHolidayCalendar, HolidayParseError> {
+        let mut calendar = FileHolidayCalendar {
+            path: path.as_ref().to_path_buf(),
+            region: region.map(String::from),
+            calendar: InMemoryHolidayCalendar::default(),
+        };
+        calendar.reload()?;
+        Ok(calendar)
+    }
+
+    /// Reads the file again, e.g. after next year's holidays were published
+    pub fn reload(&mut self) -> Result<(), HolidayParseError> {
+        let contents = fs::read_to_string(&self.path).map_err(|err| {
+            HolidayParseError::new(format!("could not read {}: {}", self.path.display(), err))
+        })?;
+        let extension = self
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let is_ics = extension == "ics"
+            || (extension != "csv" && contents.trim_start().starts_with("BEGIN:VCALENDAR"));
+        self.calendar = if is_ics {
+            if let Some(region) = &self.region {
+                return Err(HolidayParseError::new(format!(
+                    "{} is an iCalendar file, which has no regions to pick {} from",
+                    self.path.display(),
+                    region
+                )));
+            }
+            InMemoryHolidayCalendar::parse_ics(&contents)?
+        } else {
+            InMemoryHolidayCalendar::parse_csv(&contents, self.region.as_deref())?
+        };
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn calendar(&self) -> &InMemoryHolidayCalendar {
+        &self.calendar
+    }
+}
+
+impl HolidayCalendar for FileHolidayCalendar {
+    fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.calendar.is_holiday(date)
+    }
+
+    fn version(&self) -> u64 {
+        self.calendar.version()
+    }
+}
+
+/// Where an occurrence landing on a holiday or weekend goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShiftPolicy {
+    /// Drop the occurrence.
+    Skip,
+    /// Move it to the first business day after it.
+    NextBusinessDay,
+    /// Move it to the last business day before it.
+    PreviousBusinessDay,
+    /// Move it to the closest business day, the next one when both are as close.
+    Nearest,
+}
+
+impl FromStr for ShiftPolicy {
+    type Err = String;
+
+    /// Parses `skip`, `next`, `previous` or `nearest`
+    fn from_str(policy: &str) -> Result<ShiftPolicy, String> {
+        match policy.to_lowercase().as_str() {
+            "skip" => Ok(ShiftPolicy::Skip),
+            "next" => Ok(ShiftPolicy::NextBusinessDay),
+            "previous" | "prev" => Ok(ShiftPolicy::PreviousBusinessDay),
+            "nearest" => Ok(ShiftPolicy::Nearest),
+            _ => Err(format!(
+                "unknown shift policy {:?}, expected skip, next, previous or nearest",
+                policy
+            )),
+        }
+    }
+}
+
+/// A rule whose occurrences are moved off holidays and weekends, see `RRule::shifted`
+pub struct ShiftedRRule<'r, 'a> {
+    rrule: &'r RRule<'a>,
+    calendar: &'r dyn HolidayCalendar,
+    policy: ShiftPolicy,
+}
+
+impl<'a> RRule<'a> {
+    /// Moves the occurrences landing on a holiday or weekend of `calendar` as `policy`
+    /// says. A moved occurrence keeps its local time of day.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use sundial::{convert_to_rrule, InMemoryHolidayCalendar, ShiftPolicy};
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=WE;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20191218T000000").unwrap();
+    /// let calendar = InMemoryHolidayCalendar::new(vec![NaiveDate::from_ymd(2019, 12, 25)]);
+    /// let dates = rrule
+    ///     .shifted(&calendar, ShiftPolicy::NextBusinessDay)
+    ///     .get_all_iter_dates_between("3", "", None, None);
+    /// assert_eq!("2019-12-18T09:00:00+00:00", dates[0].to_rfc3339());
+    /// assert_eq!("2019-12-26T09:00:00+00:00", dates[1].to_rfc3339());
+    /// assert_eq!("2020-01-01T09:00:00+00:00", dates[2].to_rfc3339());
+    /// ```
+    pub fn shifted<'r>(
+        &'r self,
+        calendar: &'r dyn HolidayCalendar,
+        policy: ShiftPolicy,
+    ) -> ShiftedRRule<'r, 'a> {
+        ShiftedRRule {
+            rrule: self,
+            calendar,
+            policy,
+        }
+    }
+}
+
+impl<'r, 'a> ShiftedRRule<'r, 'a> {
+    /// Iterates the shifted occurrences that follow `start_date`. Occurrences shifted onto
+    /// or before one already produced are dropped, so the dates keep increasing.
+    pub fn iter_dates_from(
+        &self,
+        start_date: DateTime<Tz>,
+    ) -> impl Iterator<Item = DateTime<Tz>> + '_ {
+        self.shift_all(self.rrule.iter_dates_from(start_date))
+    }
+
+    /// Same as `RRule::get_all_iter_dates_between` with the occurrences shifted. UNTIL
+    /// bounds the occurrences before they are moved, while the count and the window apply
+    /// to the shifted dates, so skipped occurrences don't count.
+    pub fn get_all_iter_dates_between(
+        &self,
+        count_from_args: &str,
+        until_from_args: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Vec<DateTime<Tz>> {
+        self.rrule.window_iter_dates(
+            self.shift_all(self.rrule.iter_dates_until(until_from_args, after)),
+            count_from_args,
+            after,
+            before,
+        )
+    }
+
+    fn shift_all<I: Iterator<Item = DateTime<Tz>>>(
+        &self,
+        occurrences: I,
+    ) -> ShiftedDates<'_, 'r, 'a, I> {
+        ShiftedDates {
+            shifted: self,
+            occurrences,
+            last: None,
+        }
+    }
+
+    /// The business day an occurrence on `date` moves to, `None` when it is dropped
+    fn shift_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        if self.calendar.is_business_day(date) {
+            return Some(date);
+        }
+        let find = |step: i64| {
+            (1..=MAX_SHIFT_DAYS)
+                .map(|days| date + Duration::days(days * step))
+                .find(|day| self.calendar.is_business_day(*day))
+        };
+        match self.policy {
+            ShiftPolicy::Skip => None,
+            ShiftPolicy::NextBusinessDay => find(1),
+            ShiftPolicy::PreviousBusinessDay => find(-1),
+            ShiftPolicy::Nearest => match (find(-1), find(1)) {
+                (Some(previous), Some(next)) if date - previous < next - date => Some(previous),
+                (previous, next) => next.or(previous),
+            },
+        }
+    }
+
+    /// Moves an occurrence to its business day at the same local time
+    pub(crate) fn shift(&self, date: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let local = self.rrule.local_date(&date);
+        let day = self.shift_day(local.date())?;
+        if day == local.date() {
+            return Some(date);
+        }
+        resolve_local(
+            &self.rrule.zone(),
+            &day.and_time(local.time()),
+            self.rrule.dst_policy,
+        )
+        .map(|shifted| shifted.with_timezone(&date.timezone()))
+    }
+}
+
+struct ShiftedDates<'s, 'r, 'a, I> {
+    shifted: &'s ShiftedRRule<'r, 'a>,
+    occurrences: I,
+    last: Option<DateTime<Tz>>,
+}
+
+impl<'s, 'r, 'a, I: Iterator<Item = DateTime<Tz>>> Iterator for ShiftedDates<'s, 'r, 'a, I> {
+    type Item = DateTime<Tz>;
+
+    fn next(&mut self) -> Option<DateTime<Tz>> {
+        for _ in 0..MAX_DROPPED_OCCURRENCES {
+            let date = self.occurrences.next()?;
+            match self.shifted.shift(date) {
+                Some(date) if self.last.is_none_or(|last| date > last) => {
+                    self.last = Some(date);
+                    return Some(date);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Returned when a holiday calendar can't be read, the message says why
+#[derive(Debug, Clone)]
+pub struct HolidayParseError {
+    message: String,
+}
+
+impl HolidayParseError {
+    fn new<S: Into<String>>(message: S) -> HolidayParseError {
+        HolidayParseError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for HolidayParseError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "holiday calendar parse error: {}", self.message)
+    }
+}
+
+impl Error for HolidayParseError {
+    fn description(&self) -> &str {
+        "holiday calendar parse error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// The day of an iCalendar DATE or DATE-TIME value
+fn parse_ics_date(value: &str) -> Result<NaiveDate, HolidayParseError> {
+    value
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| HolidayParseError::new(format!("invalid date {:?}", value)))
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y%m%d"))
+        .ok()
+}
@@ -2,20 +2,85 @@
 extern crate pest_derive;
 
 use chrono::prelude::*;
-use chrono::{Duration, TimeZone};
+use chrono::{Duration, LocalResult, TimeZone};
 use chrono_tz::Tz;
 use pest::Parser;
 use serde::Deserialize;
 use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
-use std::str::FromStr;
+use std::ops::Add;
+use std::sync::OnceLock;
+
+mod clock;
+mod conflicts;
+mod cron;
+mod derived;
+mod diff;
+mod dst;
+mod event;
+mod fields;
+mod freebusy;
+mod holidays;
+mod materialize;
+mod merge;
+#[cfg(feature = "scheduler")]
+mod scheduler;
+mod store;
+mod systemd;
+mod text;
+mod timezone;
+mod vtimezone;
+
+pub use crate::clock::{Clock, FixedClock, SystemClock};
+pub use crate::conflicts::{find_conflicts, Conflict};
+pub use crate::cron::CronError;
+pub use crate::derived::{CutoffOffset, DerivedSchedule};
+pub use crate::diff::{diff, DiffWindow, MovedOccurrence, PartChange, ScheduleDiff};
+pub use crate::dst::{DstPolicy, GapPolicy, OverlapPolicy};
+pub use crate::event::{
+    format_ics_calendar, format_ics_date, format_ics_duration, Event, EventError, EventLength,
+    OccurrenceSpan,
+};
+pub use crate::freebusy::{free_busy, next_free_slot, FreeBusy, Period, SlotConstraints};
+pub use crate::holidays::{
+    FileHolidayCalendar, HolidayCalendar, HolidayParseError, InMemoryHolidayCalendar, ShiftPolicy,
+    ShiftedRRule,
+};
+pub use crate::materialize::{CacheStats, MaterializeError, MaterializedSchedule};
+pub use crate::merge::{merge, Merge, MergeOptions};
+#[cfg(feature = "scheduler")]
+pub use crate::scheduler::{JobId, JobRun, MissedRunPolicy, Scheduler, SchedulerError, TokioClock};
+pub use crate::store::{FileScheduleStore, ScheduleStore, StoreError, StoredSchedule};
+pub use crate::systemd::OnCalendarError;
+pub use crate::text::TextParseError;
+pub use crate::timezone::{windows_to_iana, ChronoTzProvider, TimeZoneProvider, ZoneRules};
+pub use crate::vtimezone::{TimeZoneParseError, VTimeZone, VTimeZoneProvider};
+
+use crate::clock::RuleClock;
+use crate::dst::resolve_local;
+use crate::timezone::{parse_tzid, ProvidedZone, RuleTz};
+use crate::vtimezone::nth_weekday_of_month;
 
 #[derive(Parser)]
 #[grammar = "rrule.pest"]
 struct RRuleParser;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// How a DTSTART or UNTIL date was written in the rrule string.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DateForm {
+    /// A local time without a timezone, e.g. `DTSTART=20190101T090000`.
+    #[default]
+    Floating,
+    /// A UTC time ending in `Z`, e.g. `DTSTART=20190101T090000Z`.
+    Utc,
+    /// A local time in the given timezone, e.g. `DTSTART;TZID=Australia/Sydney:20190101T090000`.
+    Zoned,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct RRule<'a> {
     #[serde(default = "default_rrule_string_field")]
@@ -67,6 +132,18 @@ pub struct RRule<'a> {
     #[serde(borrow)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     by_year_day: Vec<&'a str>,
+    #[serde(skip)]
+    dst_policy: DstPolicy,
+    #[serde(skip)]
+    dtstart_form: DateForm,
+    #[serde(skip)]
+    until_form: DateForm,
+    #[serde(skip)]
+    zone: Option<ProvidedZone>,
+    #[serde(skip)]
+    output_timezone: Option<Tz>,
+    #[serde(skip)]
+    clock: RuleClock,
 }
 
 fn default_rrule_string_field() -> String {
@@ -87,7 +164,7 @@ impl<'a> RRule<'a> {
     /// ```
     #[inline]
     pub fn new<'b>() -> RRule<'b> {
-        return RRule {
+        RRule {
             tzid: String::from(""),
             dtstart: String::from(""),
             until: String::from(""),
@@ -102,10 +179,17 @@ impl<'a> RRule<'a> {
             by_day: Vec::new(),
             by_month_day: Vec::new(),
             by_year_day: Vec::new(),
-        };
+            dst_policy: DstPolicy::default(),
+            dtstart_form: DateForm::default(),
+            until_form: DateForm::default(),
+            zone: None,
+            output_timezone: None,
+            clock: RuleClock::default(),
+        }
     }
 
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new_rrule<'b>(
         tzid: String,
         dtstart: String,
@@ -122,7 +206,7 @@ impl<'a> RRule<'a> {
         by_month_day: Vec<&'b str>,
         by_year_day: Vec<&'b str>,
     ) -> RRule<'b> {
-        return RRule {
+        RRule {
             tzid,
             dtstart,
             until,
@@ -137,32 +221,26 @@ impl<'a> RRule<'a> {
             by_day,
             by_month_day,
             by_year_day,
-        };
+            dst_policy: DstPolicy::default(),
+            dtstart_form: DateForm::default(),
+            until_form: DateForm::default(),
+            zone: None,
+            output_timezone: None,
+            clock: RuleClock::default(),
+        }
     }
 
-    // parent function that can get a list of all future iterations based on count
+    // parent function that can get a list of all future iterations based on count, a rule
+    // without DTSTART starts from the rule's clock
     pub fn get_all_iter_dates(
         &self,
         count_from_args: &str,
         until_from_args: &str,
     ) -> Vec<DateTime<Tz>> {
-        let timezone: Tz = if self.tzid.is_empty() {
-            "UTC".parse().unwrap()
-        } else {
-            self.tzid.parse().unwrap()
-        };
-
-        // we will work under the assumption that the date provided by dtstart parser will always be
-        // and we will convert to the required timezone if provided.
-        let start_date = if self.dtstart.is_empty() {
-            Utc::now().with_timezone(&timezone)
-        } else {
-            Utc.datetime_from_str(&self.dtstart, "%Y-%m-%d %H:%M:%S")
-                .unwrap()
-                .with_timezone(&timezone)
-        };
+        let timezone = self.timezone();
+        let start_date = self.start_date(self.clock.now().with_timezone(&timezone));
 
-        let mut count: i32 = 52; // default count of iterations to build
+        let count = self.count(count_from_args);
 
         let mut until = "";
 
@@ -173,15 +251,6 @@ impl<'a> RRule<'a> {
             _wkst = &self.wkst
         }
 
-        // set count
-        if count_from_args.is_empty() {
-            if !self.count.is_empty() {
-                count = self.count.parse().unwrap()
-            }
-        } else {
-            count = count_from_args.parse::<i32>().unwrap();
-        }
-
         if until_from_args.is_empty() {
             if !self.until.is_empty() {
                 until = &self.until;
@@ -191,27 +260,33 @@ impl<'a> RRule<'a> {
         }
 
         let mut next_dates_list: Vec<DateTime<Tz>> = Vec::new();
-        let mut next_date = start_date;
+        let mut iter_dates = self.iter_dates_from(start_date);
 
         if until.is_empty() {
             for _i in 0..count {
-                next_date = self.get_next_date(next_date);
+                let next_date = match iter_dates.next() {
+                    Some(next_date) => next_date,
+                    None => break,
+                };
                 next_dates_list.push(next_date);
             }
         } else {
             let until_date: DateTime<Tz> = Utc
-                .datetime_from_str(&until, "%Y-%m-%d %H:%M:%S")
+                .datetime_from_str(until, "%Y-%m-%d %H:%M:%S")
                 .unwrap()
                 .with_timezone(&timezone);
             for _i in 0..count {
-                next_date = self.get_next_date(next_date);
+                let next_date = match iter_dates.next() {
+                    Some(next_date) => next_date,
+                    None => break,
+                };
                 if next_date.gt(&until_date) {
                     break;
                 }
                 next_dates_list.push(next_date);
             }
         }
-        next_dates_list
+        self.in_output_timezone(next_dates_list)
     }
 
     // parent function that can get a list of all future iterations based on count, with the date list staring at or beyond the cutoff_date
@@ -221,21 +296,9 @@ impl<'a> RRule<'a> {
         until_from_args: &str,
         cutoff_date: DateTime<Tz>,
     ) -> Vec<DateTime<Tz>> {
-        let timezone: Tz = if self.tzid.is_empty() {
-            "UTC".parse().unwrap()
-        } else {
-            self.tzid.parse().unwrap()
-        };
-
-        // we will work under the assumption that the date provided by dtstart parser will always be
-        // and we will convert to the required timezone if provided.
-        let start_date = if self.dtstart.is_empty() {
-            Utc::now().with_timezone(&timezone)
-        } else {
-            Utc.datetime_from_str(&self.dtstart, "%Y-%m-%d %H:%M:%S")
-                .unwrap()
-                .with_timezone(&timezone)
-        };
+        let timezone = self.timezone();
+        // without a DTSTART we start iterating from the cutoff itself
+        let start_date = self.start_date(cutoff_date);
 
         // assign default weekstart and reassign if present
         let _wkst = if !self.wkst.is_empty() {
@@ -244,16 +307,7 @@ impl<'a> RRule<'a> {
             "MO"
         };
 
-        // set count
-        let count = if count_from_args.is_empty() {
-            if !self.count.is_empty() {
-                self.count.parse().unwrap()
-            } else {
-                52 // default count of iterations to build
-            }
-        } else {
-            count_from_args.parse::<i32>().unwrap()
-        };
+        let count = self.count(count_from_args);
 
         let until = if until_from_args.is_empty() {
             if !self.until.is_empty() {
@@ -266,22 +320,28 @@ impl<'a> RRule<'a> {
         };
 
         let mut next_dates_list: Vec<DateTime<Tz>> = Vec::new();
-        let mut next_date = start_date;
+        let mut iter_dates = self.iter_dates_from(start_date);
 
         if until.is_empty() {
-            while next_dates_list.len().lt(&(count as usize)) {
-                next_date = self.get_next_date(next_date);
+            while next_dates_list.len().lt(&count) {
+                let next_date = match iter_dates.next() {
+                    Some(next_date) => next_date,
+                    None => break,
+                };
                 if next_date.ge(&cutoff_date) {
                     next_dates_list.push(next_date);
                 }
             }
         } else {
             let until_date: DateTime<Tz> = Utc
-                .datetime_from_str(&until, "%Y-%m-%d %H:%M:%S")
+                .datetime_from_str(until, "%Y-%m-%d %H:%M:%S")
                 .unwrap()
                 .with_timezone(&timezone);
-            while next_dates_list.len().lt(&(count as usize)) {
-                next_date = self.get_next_date(next_date);
+            while next_dates_list.len().lt(&count) {
+                let next_date = match iter_dates.next() {
+                    Some(next_date) => next_date,
+                    None => break,
+                };
                 if next_date.gt(&until_date) {
                     break;
                 }
@@ -290,7 +350,7 @@ impl<'a> RRule<'a> {
                 }
             }
         }
-        next_dates_list
+        self.in_output_timezone(next_dates_list)
     }
 
     pub fn get_all_iter_dates_iso8601(
@@ -298,28 +358,111 @@ impl<'a> RRule<'a> {
         count_from_args: &str,
         until_from_args: &str,
     ) -> Vec<String> {
-        convert_datetime_tz_list_to_rfc339(
-            self.get_all_iter_dates(count_from_args, until_from_args),
-        )
+        self.convert_to_rfc3339_in_zone(self.get_all_iter_dates(count_from_args, until_from_args))
     }
 
-    pub fn get_all_iter_dates_from_today_iso8601(
+    /// Gets the occurrences of the rule as floating local times, that is wall clock times
+    /// which are not bound to any timezone as described in RFC 5545. DTSTART and UNTIL are
+    /// read as local times and every frequency steps through wall clock time.
+    ///
+    /// The occurrences can later be bound to a timezone with `bind_floating_dates`, for
+    /// example the local timezone of whoever the schedule is evaluated for. Without DTSTART
+    /// the UTC wall clock time of the rule's clock is used as the start.
+    pub fn get_all_floating_iter_dates(
         &self,
         count_from_args: &str,
         until_from_args: &str,
-    ) -> Vec<String> {
-        let timezone: Tz = if self.tzid.is_empty() {
-            "UTC".parse().unwrap()
+    ) -> Vec<NaiveDateTime> {
+        let start_date = self
+            .local_dtstart()
+            .unwrap_or_else(|| self.clock.now().naive_utc());
+        let until = self.until(until_from_args);
+        let until_date = if until.is_empty() {
+            None
         } else {
-            self.tzid.parse().unwrap()
+            Some(parse_local_date(until))
         };
-        convert_datetime_tz_list_to_rfc339(self.get_all_iter_dates_from_cutoff(
+
+        let mut next_dates_list: Vec<NaiveDateTime> = Vec::new();
+        let mut iter_dates = self.iter_local_dates_from(start_date);
+        while next_dates_list.len() < self.count(count_from_args) {
+            let next_date = match iter_dates.next() {
+                Some(next_date) => next_date,
+                None => break,
+            };
+            if until_date.is_some_and(|until_date| next_date.gt(&until_date)) {
+                break;
+            }
+            next_dates_list.push(next_date);
+        }
+        next_dates_list
+    }
+
+    /// Binds floating local times to `timezone`, nonexistent and ambiguous local times are
+    /// handled by the rule's `DstPolicy`.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;DTSTART=20190401T000000").unwrap();
+    /// let dates = rrule.bind_floating_dates(
+    ///     &rrule.get_all_floating_iter_dates("", ""),
+    ///     chrono_tz::Australia::Perth,
+    /// );
+    /// assert_eq!("2019-04-01T09:00:00+08:00", dates[0].to_rfc3339());
+    /// ```
+    pub fn bind_floating_dates(
+        &self,
+        dates_list: &[NaiveDateTime],
+        timezone: Tz,
+    ) -> Vec<DateTime<Tz>> {
+        dates_list
+            .iter()
+            .filter_map(|date| resolve_local(&timezone, date, self.dst_policy))
+            .collect()
+    }
+
+    /// The occurrences from today on, as read from the rule's clock
+    pub fn get_all_iter_dates_from_today_iso8601(
+        &self,
+        count_from_args: &str,
+        until_from_args: &str,
+    ) -> Vec<String> {
+        self.convert_to_rfc3339_in_zone(self.get_all_iter_dates_from_cutoff(
             count_from_args,
             until_from_args,
-            Utc::now().with_timezone(&timezone),
+            self.clock.now().with_timezone(&self.timezone()),
         ))
     }
 
+    /// Converts occurrences to the timezone set with `with_output_timezone`, if any
+    fn in_output_timezone(&self, dates_list: Vec<DateTime<Tz>>) -> Vec<DateTime<Tz>> {
+        match self.output_timezone {
+            Some(output_timezone) => {
+                convert_datetime_tz_list_to_timezone(dates_list, output_timezone)
+            }
+            None => dates_list,
+        }
+    }
+
+    /// Formats occurrences with the UTC offsets of the rule's zone, which differ from the
+    /// ones of their `Tz` for a zone only a `TimeZoneProvider` knows. Occurrences converted
+    /// to an output timezone already have its offsets.
+    fn convert_to_rfc3339_in_zone(&self, dates_list: Vec<DateTime<Tz>>) -> Vec<String> {
+        match &self.zone {
+            Some(zone) if self.output_timezone.is_none() => {
+                let zone = RuleTz::Provided(zone.rules());
+                convert_datetime_tz_list_to_rfc339(
+                    dates_list
+                        .iter()
+                        .map(|date| date.with_timezone(&zone))
+                        .collect(),
+                )
+            }
+            _ => convert_datetime_tz_list_to_rfc339(dates_list),
+        }
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
@@ -328,26 +471,531 @@ impl<'a> RRule<'a> {
         serde_json::to_string_pretty(self).unwrap()
     }
 
-    /// Gets a list of next dates that are
-    pub fn get_next_iter_dates(
+    /// Builds the canonical rrule string for this rule. Parts are written in a fixed order
+    /// with FREQ first and TZID and DTSTART last, numbers lose their leading zeros,
+    /// repeated values are dropped and default INTERVAL=1 and WKST=MO parts are left out.
+    /// DTSTART and UNTIL keep the form they were given in, a zoned DTSTART is written as
+    /// `DTSTART;TZID=<zone>:<local time>` in place of the TZID part.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("DTSTART=20190101T090000;BYHOUR=09;INTERVAL=1;FREQ=DAILY").unwrap();
+    /// assert_eq!("FREQ=DAILY;BYHOUR=9;DTSTART=20190101T090000", rrule.to_rrule_string());
+    /// ```
+    pub fn to_rrule_string(&self) -> String {
+        let mut parts = self.recurrence_parts();
+        if !self.tzid.is_empty() && self.dtstart_form != DateForm::Zoned {
+            parts.push(format!("TZID={}", self.tzid));
+        }
+        if !self.dtstart.is_empty() {
+            parts.push(match self.dtstart_form {
+                DateForm::Zoned => format!(
+                    "DTSTART;TZID={}:{}",
+                    self.tzid,
+                    format_rrule_date(&self.dtstart, self.dtstart_form)
+                ),
+                _ => format!(
+                    "DTSTART={}",
+                    format_rrule_date(&self.dtstart, self.dtstart_form)
+                ),
+            });
+        }
+        parts.join(";")
+    }
+
+    /// The value of the iCalendar RRULE property for this rule, that is `to_rrule_string`
+    /// without the TZID and DTSTART which iCalendar gives as the DTSTART property.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("DTSTART;TZID=Australia/Sydney:20190101T090000;FREQ=DAILY;COUNT=2").unwrap();
+    /// assert_eq!("FREQ=DAILY;COUNT=2", rrule.to_ics_rrule());
+    /// assert_eq!(Some("Australia/Sydney"), rrule.tzid());
+    /// ```
+    pub fn to_ics_rrule(&self) -> String {
+        self.recurrence_parts().join(";")
+    }
+
+    /// The TZID the rule is evaluated in, `None` for UTC
+    pub fn tzid(&self) -> Option<&str> {
+        if self.tzid.is_empty() {
+            None
+        } else {
+            Some(&self.tzid)
+        }
+    }
+
+    /// The canonical parts of the rule other than TZID and DTSTART
+    fn recurrence_parts(&self) -> Vec<String> {
+        let mut parts: Vec<String> = Vec::new();
+        if !self.frequency.is_empty() {
+            parts.push(format!("FREQ={}", self.frequency));
+        }
+        let interval = normalize_number(&self.interval);
+        if !interval.is_empty() && interval != "1" {
+            parts.push(format!("INTERVAL={}", interval));
+        }
+        if !self.count.is_empty() {
+            parts.push(format!("COUNT={}", normalize_number(&self.count)));
+        }
+        if !self.until.is_empty() {
+            parts.push(format!(
+                "UNTIL={}",
+                format_rrule_date(&self.until, self.until_form)
+            ));
+        }
+        let by_parts = [
+            ("BYMONTH", &self.by_month),
+            ("BYYEARDAY", &self.by_year_day),
+            ("BYMONTHDAY", &self.by_month_day),
+            ("BYDAY", &self.by_day),
+            ("BYHOUR", &self.by_hour),
+            ("BYMINUTE", &self.by_minute),
+            ("BYSECOND", &self.by_second),
+        ];
+        for (name, values) in by_parts.iter() {
+            if values.is_empty() {
+                continue;
+            }
+            let mut normalized: Vec<String> = Vec::new();
+            for value in values.iter() {
+                let value = normalize_number(value);
+                if !normalized.contains(&value) {
+                    normalized.push(value);
+                }
+            }
+            parts.push(format!("{}={}", name, normalized.join(",")));
+        }
+        if !self.wkst.is_empty() && self.wkst != "MO" {
+            parts.push(format!("WKST={}", self.wkst));
+        }
+        parts
+    }
+
+    /// Gets the occurrences at or after `after` and strictly before `before`, bounded by
+    /// the count and until like `get_all_iter_dates`. Unlike `get_all_iter_dates` the
+    /// count only includes occurrences inside the window. Without DTSTART the iteration
+    /// starts from `after`, or from the rule's clock when there is no `after` either.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000").unwrap();
+    /// let dates = rrule.get_all_iter_dates_between(
+    ///     "",
+    ///     "",
+    ///     Some(Utc.ymd(2019, 1, 10).and_hms(0, 0, 0)),
+    ///     Some(Utc.ymd(2019, 1, 12).and_hms(9, 0, 0)),
+    /// );
+    /// assert_eq!(2, dates.len());
+    /// assert_eq!("2019-01-10T09:00:00+00:00", dates[0].to_rfc3339());
+    /// ```
+    pub fn get_all_iter_dates_between(
         &self,
         count_from_args: &str,
         until_from_args: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
     ) -> Vec<DateTime<Tz>> {
-        let timezone: Tz = if self.tzid.is_empty() {
-            "UTC".parse().unwrap()
+        self.window_iter_dates(
+            self.iter_dates_until(until_from_args, after),
+            count_from_args,
+            after,
+            before,
+        )
+    }
+
+    /// The occurrences up to UNTIL, starting from DTSTART or else from `after` or the
+    /// rule's clock
+    pub(crate) fn iter_dates_until(
+        &self,
+        until_from_args: &str,
+        after: Option<DateTime<Utc>>,
+    ) -> impl Iterator<Item = DateTime<Tz>> + '_ {
+        let now = after.unwrap_or_else(|| self.clock.now());
+        let start_date = self.start_date(now.with_timezone(&self.timezone()));
+        let until = self.until(until_from_args);
+        let until_date = if until.is_empty() {
+            None
         } else {
-            self.tzid.parse().unwrap()
+            Some(Utc.datetime_from_str(until, "%Y-%m-%d %H:%M:%S").unwrap())
         };
+        self.iter_dates_from(start_date).take_while(move |date| {
+            until_date.is_none_or(|until_date| date.with_timezone(&Utc) <= until_date)
+        })
+    }
+
+    /// The `occurrences` at or after `after` and strictly before `before`, up to the count
+    pub(crate) fn window_iter_dates(
+        &self,
+        occurrences: impl Iterator<Item = DateTime<Tz>>,
+        count_from_args: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Vec<DateTime<Tz>> {
+        let count = self.count(count_from_args);
+        let mut next_dates_list: Vec<DateTime<Tz>> = Vec::new();
+        for next_date in occurrences {
+            let instant = next_date.with_timezone(&Utc);
+            if next_dates_list.len() >= count || before.is_some_and(|before| instant >= before) {
+                break;
+            }
+            if after.is_none_or(|after| instant >= after) {
+                next_dates_list.push(next_date);
+            }
+        }
+        self.in_output_timezone(next_dates_list)
+    }
+
+    /// Gets a list of next dates that are after now, as read from the rule's clock
+    pub fn get_next_iter_dates(
+        &self,
+        count_from_args: &str,
+        until_from_args: &str,
+    ) -> Vec<DateTime<Tz>> {
         lens_iter_dates(
             self.get_all_iter_dates(count_from_args, until_from_args),
-            Utc::now().with_timezone(&timezone),
+            self.clock.now().with_timezone(&self.timezone()),
         )
     }
 
+    /// The chrono-tz timezone occurrences are given in, UTC when no TZID is present or when
+    /// the TZID is only known to the `TimeZoneProvider` the rule was parsed with
+    fn timezone(&self) -> Tz {
+        parse_tzid(&self.tzid).unwrap_or(Tz::UTC)
+    }
+
+    /// The zone the rule is evaluated in, which unlike `timezone` includes the zones only
+    /// known to the `TimeZoneProvider` the rule was parsed with
+    pub(crate) fn zone(&self) -> RuleTz<'_> {
+        match &self.zone {
+            Some(zone) => RuleTz::Provided(zone.rules()),
+            None => RuleTz::Chrono(self.timezone()),
+        }
+    }
+
+    /// The local wall clock time of an occurrence in the rule's zone
+    pub(crate) fn local_date(&self, date: &DateTime<Tz>) -> NaiveDateTime {
+        match &self.zone {
+            Some(zone) => date
+                .with_timezone(&RuleTz::Provided(zone.rules()))
+                .naive_local(),
+            None => date.naive_local(),
+        }
+    }
+
+    /// The date iteration starts from, `now` is only used when the rule has no DTSTART
+    fn start_date<T: TimeZone>(&self, now: DateTime<T>) -> DateTime<T> {
+        match self.dtstart_instant() {
+            Some(dtstart) => dtstart.with_timezone(&now.timezone()),
+            None => now,
+        }
+    }
+
+    /// DTSTART as an instant, `None` when the rule has no DTSTART
+    pub(crate) fn dtstart_instant(&self) -> Option<DateTime<Utc>> {
+        // we will work under the assumption that the date provided by dtstart parser will always be
+        // and we will convert to the required timezone if provided.
+        if self.dtstart.is_empty() {
+            None
+        } else if self.dtstart_form == DateForm::Zoned {
+            // DTSTART;TZID= is kept as a local time in the rule's timezone
+            self.zone()
+                .from_local_datetime(&parse_local_date(&self.dtstart))
+                .earliest()
+                .map(|dtstart| dtstart.with_timezone(&Utc))
+        } else {
+            Some(
+                Utc.datetime_from_str(&self.dtstart, "%Y-%m-%d %H:%M:%S")
+                    .unwrap(),
+            )
+        }
+    }
+
+    /// DTSTART as a local wall clock time, ignoring any timezone it was given with
+    fn local_dtstart(&self) -> Option<NaiveDateTime> {
+        if self.dtstart.is_empty() {
+            None
+        } else {
+            Some(parse_local_date(&self.dtstart))
+        }
+    }
+
+    /// The number of occurrences to build, from the arguments, the COUNT part or the default.
+    /// Arguments that aren't a number fall back to the COUNT part, which `validate_rrule`
+    /// has already checked.
+    fn count(&self, count_from_args: &str) -> usize {
+        count_from_args
+            .parse()
+            .or_else(|_| self.count.parse())
+            .unwrap_or(52) // default count of iterations to build
+    }
+
+    /// The COUNT of a rule without DTSTART, whose occurrences are counted from wherever the
+    /// iteration starts, so whoever steps through them one at a time has to keep count
+    pub(crate) fn floating_count(&self) -> Option<usize> {
+        if self.dtstart.is_empty() && !self.count.is_empty() {
+            Some(self.count(""))
+        } else {
+            None
+        }
+    }
+
+    /// A copy of the rule whose part values don't borrow from the rrule string it was parsed
+    /// from, for keeping parsed rules around. `None` for values no valid rule has.
+    pub(crate) fn to_static(&self) -> Option<RRule<'static>> {
+        let values = |values: &[&str]| -> Option<Vec<&'static str>> {
+            values.iter().map(|value| static_value(value)).collect()
+        };
+        Some(RRule {
+            tzid: self.tzid.clone(),
+            dtstart: self.dtstart.clone(),
+            until: self.until.clone(),
+            frequency: self.frequency.clone(),
+            count: self.count.clone(),
+            interval: self.interval.clone(),
+            wkst: self.wkst.clone(),
+            by_month: values(&self.by_month)?,
+            by_hour: values(&self.by_hour)?,
+            by_minute: values(&self.by_minute)?,
+            by_second: values(&self.by_second)?,
+            by_day: values(&self.by_day)?,
+            by_month_day: values(&self.by_month_day)?,
+            by_year_day: values(&self.by_year_day)?,
+            dst_policy: self.dst_policy,
+            dtstart_form: self.dtstart_form,
+            until_form: self.until_form,
+            zone: self.zone.clone(),
+            output_timezone: self.output_timezone,
+            clock: self.clock.clone(),
+        })
+    }
+
+    /// The until date to stop at, from the arguments or the UNTIL part
+    fn until<'s>(&'s self, until_from_args: &'s str) -> &'s str {
+        if until_from_args.is_empty() {
+            &self.until
+        } else {
+            until_from_args
+        }
+    }
+
+    /// The day weeks start on, from the WKST part or Monday when it is not given
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::Weekday;
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;WKST=SU").unwrap();
+    /// assert_eq!(Weekday::Sun, rrule.week_start());
+    /// ```
+    pub fn week_start(&self) -> Weekday {
+        rrule_byday_to_chrono_weekday(&self.wkst).unwrap_or(Weekday::Mon)
+    }
+
+    /// How DTSTART was written, floating when the rule has no DTSTART
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::{convert_to_rrule, DateForm};
+    /// let rrule = convert_to_rrule("FREQ=DAILY;DTSTART=20190101T090000Z").unwrap();
+    /// assert_eq!(DateForm::Utc, rrule.dtstart_form());
+    /// ```
+    pub fn dtstart_form(&self) -> DateForm {
+        self.dtstart_form
+    }
+
+    /// How UNTIL was written, floating when the rule has no UNTIL
+    pub fn until_form(&self) -> DateForm {
+        self.until_form
+    }
+
+    /// Sets how occurrences that fall in a daylight saving transition of the rule's timezone
+    /// are handled, see `DstPolicy`
+    pub fn with_dst_policy(mut self, dst_policy: DstPolicy) -> Self {
+        self.dst_policy = dst_policy;
+        self
+    }
+
+    /// Sets the clock the rule reads the current time from, which is used as the start of a
+    /// rule without DTSTART and to scope results from today. Defaults to `SystemClock`.
+    ///
+    /// Example:
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use sundial::{convert_to_rrule, FixedClock};
+    /// let rrule = convert_to_rrule("FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;BYSECOND=0")
+    ///     .unwrap()
+    ///     .with_clock(FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+    /// let dates = rrule.get_all_iter_dates("", "");
+    /// assert_eq!("2019-04-15T09:00:00+00:00", dates[0].to_rfc3339());
+    /// ```
+    pub fn with_clock<C: Clock + Send + Sync + 'static>(mut self, clock: C) -> Self {
+        self.clock = RuleClock::new(clock);
+        self
+    }
+
+    /// Converts every occurrence the `get_all_*` and `get_next_iter_dates` methods return to
+    /// `output_timezone`, the rule is still evaluated in its own TZID. This shows a schedule
+    /// defined in Perth to someone in Sydney.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=DAILY;COUNT=1;BYHOUR=9;BYMINUTE=0;TZID=Australia/Perth;DTSTART=20190401T000000")
+    ///     .unwrap()
+    ///     .with_output_timezone(chrono_tz::Australia::Sydney);
+    /// let dates = rrule.get_all_iter_dates("", "");
+    /// assert_eq!("2019-04-01T12:00:00+11:00", dates[0].to_rfc3339());
+    /// ```
+    pub fn with_output_timezone(mut self, output_timezone: Tz) -> Self {
+        self.output_timezone = Some(output_timezone);
+        self
+    }
+
     // standalone function that gets iterations from a single start date
     pub fn get_next_date(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
-        let next_date = if self.frequency.eq("YEARLY") {
+        self.iter_dates_from(start_date)
+            .next()
+            .unwrap_or(start_date)
+    }
+
+    /// Iterates the occurrences that follow `start_date` in the rule's zone, given in the
+    /// timezone of `start_date`
+    pub(crate) fn iter_dates_from(
+        &self,
+        start_date: DateTime<Tz>,
+    ) -> Box<dyn Iterator<Item = DateTime<Tz>> + '_> {
+        match &self.zone {
+            Some(zone) => {
+                let timezone = start_date.timezone();
+                let zone = RuleTz::Provided(zone.rules());
+                Box::new(
+                    self.iter_zone_dates_from(start_date.with_timezone(&zone))
+                        .map(move |date| date.with_timezone(&timezone)),
+                )
+            }
+            None => Box::new(self.iter_zone_dates_from(start_date)),
+        }
+    }
+
+    /// Iterates the occurrences that follow `start_date`. Daily and coarser frequencies are
+    /// calculated on the local wall clock time so they stay at the same local time across
+    /// daylight saving transitions, finer frequencies step through elapsed time.
+    fn iter_zone_dates_from<T: TimeZone>(
+        &self,
+        start_date: DateTime<T>,
+    ) -> UnionDates<IterDates<'_, 'a, T>>
+    where
+        T::Offset: Copy,
+    {
+        UnionDates::new(
+            self.single_value_rules()
+                .into_iter()
+                .map(|rrule| IterDates {
+                    rrule,
+                    local_date: start_date.naive_local(),
+                    date: start_date,
+                })
+                .collect(),
+        )
+    }
+
+    /// Same as `iter_dates_from` but iterating floating local times
+    fn iter_local_dates_from(
+        &self,
+        local_date: NaiveDateTime,
+    ) -> UnionDates<IterLocalDates<'_, 'a>> {
+        UnionDates::new(
+            self.single_value_rules()
+                .into_iter()
+                .map(|rrule| IterLocalDates { rrule, local_date })
+                .collect(),
+        )
+    }
+
+    /// The rules with a single BYMONTH, BYMONTHDAY, BYHOUR, BYMINUTE and BYSECOND value
+    /// whose occurrences together are the occurrences of this rule, the next date
+    /// calculations only look at the first value of those parts. A rule without lists is
+    /// its own single rule.
+    fn single_value_rules(&self) -> Vec<Cow<'_, RRule<'a>>> {
+        let parts = [
+            &self.by_month,
+            &self.by_month_day,
+            &self.by_hour,
+            &self.by_minute,
+            &self.by_second,
+        ];
+        if parts.iter().all(|values| values.len() <= 1) {
+            return vec![Cow::Borrowed(self)];
+        }
+        let mut rules = vec![self.clone()];
+        rules = split_values(rules, |rrule| &mut rrule.by_month);
+        rules = split_values(rules, |rrule| &mut rrule.by_month_day);
+        rules = split_values(rules, |rrule| &mut rrule.by_hour);
+        rules = split_values(rules, |rrule| &mut rrule.by_minute);
+        rules = split_values(rules, |rrule| &mut rrule.by_second);
+        rules.into_iter().map(Cow::Owned).collect()
+    }
+
+    /// Same as `iter_dates_from` but ending with the COUNT and UNTIL of the rule
+    pub(crate) fn bounded_iter_dates_from(
+        &self,
+        start_date: DateTime<Tz>,
+    ) -> Box<dyn Iterator<Item = DateTime<Tz>> + '_> {
+        let until_date = if self.until.is_empty() {
+            None
+        } else {
+            Some(
+                Utc.datetime_from_str(&self.until, "%Y-%m-%d %H:%M:%S")
+                    .unwrap(),
+            )
+        };
+        let count = if self.count.is_empty() {
+            usize::MAX
+        } else {
+            self.count("")
+        };
+        Box::new(
+            self.iter_dates_from(start_date)
+                .take_while(move |date| {
+                    until_date.is_none_or(|until_date| date.with_timezone(&Utc) <= until_date)
+                })
+                .take(count),
+        )
+    }
+
+    /// The longest one INTERVAL of the rule's frequency can be, a month is 31 days and a
+    /// year 366
+    pub(crate) fn interval_length(&self) -> Duration {
+        let interval = self
+            .interval
+            .parse::<u32>()
+            .unwrap_or(1)
+            .clamp(1, MAX_INTERVAL);
+        let unit = match self.frequency.as_str() {
+            "SECONDLY" => Duration::seconds(1),
+            "MINUTELY" => Duration::minutes(1),
+            "HOURLY" => Duration::hours(1),
+            "DAILY" => Duration::days(1),
+            "WEEKLY" => Duration::weeks(1),
+            "FORTNIGHTLY" => Duration::weeks(2),
+            "MONTHLY" => Duration::days(31),
+            _ => Duration::days(366),
+        };
+        unit * interval as i32
+    }
+
+    fn is_sub_daily(&self) -> bool {
+        self.frequency == "HOURLY" || self.frequency == "MINUTELY" || self.frequency == "SECONDLY"
+    }
+
+    /// Calculates the next local wall clock time for daily and coarser frequencies
+    fn get_next_local_date(&self, start_date: NaiveDateTime) -> NaiveDateTime {
+        if self.frequency.eq("YEARLY") {
             self.handle_yearly(start_date)
         } else if self.frequency == "MONTHLY" {
             self.handle_monthly(start_date)
@@ -355,21 +1003,51 @@ impl<'a> RRule<'a> {
             self.handle_weekly(start_date)
         } else if self.frequency == "DAILY" {
             self.handle_daily(start_date)
-        } else if self.frequency == "HOURLY" {
+        } else {
+            // println!("Given rrule frequency is not supported");
+            start_date
+        }
+    }
+
+    /// Calculates the next date for hourly and finer frequencies
+    fn get_next_sub_daily_date<D: IterDate>(&self, start_date: D) -> D {
+        if self.frequency == "HOURLY" {
             self.handle_hourly(start_date)
         } else if self.frequency == "MINUTELY" {
             self.handle_minutely(start_date)
-        } else if self.frequency == "SECONDLY" {
+        } else {
             self.handle_secondly(start_date)
+        }
+    }
+
+    /// The next hourly or finer occurrence from `initial_date`, the previous date with the BY
+    /// parts finer than the frequency applied. That is the occurrence itself when its wall
+    /// clock time is `ahead` of the previous date and it matches the rule, such as 00:15
+    /// for `BYMINUTE=15` from 00:00.
+    fn next_sub_daily_date<D: IterDate>(&self, initial_date: D, ahead: bool) -> D {
+        if ahead && self.matches_sub_daily_filters(initial_date) {
+            initial_date
         } else {
-            // println!("Given rrule frequency is not supported");
-            start_date
+            self.get_next_sub_daily_date(initial_date)
+        }
+    }
+
+    /// Whether the date is in the BYMONTH, BYDAY and BYHOUR the hourly and finer
+    /// frequencies step through
+    fn matches_sub_daily_filters<D: IterDate>(&self, date: D) -> bool {
+        let matches = |values: &[&str], value: u32| {
+            values
+                .first()
+                .is_none_or(|first| first.parse::<u32>() == Ok(value))
         };
-        return next_date;
+        matches(&self.by_month, date.month())
+            && self.matches_by_day(date.weekday())
+            && (self.frequency == "HOURLY" && self.by_hour.is_empty()
+                || matches(&self.by_hour, date.hour()))
     }
 
     // set the lower interval time for start date
-    fn with_initial_time_intervals(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
+    fn with_initial_time_intervals(&self, start_date: NaiveDateTime) -> NaiveDateTime {
         // let mut start_date_with_intervals = start_date;
 
         let start_date_with_second = if self.frequency.ne("SECONDLY") {
@@ -413,49 +1091,108 @@ impl<'a> RRule<'a> {
     }
 
     // currently only supports rrules of type: REQ=YEARLY;COUNT=x;INTERVAL=x
-    fn handle_yearly(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
+    fn handle_yearly(&self, start_date: NaiveDateTime) -> NaiveDateTime {
         let interval: u32 = self.interval.parse().unwrap_or(1);
         let max_year = 2099;
         let mut next_date = start_date;
-        let mut next_year = start_date.year() + 1;
-        for _i in 0..interval {
-            if next_date.year().lt(&(max_year as i32)) {
+        for (next_year, _i) in ((start_date.year() + 1)..).zip(0..interval) {
+            if next_date.year().lt(&max_year) {
                 next_date = next_date.with_year(next_year).unwrap()
             }
-            next_year += 1;
         }
         next_date
     }
 
     /// Handles the calculation of next date based on a monthly rule.
-    /// Currently supports BYMONTH and BYMONTHDAY params
-    fn handle_monthly(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
-        let mut next_date: DateTime<Tz> = self.with_initial_time_intervals(start_date);
-        let interval: u32 = self.interval.parse().unwrap_or(1);
-
-        let by_month_day = self.by_month_day.first().unwrap_or(&"").to_owned();
-        let by_month = self.by_month.first().unwrap_or(&"").to_owned();
-
-        if !by_month_day.is_empty() {
-            let by_month_day_u32 = by_month_day.parse::<u32>().unwrap();
-            next_date = next_date.with_day(by_month_day_u32).unwrap();
+    /// Currently supports BYMONTH, BYMONTHDAY and ordinal BYDAY params, months without the
+    /// day such as February for BYMONTHDAY=30 are skipped
+    fn handle_monthly(&self, start_date: NaiveDateTime) -> NaiveDateTime {
+        if let Some((ordinal, weekday)) = self.by_day.first().and_then(|day| split_byday(day)) {
+            if ordinal != 0 {
+                return self.handle_monthly_by_ordinal_weekday(start_date, ordinal, weekday);
+            }
         }
+        // without BYMONTHDAY the rule repeats on the day of the month it started on
+        let by_month_day = match self.by_month_day.first() {
+            Some(by_month_day) => by_month_day.parse::<u32>().unwrap_or(0),
+            None => start_date.day(),
+        };
+        self.next_monthly_date(start_date, |year, month| {
+            NaiveDate::from_ymd_opt(year, month, by_month_day)
+        })
+    }
 
-        if !by_month.is_empty() {
-            let by_month_u32 = by_month.parse::<u32>().unwrap();
-            next_date = next_date.with_month(by_month_u32).unwrap();
-            if next_date <= start_date {
-                next_date = next_date.with_year(next_date.year() + 1).unwrap();
+    /// Handles monthly rules with an ordinal BYDAY such as `-1FR`, the last Friday of the
+    /// month. Months without that weekday, like a 5th Monday, are skipped.
+    fn handle_monthly_by_ordinal_weekday(
+        &self,
+        start_date: NaiveDateTime,
+        ordinal: i32,
+        weekday: Weekday,
+    ) -> NaiveDateTime {
+        self.next_monthly_date(start_date, |year, month| {
+            nth_weekday_of_month(year, month, ordinal, weekday)
+        })
+    }
+
+    /// The first date after `start_date` on the day `day_of_month` picks in a month,
+    /// stepping INTERVAL months at a time and skipping the months without such a day or
+    /// not in BYMONTH
+    fn next_monthly_date<F: Fn(i32, u32) -> Option<NaiveDate>>(
+        &self,
+        start_date: NaiveDateTime,
+        day_of_month: F,
+    ) -> NaiveDateTime {
+        let interval = self.interval.parse::<i32>().unwrap_or(1).max(1);
+        let time = self.with_initial_time_intervals(start_date).time();
+        let mut month_index = start_date.year() * 12 + start_date.month0() as i32;
+        // a few hundred years is plenty to find a month with the day, anything further
+        // is treated like an unsupported rule
+        for _i in 0..4800 {
+            let (year, month) = (
+                month_index.div_euclid(12),
+                month_index.rem_euclid(12) as u32 + 1,
+            );
+            let in_by_month = self.by_month.is_empty()
+                || self
+                    .by_month
+                    .iter()
+                    .any(|by_month| by_month.parse::<u32>() == Ok(month));
+            if in_by_month {
+                if let Some(day) = day_of_month(year, month) {
+                    let next_date = day.and_time(time);
+                    if next_date > start_date {
+                        return next_date;
+                    }
+                }
             }
+            month_index += interval;
         }
+        start_date
+    }
 
-        // If the calculated next_date is greater than the start date we don't need to add another month
-        let start = if next_date.gt(&start_date) { 1 } else { 0 };
-        for _i in start..interval {
-            next_date = add_month_to_date(next_date);
-        }
+    /// Handles weekly rules with more than one BYDAY, every matching day of the week (starting
+    /// on WKST) is an occurrence and INTERVAL - 1 weeks are skipped after the last of them
+    fn handle_weekly_by_days(&self, start_date: NaiveDateTime) -> NaiveDateTime {
+        let interval = self.interval.parse::<i64>().unwrap_or(1).max(1);
+        let week_start = self.week_start().num_days_from_monday();
+        let days_into_week =
+            |date: NaiveDateTime| (date.weekday().num_days_from_monday() + 7 - week_start) % 7;
 
-        next_date
+        let mut next_date = self.with_initial_time_intervals(start_date);
+        if next_date > start_date && self.matches_by_day(next_date.weekday()) {
+            return next_date;
+        }
+        loop {
+            let previous_days_into_week = days_into_week(next_date);
+            next_date += Duration::days(1);
+            if days_into_week(next_date) < previous_days_into_week {
+                next_date += Duration::weeks(interval - 1);
+            }
+            if self.matches_by_day(next_date.weekday()) {
+                return next_date;
+            }
+        }
     }
 
     /// Handles both weekly and special variants of weekly such as [FREQ=WEEKLY;INTERVAL=2;]
@@ -464,7 +1201,10 @@ impl<'a> RRule<'a> {
     /// if start_date_with_interval > start_date
     ///     check if start_date_with_intervals is on the same day as today
     ///     if yes, don't add and that's our first dat
-    fn handle_weekly(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
+    fn handle_weekly(&self, start_date: NaiveDateTime) -> NaiveDateTime {
+        if self.by_day.len() > 1 {
+            return self.handle_weekly_by_days(start_date);
+        }
         let mut start_date_with_intervals = self.with_initial_time_intervals(start_date);
         // adjust start_date if it does not start on the start
         let by_day = self
@@ -479,22 +1219,22 @@ impl<'a> RRule<'a> {
             && start_date_with_intervals.ordinal() > start_date.ordinal();
         let days_to_adjust =
             self.calculate_weekday_distance(by_day, start_date.weekday(), in_future_day);
-        start_date_with_intervals = start_date_with_intervals + Duration::days(days_to_adjust);
+        start_date_with_intervals += Duration::days(days_to_adjust);
 
         let mut next_date = start_date_with_intervals;
         for _i in 0..interval {
             if start_date_with_intervals.gt(&start_date) {
                 break;
             }
-            next_date = next_date + Duration::days(7);
+            next_date += Duration::days(7);
         }
         let final_days_to_adjust =
             self.calculate_weekday_distance(by_day, next_date.weekday(), false);
-        next_date = next_date + Duration::days(final_days_to_adjust);
+        next_date += Duration::days(final_days_to_adjust);
         next_date
     }
 
-    fn handle_daily(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
+    fn handle_daily(&self, start_date: NaiveDateTime) -> NaiveDateTime {
         let start_date_with_intervals = self.with_initial_time_intervals(start_date);
 
         let by_day = self.by_day.first().unwrap_or(&"").to_owned();
@@ -506,31 +1246,36 @@ impl<'a> RRule<'a> {
         if by_month.is_empty() {
             if by_day.is_empty() {
                 if start_date.eq(&next_date) {
-                    next_date = next_date + Duration::days(1);
+                    next_date += Duration::days(1);
                 }
                 for _i in 1..interval {
-                    next_date = next_date + Duration::days(1);
+                    next_date += Duration::days(1);
                 }
             } else {
                 loop {
                     for _i in 0..interval {
-                        next_date = next_date + Duration::days(1);
+                        next_date += Duration::days(1);
                     }
-                    if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                    if self.matches_by_day(next_date.weekday()) {
                         break;
                     }
                 }
             }
         } else if by_day.is_empty() {
-            for _i in 0..interval {
-                next_date = next_date + Duration::days(1);
+            loop {
+                for _i in 0..interval {
+                    next_date += Duration::days(1);
+                }
+                if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                    break;
+                }
             }
         } else {
             loop {
                 for _i in 0..interval {
-                    next_date = next_date + Duration::days(1);
+                    next_date += Duration::days(1);
                 }
-                if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                if self.matches_by_day(next_date.weekday())
                     && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                 {
                     break;
@@ -541,8 +1286,9 @@ impl<'a> RRule<'a> {
         next_date
     }
 
-    fn handle_hourly(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
-        let mut next_date = self.with_initial_time_intervals(start_date);
+    // expects the initial time intervals to already be applied to the start date
+    fn handle_hourly<D: IterDate>(&self, start_date: D) -> D {
+        let mut next_date = start_date;
         let interval: u32 = self.interval.parse().unwrap_or(1);
 
         let by_hour = self.by_hour.first().unwrap_or(&"").to_owned();
@@ -560,21 +1306,26 @@ impl<'a> RRule<'a> {
                         for _i in 0..interval {
                             next_date = next_date + Duration::hours(1)
                         }
-                        if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                        if self.matches_by_day(next_date.weekday()) {
                             break;
                         }
                     }
                 }
             } else if by_day.is_empty() {
-                for _i in 0..interval {
-                    next_date = next_date + Duration::hours(1)
+                loop {
+                    for _i in 0..interval {
+                        next_date = next_date + Duration::hours(1)
+                    }
+                    if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                        break;
+                    }
                 }
             } else {
                 loop {
                     for _i in 0..interval {
                         next_date = next_date + Duration::hours(1)
                     }
-                    if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                    if self.matches_by_day(next_date.weekday())
                         && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                     {
                         break;
@@ -593,21 +1344,26 @@ impl<'a> RRule<'a> {
                             for _i in 0..interval {
                                 next_date = next_date + Duration::hours(1)
                             }
-                            if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                            if self.matches_by_day(next_date.weekday()) {
                                 break;
                             }
                         }
                     }
                 } else if by_day.is_empty() {
-                    for _i in 0..interval {
-                        next_date = next_date + Duration::hours(1)
+                    loop {
+                        for _i in 0..interval {
+                            next_date = next_date + Duration::hours(1)
+                        }
+                        if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                            break;
+                        }
                     }
                 } else {
                     loop {
                         for _i in 0..interval {
                             next_date = next_date + Duration::hours(1)
                         }
-                        if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                        if self.matches_by_day(next_date.weekday())
                             && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                         {
                             break;
@@ -623,8 +1379,9 @@ impl<'a> RRule<'a> {
         next_date
     }
 
-    fn handle_minutely(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
-        let mut next_date = self.with_initial_time_intervals(start_date);
+    // expects the initial time intervals to already be applied to the start date
+    fn handle_minutely<D: IterDate>(&self, start_date: D) -> D {
+        let mut next_date = start_date;
         let interval: u32 = self.interval.parse().unwrap_or(1);
 
         let by_day = self.by_day.first().unwrap_or(&"").to_owned();
@@ -642,21 +1399,26 @@ impl<'a> RRule<'a> {
                         for _i in 0..interval {
                             next_date = next_date + Duration::minutes(1)
                         }
-                        if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                        if self.matches_by_day(next_date.weekday()) {
                             break;
                         }
                     }
                 }
             } else if by_day.is_empty() {
-                for _i in 0..interval {
-                    next_date = next_date + Duration::minutes(1)
+                loop {
+                    for _i in 0..interval {
+                        next_date = next_date + Duration::minutes(1)
+                    }
+                    if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                        break;
+                    }
                 }
             } else {
                 loop {
                     for _i in 0..interval {
                         next_date = next_date + Duration::minutes(1)
                     }
-                    if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                    if self.matches_by_day(next_date.weekday())
                         && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                     {
                         break;
@@ -675,21 +1437,26 @@ impl<'a> RRule<'a> {
                             for _i in 0..interval {
                                 next_date = next_date + Duration::minutes(1)
                             }
-                            if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                            if self.matches_by_day(next_date.weekday()) {
                                 break;
                             }
                         }
                     }
                 } else if by_day.is_empty() {
-                    for _i in 0..interval {
-                        next_date = next_date + Duration::minutes(1)
+                    loop {
+                        for _i in 0..interval {
+                            next_date = next_date + Duration::minutes(1)
+                        }
+                        if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                            break;
+                        }
                     }
                 } else {
                     loop {
                         for _i in 0..interval {
                             next_date = next_date + Duration::minutes(1)
                         }
-                        if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                        if self.matches_by_day(next_date.weekday())
                             && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                         {
                             break;
@@ -704,8 +1471,9 @@ impl<'a> RRule<'a> {
         next_date
     }
 
-    fn handle_secondly(&self, start_date: DateTime<Tz>) -> DateTime<Tz> {
-        let mut next_date = self.with_initial_time_intervals(start_date);
+    // expects the initial time intervals to already be applied to the start date
+    fn handle_secondly<D: IterDate>(&self, start_date: D) -> D {
+        let mut next_date = start_date;
         let interval: u32 = self.interval.parse().unwrap_or(1);
 
         let by_day = self.by_day.first().unwrap_or(&"").to_owned();
@@ -721,21 +1489,26 @@ impl<'a> RRule<'a> {
                     for _i in 0..interval {
                         next_date = next_date + Duration::seconds(1)
                     }
-                    if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day) {
+                    if self.matches_by_day(next_date.weekday()) {
                         break;
                     }
                 }
             }
         } else if by_day.is_empty() {
-            for _i in 0..interval {
-                next_date = next_date + Duration::seconds(1)
+            loop {
+                for _i in 0..interval {
+                    next_date = next_date + Duration::seconds(1)
+                }
+                if next_date.month().eq(&(by_month.parse::<u32>().unwrap())) {
+                    break;
+                }
             }
         } else {
             loop {
                 for _i in 0..interval {
                     next_date = next_date + Duration::seconds(1)
                 }
-                if chrono_weekday_to_rrule_byday(next_date.weekday()).eq(by_day)
+                if self.matches_by_day(next_date.weekday())
                     && next_date.month().eq(&(by_month.parse::<u32>().unwrap()))
                 {
                     break;
@@ -745,6 +1518,14 @@ impl<'a> RRule<'a> {
         next_date
     }
 
+    /// Whether the weekday is one of the BYDAY weekdays, every day matches without BYDAY
+    fn matches_by_day(&self, weekday: Weekday) -> bool {
+        self.by_day.is_empty()
+            || self
+                .by_day
+                .contains(&chrono_weekday_to_rrule_byday(weekday))
+    }
+
     /// Calculates the weekdays to add based on the given byweekday and current weekday.
     /// Use the `in_future_from_current_day` property to determine whether we should use the
     /// current day or the day in future.
@@ -1027,6 +1808,56 @@ impl<'a> RRule<'a> {
     }
 }
 
+/// The date types rules can be iterated on, either a local wall clock time or a timezone
+/// aware date
+trait IterDate: Datelike + Timelike + Copy + Add<Duration, Output = Self> {}
+
+impl<D: Datelike + Timelike + Copy + Add<Duration, Output = D>> IterDate for D {}
+
+/// Iterator over the occurrences of a rule following a start date. It keeps track of the
+/// local wall clock time separately from the returned dates so occurrences that were
+/// shifted or skipped by the `DstPolicy` don't drift the ones that follow.
+struct IterDates<'r, 'a, T: TimeZone> {
+    rrule: Cow<'r, RRule<'a>>,
+    local_date: NaiveDateTime,
+    date: DateTime<T>,
+}
+
+impl<'r, 'a, T: TimeZone> Iterator for IterDates<'r, 'a, T>
+where
+    T::Offset: Copy,
+{
+    type Item = DateTime<T>;
+
+    fn next(&mut self) -> Option<DateTime<T>> {
+        let timezone = self.date.timezone();
+        if self.rrule.is_sub_daily() {
+            let local_date = self
+                .rrule
+                .with_initial_time_intervals(self.date.naive_local());
+            let start_date =
+                resolve_local(&timezone, &local_date, self.rrule.dst_policy).unwrap_or(self.date);
+            let ahead = local_date > self.date.naive_local();
+            self.date = self.rrule.next_sub_daily_date(start_date, ahead);
+            self.local_date = self.date.naive_local();
+            return Some(self.date);
+        }
+
+        loop {
+            let next_local_date = self.rrule.get_next_local_date(self.local_date);
+            if next_local_date == self.local_date {
+                // unsupported frequency, there is nothing to iterate
+                return None;
+            }
+            self.local_date = next_local_date;
+            if let Some(date) = resolve_local(&timezone, &self.local_date, self.rrule.dst_policy) {
+                self.date = date;
+                return Some(date);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RuleValidationError {
     validation_error_string: String,
@@ -1047,7 +1878,7 @@ impl Error for RuleValidationError {
         "Encountered Rrule validation errors"
     }
 
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         None
     }
 }
@@ -1066,13 +1897,13 @@ impl Error for RuleParseError {
         "encountered parsing errors"
     }
 
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         None
     }
 }
 
 fn chrono_weekday_to_rrule_byday(weekday: Weekday) -> &'static str {
-    return match weekday {
+    match weekday {
         Weekday::Mon => "MO",
         Weekday::Tue => "TU",
         Weekday::Wed => "WE",
@@ -1080,45 +1911,129 @@ fn chrono_weekday_to_rrule_byday(weekday: Weekday) -> &'static str {
         Weekday::Fri => "FR",
         Weekday::Sat => "SA",
         Weekday::Sun => "SU",
+    }
+}
+
+fn rrule_byday_to_chrono_weekday(byday: &str) -> Option<Weekday> {
+    match byday {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Splits a BYDAY value such as `-1FR` into its ordinal and weekday, the ordinal is 0 for
+/// plain weekdays
+fn split_byday(byday: &str) -> Option<(i32, Weekday)> {
+    let split = byday.len().checked_sub(2)?;
+    let weekday = rrule_byday_to_chrono_weekday(byday.get(split..)?)?;
+    let ordinal = match byday.get(..split)? {
+        "" => 0,
+        ordinal => ordinal.trim_start_matches('+').parse().ok()?,
     };
+    Some((ordinal, weekday))
 }
 
-/// Adds a month to a given timezone aware `DateTime` type and takes care of any monthly boundaries
-fn add_month_to_date(date: DateTime<Tz>) -> DateTime<Tz> {
-    let mut date_with_month_added: DateTime<Tz> = date;
-    let year = date.year();
-
-    match date.month() {
-        2 => {
-            // handle leap years
-            if year % 4 == 0 {
-                if year % 100 == 0 {
-                    if year % 400 == 0 {
-                        date_with_month_added = date_with_month_added + Duration::days(29);
-                    } else {
-                        date_with_month_added = date_with_month_added + Duration::days(28);
-                    }
-                } else {
-                    date_with_month_added = date_with_month_added + Duration::days(29);
-                }
-            } else {
-                date_with_month_added = date_with_month_added + Duration::days(28);
-            }
+/// Iterator over the floating local occurrences of a rule following a start date
+struct IterLocalDates<'r, 'a> {
+    rrule: Cow<'r, RRule<'a>>,
+    local_date: NaiveDateTime,
+}
+
+impl<'r, 'a> Iterator for IterLocalDates<'r, 'a> {
+    type Item = NaiveDateTime;
+
+    fn next(&mut self) -> Option<NaiveDateTime> {
+        let next_local_date = if self.rrule.is_sub_daily() {
+            let initial_date = self.rrule.with_initial_time_intervals(self.local_date);
+            self.rrule
+                .next_sub_daily_date(initial_date, initial_date > self.local_date)
+        } else {
+            self.rrule.get_next_local_date(self.local_date)
+        };
+        if next_local_date == self.local_date {
+            // unsupported frequency, there is nothing to iterate
+            return None;
+        }
+        self.local_date = next_local_date;
+        Some(next_local_date)
+    }
+}
+
+/// Iterates the occurrences of several single value rules in order, an occurrence of more
+/// than one of them is yielded once
+struct UnionDates<I: Iterator> {
+    sources: Vec<I>,
+    pending: Vec<Option<I::Item>>,
+    last: Option<I::Item>,
+}
+
+impl<I: Iterator> UnionDates<I> {
+    fn new(mut sources: Vec<I>) -> UnionDates<I> {
+        let pending = sources.iter_mut().map(|source| source.next()).collect();
+        UnionDates {
+            sources,
+            pending,
+            last: None,
         }
-        1 | 3 | 5 | 7 | 8 | 10 | 12 => {
-            date_with_month_added = date_with_month_added + Duration::days(31);
+    }
+}
+
+impl<I: Iterator> Iterator for UnionDates<I>
+where
+    I::Item: Clone + Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            let (index, _) = self
+                .pending
+                .iter()
+                .enumerate()
+                .filter_map(|(index, date)| date.as_ref().map(|date| (index, date)))
+                .min_by(|(_, a), (_, b)| a.cmp(b))?;
+            let date = std::mem::replace(&mut self.pending[index], self.sources[index].next())?;
+            if self.last.as_ref() == Some(&date) {
+                continue;
+            }
+            self.last = Some(date.clone());
+            return Some(date);
         }
-        4 | 6 | 9 | 11 => {
-            date_with_month_added = date_with_month_added + Duration::days(30);
+    }
+}
+
+/// Replaces every rule listing several values of the part picked by `part` with one rule
+/// per value
+fn split_values<'a, F>(rules: Vec<RRule<'a>>, part: F) -> Vec<RRule<'a>>
+where
+    F: for<'r> Fn(&'r mut RRule<'a>) -> &'r mut Vec<&'a str>,
+{
+    let mut split = Vec::with_capacity(rules.len());
+    for mut rrule in rules {
+        let values = part(&mut rrule).clone();
+        if values.len() <= 1 {
+            split.push(rrule);
+            continue;
         }
-        _ => {
-            panic!(
-                "Unrecognised month value when adding month to date {:?}",
-                date
-            );
+        for value in values {
+            let mut single = rrule.clone();
+            *part(&mut single) = vec![value];
+            split.push(single);
         }
     }
-    date_with_month_added
+    split
+}
+
+/// Parses a DTSTART or UNTIL value as stored by `convert_to_rrule` to a local date time,
+/// dropping the timezone abbreviation that DTSTART;TZID values carry
+fn parse_local_date(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date.get(..19).unwrap_or(date), "%Y-%m-%d %H:%M:%S").unwrap()
 }
 
 /// Given a `dates_list` of future iteration dates and a `lens_from_date` to look
@@ -1139,7 +2054,66 @@ fn lens_iter_dates(
     lensed_dates_list
 }
 
-fn convert_datetime_tz_list_to_rfc339(dates_list: Vec<DateTime<Tz>>) -> Vec<String> {
+fn convert_datetime_tz_list_to_timezone(
+    dates_list: Vec<DateTime<Tz>>,
+    timezone: Tz,
+) -> Vec<DateTime<Tz>> {
+    dates_list
+        .iter()
+        .map(|date| date.with_timezone(&timezone))
+        .collect()
+}
+
+/// The canonical form of a numeric or BYDAY rule value, e.g. `9` for `09` and `1MO` for
+/// `+1MO`, from a table of every value the parts can have
+fn static_value(value: &str) -> Option<&'static str> {
+    fn byday(ordinal: i32, weekday: &str) -> String {
+        match ordinal {
+            0 => weekday.to_string(),
+            _ => format!("{}{}", ordinal, weekday),
+        }
+    }
+    static VALUES: OnceLock<BTreeSet<String>> = OnceLock::new();
+    let values = VALUES.get_or_init(|| {
+        let numbers = (-366..=366).map(|number: i32| number.to_string());
+        let weekdays = (-53..=53).flat_map(|ordinal| {
+            ["MO", "TU", "WE", "TH", "FR", "SA", "SU"]
+                .iter()
+                .map(move |weekday| byday(ordinal, weekday))
+        });
+        numbers.chain(weekdays).collect()
+    });
+    let canonical = match value.trim_start_matches('+').parse::<i32>() {
+        Ok(number) => number.to_string(),
+        Err(_) => {
+            let (ordinal, weekday) = split_byday(value)?;
+            byday(ordinal, chrono_weekday_to_rrule_byday(weekday))
+        }
+    };
+    values.get(&canonical).map(String::as_str)
+}
+
+/// Strips leading zeros from numeric rule values, anything else is returned as is
+fn normalize_number(value: &str) -> String {
+    match value.parse::<u32>() {
+        Ok(number) => number.to_string(),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// Formats a date stored on the rule in the rrule date format, in the `form` it was given in
+fn format_rrule_date(date: &str, form: DateForm) -> String {
+    let formatted = parse_local_date(date).format("%Y%m%dT%H%M%S").to_string();
+    match form {
+        DateForm::Utc => formatted + "Z",
+        DateForm::Floating | DateForm::Zoned => formatted,
+    }
+}
+
+fn convert_datetime_tz_list_to_rfc339<T: TimeZone>(dates_list: Vec<DateTime<T>>) -> Vec<String>
+where
+    T::Offset: Display,
+{
     let mut converted_dates: Vec<String> = Vec::new();
     for date in dates_list.iter() {
         converted_dates.push(date.to_rfc3339_opts(SecondsFormat::Secs, false));
@@ -1148,13 +2122,83 @@ fn convert_datetime_tz_list_to_rfc339(dates_list: Vec<DateTime<Tz>>) -> Vec<Stri
 }
 
 /// Converts and rrule string to a rrule struct
-pub fn convert_to_rrule(rrule_string: &str) -> Result<RRule, RuleParseError> {
+pub fn convert_to_rrule(rrule_string: &str) -> Result<RRule<'_>, RuleParseError> {
+    convert_to_rrule_with_provider(rrule_string, &ChronoTzProvider)
+}
+
+/// Same as `convert_to_rrule` but looks the TZID up with `provider`, which allows evaluating
+/// rules in timezones chrono-tz does not know about such as the ones defined by a VTIMEZONE
+/// component. The zone is kept on the rule, the methods giving `DateTime<Tz>` occurrences
+/// give them in UTC for a zone chrono-tz doesn't know while the ISO 8601 ones keep the
+/// zone's UTC offsets.
+///
+/// Example:
+/// ```
+/// use sundial::{convert_to_rrule_with_provider, VTimeZone, VTimeZoneProvider};
+/// let provider = VTimeZoneProvider::new(vec![VTimeZone::parse(
+///     "BEGIN:VTIMEZONE\n\
+///      TZID:Perth\n\
+///      BEGIN:STANDARD\n\
+///      DTSTART:16010101T000000\n\
+///      TZOFFSETFROM:+0800\n\
+///      TZOFFSETTO:+0800\n\
+///      END:STANDARD\n\
+///      END:VTIMEZONE",
+/// )
+/// .unwrap()]);
+/// let rrule = convert_to_rrule_with_provider(
+///     "FREQ=DAILY;COUNT=1;BYHOUR=9;BYMINUTE=0;TZID=Perth;DTSTART=20190401T000000",
+///     &provider,
+/// )
+/// .unwrap();
+/// let dates = rrule.get_all_iter_dates_iso8601("", "");
+/// assert_eq!("2019-04-01T09:00:00+08:00", dates[0]);
+/// ```
+pub fn convert_to_rrule_with_provider<'a>(
+    rrule_string: &'a str,
+    provider: &dyn TimeZoneProvider,
+) -> Result<RRule<'a>, RuleParseError> {
+    let mut rrule_result = parse_rrule(rrule_string)?;
+    match validate_rrule_with_provider(&rrule_result, provider) {
+        Ok(()) => {
+            if !rrule_result.tzid.is_empty() && parse_tzid(&rrule_result.tzid).is_none() {
+                rrule_result.zone = provider.zone(&rrule_result.tzid).map(ProvidedZone::new);
+            }
+            Ok(rrule_result)
+        }
+        Err(err) => {
+            eprintln!("Error encountered: {}", err);
+            Err(RuleParseError)
+        }
+    }
+}
+
+/// Parses an rrule string into a rrule struct without validating it, so syntax errors
+/// can be told apart from validation errors. Fails when part of the string is not
+/// recognised or a DTSTART/UNTIL date is malformed.
+///
+/// Example:
+/// ```
+/// use sundial::{parse_rrule, validate_rrule};
+/// let rrule = parse_rrule("FREQ=DAILY;BYHOUR=25").unwrap();
+/// assert!(validate_rrule(&rrule).is_err());
+/// assert!(parse_rrule("FREQ=DAILY;EVERY=DAY").is_err());
+/// ```
+pub fn parse_rrule(rrule_string: &str) -> Result<RRule<'_>, RuleParseError> {
     let mut rrule_result = RRule::new();
+    let mut dtstart_tzid: Option<String> = None;
 
     let parse_result = RRuleParser::parse(Rule::expr, rrule_string)
-        .expect("unsuccessful parse") // unwrap the parse result
+        .map_err(|_| RuleParseError)?
         .next()
-        .unwrap();
+        .ok_or(RuleParseError)?;
+
+    // the grammar matches the longest valid prefix, anything left over is not a rule part
+    if parse_result.as_str().trim_end_matches(';').len()
+        != rrule_string.trim().trim_end_matches(';').len()
+    {
+        return Err(RuleParseError);
+    }
 
     for line in parse_result.into_inner() {
         match line.as_rule() {
@@ -1164,48 +2208,48 @@ pub fn convert_to_rrule(rrule_string: &str) -> Result<RRule, RuleParseError> {
                 rrule_result.tzid = tz_unparsed;
             }
 
-            Rule::dtstart_expr_with_tz => {
-                // For when dtstart had timezone provided
-                // Todo: Heaps of failure points here, add error handling; actually the whole filed needs it
-                if rrule_result.dtstart.is_empty() {
-                    // only one instance of dtStart is allowed and according to
-                    // the spec any errors should be silently dropped when parsing
-                    let non_validated_dtstart: String =
-                        line.into_inner().next().unwrap().as_str().to_string();
-                    let tz_split: Vec<&str> = non_validated_dtstart.split(':').collect();
-                    if tz_split.len() > 1 {
-                        // we have time zone
-                        let tz = tz_split[0];
-                        let timezone = chrono_tz::Tz::from_str(tz).unwrap();
-                        let naive_date =
-                            NaiveDateTime::parse_from_str(tz_split[1], "%Y%m%dT%H%M%S").unwrap();
-                        rrule_result.dtstart = timezone
-                            .from_local_datetime(&naive_date)
-                            .unwrap()
-                            .to_string();
-                    } else {
-                        panic!("Invalid DTSTART;TZID string {}", non_validated_dtstart)
-                    }
-                }
+            // For when dtstart had timezone provided
+            // only one instance of dtStart is allowed and according to
+            // the spec any errors should be silently dropped when parsing
+            Rule::dtstart_expr_with_tz if rrule_result.dtstart.is_empty() => {
+                let non_validated_dtstart = line.into_inner().next().unwrap().as_str();
+                // like the TZID part the zone is looked up when the rule is validated, so
+                // zones only a TimeZoneProvider knows can be used
+                let (tz, local) = non_validated_dtstart
+                    .split_once(':')
+                    .ok_or(RuleParseError)?;
+                let naive_date = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S")
+                    .map_err(|_| RuleParseError)?;
+                rrule_result.dtstart = match parse_tzid(tz) {
+                    // chrono-tz zones keep their abbreviation after the local time
+                    Some(timezone) => timezone
+                        .from_local_datetime(&naive_date)
+                        .earliest()
+                        .ok_or(RuleParseError)?
+                        .to_string(),
+                    None => naive_date.to_string(),
+                };
+                rrule_result.dtstart_form = DateForm::Zoned;
+                dtstart_tzid = Some(tz.to_string());
             }
 
-            Rule::dtstart_expr_without_tz => {
-                // assume UTC if not provided
-                if rrule_result.dtstart.is_empty() {
-                    let non_validated_dtstart: String =
-                        line.into_inner().next().unwrap().as_str().to_string();
-                    if non_validated_dtstart.contains('Z') {
-                        let naive_date =
-                            NaiveDateTime::parse_from_str(&non_validated_dtstart, "%Y%m%dT%H%M%SZ")
-                                .unwrap();
-                        rrule_result.dtstart = naive_date.to_string();
-                    } else {
-                        // no tzId specified, use UTC
-                        let naive_date =
-                            NaiveDateTime::parse_from_str(&non_validated_dtstart, "%Y%m%dT%H%M%S")
-                                .unwrap();
-                        rrule_result.dtstart = naive_date.to_string();
-                    }
+            // assume UTC if not provided
+            Rule::dtstart_expr_without_tz if rrule_result.dtstart.is_empty() => {
+                let non_validated_dtstart: String =
+                    line.into_inner().next().unwrap().as_str().to_string();
+                if non_validated_dtstart.contains('Z') {
+                    let naive_date =
+                        NaiveDateTime::parse_from_str(&non_validated_dtstart, "%Y%m%dT%H%M%SZ")
+                            .map_err(|_| RuleParseError)?;
+                    rrule_result.dtstart = naive_date.to_string();
+                    rrule_result.dtstart_form = DateForm::Utc;
+                } else {
+                    // no tzId specified, use UTC
+                    let naive_date =
+                        NaiveDateTime::parse_from_str(&non_validated_dtstart, "%Y%m%dT%H%M%S")
+                            .map_err(|_| RuleParseError)?;
+                    rrule_result.dtstart = naive_date.to_string();
+                    rrule_result.dtstart_form = DateForm::Floating;
                 }
             }
 
@@ -1215,13 +2259,15 @@ pub fn convert_to_rrule(rrule_string: &str) -> Result<RRule, RuleParseError> {
                 if non_validated_until.contains('Z') {
                     let naive_date =
                         NaiveDateTime::parse_from_str(&non_validated_until, "%Y%m%dT%H%M%SZ")
-                            .unwrap();
+                            .map_err(|_| RuleParseError)?;
                     rrule_result.until = naive_date.to_string();
+                    rrule_result.until_form = DateForm::Utc;
                 } else {
                     let naive_date =
                         NaiveDateTime::parse_from_str(&non_validated_until, "%Y%m%dT%H%M%S")
-                            .unwrap();
+                            .map_err(|_| RuleParseError)?;
                     rrule_result.until = naive_date.to_string();
+                    rrule_result.until_form = DateForm::Floating;
                 }
             }
 
@@ -1313,24 +2359,39 @@ pub fn convert_to_rrule(rrule_string: &str) -> Result<RRule, RuleParseError> {
             _ => {}
         }
     }
-    match validate_rrule(&rrule_result) {
-        Ok(()) => Ok(rrule_result),
-        Err(err) => {
-            eprintln!("Error encountered: {}", err);
-            Err(RuleParseError)
+
+    // a zoned DTSTART is evaluated in its own timezone, which a TZID part can't contradict
+    if let Some(dtstart_tzid) = dtstart_tzid {
+        if rrule_result.tzid.is_empty() {
+            rrule_result.tzid = dtstart_tzid;
+        } else if rrule_result.tzid != dtstart_tzid {
+            return Err(RuleParseError);
         }
     }
+    Ok(rrule_result)
 }
 
+/// The largest INTERVAL `validate_rrule` accepts
+const MAX_INTERVAL: u32 = 65535;
+
 pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
+    validate_rrule_with_provider(rrule, &ChronoTzProvider)
+}
+
+/// Same as `validate_rrule` but checks the TZID against the timezones known to `provider`
+pub fn validate_rrule_with_provider(
+    rrule: &RRule,
+    provider: &dyn TimeZoneProvider,
+) -> Result<(), RuleValidationError> {
     let mut error_string: String = String::from("");
+    // values too large for a u32 are out of range for every part
     // validate byhour
     if !rrule.by_hour.is_empty()
         && rrule
             .by_hour
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 23))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| x > 23)
     {
         error_string.push_str(&format!(
             "BYHOUR can only be in range 0-23 | Provided value {:?}",
@@ -1343,8 +2404,8 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         && rrule
             .by_minute
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 59))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| x > 59)
     {
         error_string.push_str(
             format!(
@@ -1360,8 +2421,8 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         && rrule
             .by_second
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 60))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| x > 60)
     {
         error_string.push_str(
             format!(
@@ -1376,8 +2437,8 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         && rrule
             .by_month_day
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 31 || x < 1))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| !(1..=31).contains(&x))
     {
         error_string.push_str(
             format!(
@@ -1393,8 +2454,8 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         && rrule
             .by_month
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 12 || x < 1))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| !(1..=12).contains(&x))
     {
         error_string.push_str(
             format!(
@@ -1410,8 +2471,8 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         && rrule
             .by_year_day
             .iter()
-            .map(|x| x.parse::<u32>().unwrap())
-            .any(|x| (x > 366 || x < 1))
+            .map(|x| x.parse::<u32>().unwrap_or(u32::MAX))
+            .any(|x| !(1..=366).contains(&x))
     {
         error_string.push_str(
             format!(
@@ -1422,17 +2483,71 @@ pub fn validate_rrule(rrule: &RRule) -> Result<(), RuleValidationError> {
         );
     }
 
+    // validate byday, ordinals such as -1FR are only iterated for monthly rules
+    let ordinals: Vec<i32> = rrule
+        .by_day
+        .iter()
+        .filter_map(|day| split_byday(day))
+        .map(|(ordinal, _)| ordinal)
+        .filter(|ordinal| *ordinal != 0)
+        .collect();
+    if ordinals
+        .iter()
+        .any(|ordinal| !(1..=5).contains(&ordinal.abs()))
+    {
+        error_string.push_str(&format!(
+            "BYDAY ordinals can only be in range 1-5 or -5 to -1 | Provided value {:?}",
+            rrule.by_day
+        ));
+    } else if !ordinals.is_empty() && (rrule.frequency != "MONTHLY" || rrule.by_day.len() > 1) {
+        error_string.push_str(&format!(
+            "BYDAY ordinals are only supported as the only BYDAY of a MONTHLY rule | Provided value {:?}",
+            rrule.by_day
+        ));
+    }
+
+    // validate count
+    if !rrule.count.is_empty() && rrule.count.parse::<i32>().is_err() {
+        error_string.push_str(&format!(
+            "COUNT can only be in range 0-{} | Provided value {:?}",
+            i32::MAX,
+            rrule.count
+        ));
+    }
+
+    // validate interval, the rule is stepped through one unit of its frequency at a time
+    if !rrule.interval.is_empty()
+        && !(1..=MAX_INTERVAL).contains(&rrule.interval.parse::<u32>().unwrap_or(u32::MAX))
+    {
+        error_string.push_str(&format!(
+            "INTERVAL can only be in range 1-{} | Provided value {:?}",
+            MAX_INTERVAL, rrule.interval
+        ));
+    }
+
     // validate tzid
     if !rrule.tzid.is_empty() {
-        let tz = rrule.tzid.parse::<Tz>();
-        if tz.is_err() {
-            error_string.push_str(
+        match provider.zone(&rrule.tzid) {
+            None => error_string.push_str(
                 format!(
                     "Timezone ID: {:?} is not recognised, please try an IANA recognised tzid",
                     rrule.tzid
                 )
                 .as_ref(),
-            );
+            ),
+            // a zoned DTSTART is a local time, which doesn't exist in a daylight saving gap
+            Some(zone)
+                if rrule.dtstart_form == DateForm::Zoned
+                    && zone.offset_from_local(&parse_local_date(&rrule.dtstart))
+                        == LocalResult::None =>
+            {
+                error_string.push_str(&format!(
+                    "DTSTART {:?} does not exist in timezone {:?}",
+                    format_rrule_date(&rrule.dtstart, rrule.dtstart_form),
+                    rrule.tzid
+                ))
+            }
+            Some(_) => {}
         }
     }
 
@@ -3,32 +3,1064 @@ extern crate clap;
 #[macro_use]
 extern crate human_panic;
 
-use clap::App;
-use sundial::get_all_iter_dates;
-use sundial::get_all_iter_dates_from_today;
+mod commands;
+
+use crate::commands::cal::{self, Highlight};
+use crate::commands::format::{self, OutputFormat};
+use crate::commands::repl::{self, Session};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    SecondsFormat, Utc,
+};
+use chrono_tz::Tz;
+use clap::{App, ArgMatches};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::thread;
+use sundial::{
+    diff, find_conflicts, format_ics_duration, free_busy, merge, next_free_slot, parse_rrule,
+    validate_rrule, CutoffOffset, DerivedSchedule, DiffWindow, Event, EventLength,
+    FileHolidayCalendar, HolidayCalendar, InMemoryHolidayCalendar, MergeOptions, OccurrenceSpan,
+    Period, RRule, ShiftPolicy, SlotConstraints,
+};
+
+/// Errors reported by the cli, each kind exits with its own status code so scripts
+/// can tell a bad rule from a failure while evaluating it
+#[derive(Debug)]
+enum CliError {
+    /// The rrule string could not be parsed
+    Parse(String),
+    /// The rrule or one of the arguments is invalid
+    Validation(String),
+    /// Anything that went wrong while evaluating the rule or writing the results
+    Runtime(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Parse(_) => 2,
+            CliError::Validation(_) => 3,
+            CliError::Runtime(_) => 4,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            CliError::Parse(message)
+            | CliError::Validation(message)
+            | CliError::Runtime(message) => message,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let (kind, message) = match self {
+            CliError::Parse(message) => ("parse", message),
+            CliError::Validation(message) => ("validation", message),
+            CliError::Runtime(message) => ("runtime", message),
+        };
+        json!({ "error": { "kind": kind, "message": message } })
+    }
+}
+
+/// What a command prints on success
+enum Output {
+    Json(Value),
+    Text(String),
+    /// The command already wrote its output
+    None,
+}
 
 fn main() {
     setup_panic!();
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
-    let rrule = matches.value_of("rrule").unwrap_or("");
 
-    if rrule.is_empty() {
-        panic!("rrule string cannot be empty, use -h argument to view help");
+    // errors are returned by the commands, catching a panic is only a last resort so a bug
+    // still ends in a JSON error instead of a crash report
+    let result = catch_runtime_error(|| match matches.subcommand() {
+        ("parse", Some(sub_matches)) => parse(sub_matches),
+        ("validate", Some(sub_matches)) => validate(sub_matches),
+        ("expand", Some(sub_matches)) => expand(sub_matches),
+        ("normalize", Some(sub_matches)) => normalize(sub_matches),
+        ("cal", Some(sub_matches)) => cal(sub_matches),
+        ("merge", Some(sub_matches)) => merge_rrules(sub_matches),
+        ("diff", Some(sub_matches)) => diff_rrules(sub_matches),
+        ("spans", Some(sub_matches)) => spans(sub_matches),
+        ("conflicts", Some(sub_matches)) => conflicts(sub_matches),
+        ("freebusy", Some(sub_matches)) => free_busy_periods(sub_matches),
+        ("next-slot", Some(sub_matches)) => next_slot(sub_matches),
+        ("cutoffs", Some(sub_matches)) => cutoffs(sub_matches),
+        ("repl", Some(sub_matches)) => run_repl(sub_matches),
+        ("explain", Some(sub_matches)) => explain(sub_matches),
+        ("from-text", Some(sub_matches)) => from_text(sub_matches),
+        ("from-cron", Some(sub_matches)) => from_cron(sub_matches),
+        ("to-cron", Some(sub_matches)) => to_cron(sub_matches),
+        ("from-oncalendar", Some(sub_matches)) => from_on_calendar(sub_matches),
+        ("to-oncalendar", Some(sub_matches)) => to_on_calendar(sub_matches),
+        // without a subcommand we expand the rule given to sundial itself
+        _ => expand(&matches),
+    })
+    .and_then(|result| result);
+    let pretty = matches.is_present("pretty")
+        || matches
+            .subcommand_matches(matches.subcommand_name().unwrap_or(""))
+            .is_some_and(|sub_matches| sub_matches.is_present("pretty"));
+    if let Err(err) = result.and_then(|output| print_output(&output, pretty)) {
+        eprintln!("{}", err.to_json());
+        process::exit(err.exit_code());
+    }
+}
+
+fn parse(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    Ok(Output::Text(rrule.to_json_pretty()))
+}
+
+fn validate(matches: &ArgMatches) -> Result<Output, CliError> {
+    parse_and_validate(rrule_argument(matches)?)?;
+    Ok(Output::Json(json!({ "valid": true })))
+}
+
+fn normalize(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    Ok(Output::Text(rrule.to_rrule_string()))
+}
+
+fn explain(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    Ok(Output::Text(rrule.to_text()))
+}
+
+fn from_text(matches: &ArgMatches) -> Result<Output, CliError> {
+    let text = matches
+        .values_of("text")
+        .map(|words| words.collect::<Vec<&str>>().join(" "))
+        .unwrap_or_default();
+    let rrule = RRule::from_text(&text).map_err(|err| CliError::Parse(err.to_string()))?;
+    Ok(Output::Text(rrule.to_rrule_string()))
+}
+
+fn from_cron(matches: &ArgMatches) -> Result<Output, CliError> {
+    let expression = matches
+        .values_of("expression")
+        .map(|fields| fields.collect::<Vec<&str>>().join(" "))
+        .unwrap_or_default();
+    let rrule = RRule::from_cron(&expression, matches.value_of("tz").unwrap_or(""))
+        .map_err(|err| CliError::Parse(err.to_string()))?;
+    Ok(Output::Text(rrule.to_rrule_string()))
+}
+
+fn to_cron(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    let expression = rrule
+        .to_cron()
+        .map_err(|err| CliError::Validation(err.to_string()))?;
+    Ok(Output::Text(expression))
+}
+
+fn from_on_calendar(matches: &ArgMatches) -> Result<Output, CliError> {
+    let expression = matches
+        .values_of("expression")
+        .map(|parts| parts.collect::<Vec<&str>>().join(" "))
+        .unwrap_or_default();
+    let rrule =
+        RRule::from_on_calendar(&expression).map_err(|err| CliError::Parse(err.to_string()))?;
+    Ok(Output::Text(rrule.to_rrule_string()))
+}
+
+fn to_on_calendar(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    let expression = rrule
+        .to_on_calendar()
+        .map_err(|err| CliError::Validation(err.to_string()))?;
+    Ok(Output::Text(expression))
+}
+
+fn cal(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    let months = match matches.value_of("months") {
+        Some(months) => months
+            .parse::<u32>()
+            .ok()
+            .filter(|months| (1..=120).contains(months))
+            .ok_or_else(|| {
+                CliError::Validation(format!("months {} is not a number from 1 to 120", months))
+            })?,
+        None => 3,
+    };
+    let output_tz = match matches.value_of("output-tz") {
+        Some(tz) => Some(parse_output_tz(tz)?),
+        None => None,
+    };
+    let local_date = |date: &DateTime<Tz>| match output_tz {
+        Some(output_tz) => date.with_timezone(&output_tz).date().naive_local(),
+        None => date.date().naive_local(),
+    };
+
+    let start = match matches.value_of("start") {
+        Some(start) => NaiveDate::parse_from_str(&format!("{}-01", start), "%Y-%m-%d")
+            .map_err(|_| CliError::Validation(format!("start {} is not a YYYY-MM month", start)))?,
+        // start at the month of the first occurrence
+        None => rrule
+            .get_all_iter_dates("1", "")
+            .first()
+            .map(local_date)
+            .unwrap_or_else(|| Utc::today().naive_utc()),
+    };
+    let start = start.with_day(1).unwrap();
+    let end = cal::add_months(start, months);
+
+    // widen the window by a day on both ends as the grid is in local dates
+    let after = DateTime::from_utc(start.pred().and_hms(0, 0, 0), Utc);
+    let before = DateTime::from_utc(end.succ().and_hms(0, 0, 0), Utc);
+    let occurrences: HashSet<NaiveDate> = rrule
+        .get_all_iter_dates_between("100000", "", Some(after), Some(before))
+        .iter()
+        .map(local_date)
+        .collect();
+
+    let highlight = if io::stdout().is_terminal() {
+        Highlight::Ansi
+    } else {
+        Highlight::Marker
+    };
+    Ok(Output::Text(cal::render_months(
+        start,
+        months,
+        rrule.week_start(),
+        &occurrences,
+        highlight,
+    )))
+}
+
+fn merge_rrules(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrules = matches
+        .values_of("rrule")
+        .into_iter()
+        .flatten()
+        .map(parse_and_validate)
+        .collect::<Result<Vec<RRule>, CliError>>()?;
+    let ids = source_ids(matches, rrules.len())?;
+    let count = match matches.value_of("count") {
+        Some(count) => count
+            .parse::<usize>()
+            .map_err(|_| CliError::Validation(format!("count {} is not a number", count)))?,
+        None => 52,
+    };
+    let after = match matches.value_of("after") {
+        Some(after) => Some(DateTime::from_utc(parse_utc_date("after", after)?, Utc)),
+        None => None,
+    };
+    let before = match matches.value_of("before") {
+        Some(before) => Some(DateTime::from_utc(parse_utc_date("before", before)?, Utc)),
+        None => None,
+    };
+    let options = MergeOptions {
+        dedup: matches.is_present("dedup"),
+        timezone: match matches.value_of("output-tz") {
+            Some(tz) => Some(parse_output_tz(tz)?),
+            None => None,
+        },
+    };
+
+    let sources = ids
+        .iter()
+        .zip(rrules.iter())
+        .map(|(id, rrule)| (id.as_str(), rrule.iter_dates_after(after)))
+        .collect();
+    let dates = merge(sources, options)
+        .take_while(|(_, date)| before.is_none_or(|before| date.with_timezone(&Utc) < before))
+        .take(count)
+        .map(|(source, date)| {
+            json!({
+                "source": source,
+                "date": date.to_rfc3339_opts(SecondsFormat::Secs, false),
+            })
+        })
+        .collect::<Vec<Value>>();
+    Ok(Output::Json(Value::Array(dates)))
+}
+
+/// The `--id` of each `--rrule`, their positions from 1 when there are none
+fn source_ids(matches: &ArgMatches, rrules: usize) -> Result<Vec<String>, CliError> {
+    let ids: Vec<String> = match matches.values_of("id") {
+        Some(ids) => ids.map(String::from).collect(),
+        None => (1..=rrules).map(|id| id.to_string()).collect(),
+    };
+    if ids.len() != rrules {
+        return Err(CliError::Validation(format!(
+            "got {} ids for {} rrules, give one --id per --rrule",
+            ids.len(),
+            rrules
+        )));
+    }
+    Ok(ids)
+}
+
+fn diff_rrules(matches: &ArgMatches) -> Result<Output, CliError> {
+    let old = parse_and_validate(matches.value_of("old").unwrap_or(""))?;
+    let new = parse_and_validate(matches.value_of("new").unwrap_or(""))?;
+    let after = match matches.value_of("after") {
+        Some(after) => DateTime::from_utc(parse_utc_date("after", after)?, Utc),
+        None => Utc::now(),
+    };
+    let before = DateTime::from_utc(
+        parse_utc_date("before", matches.value_of("before").unwrap_or(""))?,
+        Utc,
+    );
+    if before <= after {
+        return Err(CliError::Validation(String::from(
+            "before has to be later than after",
+        )));
+    }
+    let mut window = DiffWindow::new(after, before);
+    if let Some(max_move) = matches.value_of("max-move") {
+        window.max_move = parse_duration(max_move)?;
+    }
+
+    let changes = diff(&old, &new, window);
+    let format_date = |date: &DateTime<Tz>| date.to_rfc3339_opts(SecondsFormat::Secs, false);
+    Ok(Output::Json(json!({
+        "parts": changes
+            .parts
+            .iter()
+            .map(|change| json!({ "part": change.part, "old": change.old, "new": change.new }))
+            .collect::<Vec<Value>>(),
+        "added": changes.added.iter().map(format_date).collect::<Vec<String>>(),
+        "removed": changes.removed.iter().map(format_date).collect::<Vec<String>>(),
+        "moved": changes
+            .moved
+            .iter()
+            .map(|moved| json!({ "from": format_date(&moved.from), "to": format_date(&moved.to) }))
+            .collect::<Vec<Value>>(),
+    })))
+}
+
+fn spans(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    let event = match (matches.value_of("duration"), matches.value_of("dtend")) {
+        (Some(duration), _) => Event::with_duration(&rrule, duration),
+        (None, dtend) => Event::with_end(&rrule, dtend.unwrap_or("")),
+    }
+    .map_err(|err| CliError::Validation(err.to_string()))?;
+    let format_span = |span: OccurrenceSpan| {
+        json!({
+            "start": span.start.to_rfc3339_opts(SecondsFormat::Secs, false),
+            "end": span.end.to_rfc3339_opts(SecondsFormat::Secs, false),
+        })
+    };
+
+    if let Some(at) = matches.value_of("at") {
+        let at = DateTime::from_utc(parse_utc_date("at", at)?, Utc);
+        let span = event.span_at(at);
+        return Ok(Output::Json(span.map_or(Value::Null, format_span)));
+    }
+    let count = match matches.value_of("count") {
+        Some(count) => count
+            .parse::<usize>()
+            .map_err(|_| CliError::Validation(format!("count {} is not a number", count)))?,
+        None => 52,
+    };
+    let after = match matches.value_of("after") {
+        Some(after) => Some(DateTime::from_utc(parse_utc_date("after", after)?, Utc)),
+        None => None,
+    };
+    let spans = match matches.value_of("before") {
+        Some(before) => {
+            let before = DateTime::from_utc(parse_utc_date("before", before)?, Utc);
+            let after = after.unwrap_or_else(Utc::now);
+            event.spans_between(after, before)
+        }
+        None => event.iter_spans_after(after).take(count).collect(),
+    };
+    Ok(Output::Json(Value::Array(
+        spans.into_iter().take(count).map(format_span).collect(),
+    )))
+}
+
+fn conflicts(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrules = matches
+        .values_of("rrule")
+        .into_iter()
+        .flatten()
+        .map(parse_and_validate)
+        .collect::<Result<Vec<RRule>, CliError>>()?;
+    let ids = source_ids(matches, rrules.len())?;
+    let after = match matches.value_of("after") {
+        Some(after) => DateTime::from_utc(parse_utc_date("after", after)?, Utc),
+        None => Utc::now(),
+    };
+    let before = DateTime::from_utc(
+        parse_utc_date("before", matches.value_of("before").unwrap_or(""))?,
+        Utc,
+    );
+
+    let events: Vec<(&str, Event)> = ids
+        .iter()
+        .map(String::as_str)
+        .zip(duration_events(matches, &rrules)?)
+        .collect();
+    let conflicts = find_conflicts(&events, after, before);
+    let format_occurrence = |(source, span): &(&str, OccurrenceSpan)| {
+        json!({
+            "source": source,
+            "start": span.start.to_rfc3339_opts(SecondsFormat::Secs, false),
+            "end": span.end.to_rfc3339_opts(SecondsFormat::Secs, false),
+        })
+    };
+    Ok(Output::Json(Value::Array(
+        conflicts
+            .iter()
+            .map(|conflict| {
+                json!({
+                    "first": format_occurrence(&conflict.first),
+                    "second": format_occurrence(&conflict.second),
+                    "overlap": format_ics_duration(conflict.overlap),
+                })
+            })
+            .collect(),
+    )))
+}
+
+/// The `--rrule` rules as events lasting their `--duration`, given once for all of them or
+/// once per rule
+fn duration_events<'r, 'a>(
+    matches: &ArgMatches,
+    rrules: &'r [RRule<'a>],
+) -> Result<Vec<Event<'r, 'a>>, CliError> {
+    let durations: Vec<&str> = matches
+        .values_of("duration")
+        .into_iter()
+        .flatten()
+        .collect();
+    if durations.len() != 1 && durations.len() != rrules.len() {
+        return Err(CliError::Validation(format!(
+            "got {} durations for {} rrules, give one --duration for all of them or one per --rrule",
+            durations.len(),
+            rrules.len()
+        )));
+    }
+    rrules
+        .iter()
+        .zip(durations.iter().cycle())
+        .map(|(rrule, duration)| {
+            Event::with_duration(rrule, duration)
+                .map_err(|err| CliError::Validation(err.to_string()))
+        })
+        .collect()
+}
+
+fn free_busy_periods(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrules = matches
+        .values_of("rrule")
+        .into_iter()
+        .flatten()
+        .map(parse_and_validate)
+        .collect::<Result<Vec<RRule>, CliError>>()?;
+    let events = duration_events(matches, &rrules)?;
+    let after = match matches.value_of("after") {
+        Some(after) => DateTime::from_utc(parse_utc_date("after", after)?, Utc),
+        None => Utc::now(),
+    };
+    let before = DateTime::from_utc(
+        parse_utc_date("before", matches.value_of("before").unwrap_or(""))?,
+        Utc,
+    );
+    if before <= after {
+        return Err(CliError::Validation(String::from(
+            "before has to be later than after",
+        )));
+    }
+
+    let periods = free_busy(&events, after, before);
+    if matches.is_present("ics") {
+        return Ok(Output::Text(periods.to_ics(Utc::now())));
+    }
+    let format_periods = |periods: &[Period]| {
+        periods
+            .iter()
+            .map(|period| {
+                json!({
+                    "start": period.start.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "end": period.end.to_rfc3339_opts(SecondsFormat::Secs, true),
+                })
+            })
+            .collect::<Vec<Value>>()
+    };
+    Ok(Output::Json(json!({
+        "busy": format_periods(&periods.busy),
+        "free": format_periods(&periods.free),
+    })))
+}
+
+fn next_slot(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrules = matches
+        .values_of("rrule")
+        .into_iter()
+        .flatten()
+        .map(parse_and_validate)
+        .collect::<Result<Vec<RRule>, CliError>>()?;
+    let events = duration_events(matches, &rrules)?;
+    let length = match EventLength::parse_duration(matches.value_of("length").unwrap_or("")) {
+        Ok(EventLength::Duration { days, time }) => Duration::days(days) + time,
+        Ok(EventLength::WallClock(length)) => length,
+        Err(err) => return Err(CliError::Validation(err.to_string())),
+    };
+    let working_rrule = match matches.value_of("working-hours") {
+        Some(working_hours) => Some(parse_and_validate(working_hours)?),
+        None => None,
+    };
+    let working_hours = match working_rrule.as_ref() {
+        Some(rrule) => Some(
+            Event::with_duration(rrule, matches.value_of("working-duration").unwrap_or(""))
+                .map_err(|err| CliError::Validation(err.to_string()))?,
+        ),
+        None => None,
+    };
+    let after = match matches.value_of("after") {
+        Some(after) => DateTime::from_utc(parse_utc_date("after", after)?, Utc),
+        None => Utc::now(),
+    };
+    let before = match matches.value_of("before") {
+        Some(before) => Some(DateTime::from_utc(parse_utc_date("before", before)?, Utc)),
+        None => None,
+    };
+    let output_tz = match matches.value_of("output-tz") {
+        Some(tz) => parse_output_tz(tz)?,
+        None => Tz::UTC,
+    };
+
+    let constraints = SlotConstraints {
+        working_hours: working_hours.as_ref(),
+        before,
+    };
+    let slot = next_free_slot(&events, after, length, constraints);
+    Ok(Output::Json(slot.map_or(Value::Null, |slot| {
+        json!({
+            "start": slot.start.with_timezone(&output_tz).to_rfc3339_opts(SecondsFormat::Secs, false),
+            "end": slot.end.with_timezone(&output_tz).to_rfc3339_opts(SecondsFormat::Secs, false),
+        })
+    })))
+}
+
+fn cutoffs(matches: &ArgMatches) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_argument(matches)?)?;
+    let calendar: Box<dyn HolidayCalendar> = match matches.value_of("holidays") {
+        Some(path) => Box::new(
+            FileHolidayCalendar::open(path, matches.value_of("region"))
+                .map_err(|err| CliError::Validation(err.to_string()))?,
+        ),
+        None => Box::new(InMemoryHolidayCalendar::default()),
+    };
+    let cutoff_rrule = match matches.value_of("cutoff-rrule") {
+        Some(cutoff_rrule) => Some(parse_and_validate(cutoff_rrule)?),
+        None => None,
+    };
+    let offset = match (
+        &cutoff_rrule,
+        matches.value_of("business-days"),
+        matches.value_of("offset"),
+    ) {
+        (Some(cutoff_rrule), _, _) => CutoffOffset::PreviousOccurrence(cutoff_rrule),
+        (None, Some(days), _) => CutoffOffset::BusinessDays {
+            days: days.parse::<u32>().map_err(|_| {
+                CliError::Validation(format!("business days {} is not a number", days))
+            })?,
+            time: parse_time_of_day(matches.value_of("at").unwrap_or(""))?,
+            calendar: calendar.as_ref(),
+        },
+        (None, None, offset) => CutoffOffset::Duration(parse_duration(offset.unwrap_or(""))?),
+    };
+    let schedule = DerivedSchedule::new(&rrule, offset);
+    let pair_json = |(cutoff, delivery): (DateTime<Tz>, DateTime<Tz>)| {
+        json!({
+            "cutoff": cutoff.to_rfc3339_opts(SecondsFormat::Secs, false),
+            "delivery": delivery.to_rfc3339_opts(SecondsFormat::Secs, false),
+        })
+    };
+
+    if let Some(order_at) = matches.value_of("order-at") {
+        let placed_at = if order_at == "now" {
+            Utc::now()
+        } else {
+            DateTime::from_utc(parse_utc_date("order-at", order_at)?, Utc)
+        };
+        let pair = schedule.delivery_for_order(placed_at).ok_or_else(|| {
+            CliError::Validation(format!(
+                "there is no delivery with a cutoff after {}",
+                placed_at
+            ))
+        })?;
+        return Ok(Output::Json(pair_json(pair)));
     }
+    let count = match matches.value_of("count") {
+        Some(count) => count
+            .parse::<u32>()
+            .map(|_| count)
+            .map_err(|_| CliError::Validation(format!("count {} is not a number", count)))?,
+        None => "",
+    };
+    let after = match matches.value_of("after") {
+        Some(after) => Some(DateTime::from_utc(parse_utc_date("after", after)?, Utc)),
+        None => None,
+    };
+    let pairs = schedule.get_all_cutoffs_between(count, after, None);
+    Ok(Output::Json(Value::Array(
+        pairs.into_iter().map(pair_json).collect(),
+    )))
+}
+
+/// Parses a duration such as `36h`, `2d` or `1d12h30m`, made of numbers followed by `w`,
+/// `d`, `h`, `m` or `s`
+fn parse_duration(duration: &str) -> Result<Duration, CliError> {
+    let invalid = || {
+        CliError::Validation(format!(
+            "duration {} is not made of numbers followed by w, d, h, m or s, e.g. 1d12h",
+            duration
+        ))
+    };
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value = number.parse::<i64>().map_err(|_| invalid())?;
+        total = total
+            + match c {
+                'w' => Duration::weeks(value),
+                'd' => Duration::days(value),
+                'h' => Duration::hours(value),
+                'm' => Duration::minutes(value),
+                's' => Duration::seconds(value),
+                _ => return Err(invalid()),
+            };
+        number.clear();
+    }
+    if !number.is_empty() || duration.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// Parses a local time of day given as `HH:MM` or `HH:MM:SS`
+fn parse_time_of_day(time: &str) -> Result<NaiveTime, CliError> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .map_err(|_| CliError::Validation(format!("time {} is not a HH:MM time of day", time)))
+}
+
+fn run_repl(matches: &ArgMatches) -> Result<Output, CliError> {
+    let count = match matches.value_of("count") {
+        Some(count) => count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| (1..=repl::MAX_COUNT).contains(count))
+            .ok_or_else(|| {
+                CliError::Validation(format!(
+                    "count {} is not a number from 1 to {}",
+                    count,
+                    repl::MAX_COUNT
+                ))
+            })?,
+        None => 5,
+    };
+    let stdin = io::stdin();
+    // the prompt and greeting would only clutter piped output
+    let interactive = stdin.is_terminal();
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    let write_error = |err: io::Error| CliError::Runtime(err.to_string());
+    let mut session = Session::new(count);
+
+    if interactive {
+        writeln!(handle, "type a rule or help for the commands").map_err(write_error)?;
+    }
+    let mut lines = matches.value_of("rrule").map(String::from).into_iter();
+    loop {
+        let line = match lines.next() {
+            Some(line) => line,
+            None => {
+                if interactive {
+                    write!(handle, "sundial> ").map_err(write_error)?;
+                    handle.flush().map_err(write_error)?;
+                }
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line).map_err(write_error)? == 0 {
+                    break;
+                }
+                line
+            }
+        };
+        // a rule that panics while iterating is reported and the session goes on
+        let reply = catch_panic(|| session.execute(&line))
+            .unwrap_or_else(|err| Some(format!("error: {}", err.message())));
+        match reply {
+            Some(reply) if reply.is_empty() => {}
+            Some(reply) => writeln!(handle, "{}", reply).map_err(write_error)?,
+            None => break,
+        }
+    }
+    Ok(Output::None)
+}
+
+fn parse_output_tz(tz: &str) -> Result<Tz, CliError> {
+    tz.parse::<Tz>().map_err(|_| {
+        CliError::Validation(format!(
+            "output timezone {} is not a recognised IANA tzid",
+            tz
+        ))
+    })
+}
+
+/// The expand arguments that apply to every rule being expanded
+struct ExpandOptions<'m> {
+    count: &'m str,
+    until: String,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    cutoff: bool,
+    output_tz: Option<Tz>,
+    /// The calendar occurrences are moved off holidays and weekends with
+    holidays: Option<(FileHolidayCalendar, ShiftPolicy)>,
+}
+
+impl<'m> ExpandOptions<'m> {
+    fn from_matches(matches: &'m ArgMatches) -> Result<ExpandOptions<'m>, CliError> {
+        let count = match matches.value_of("count") {
+            Some(count) => count
+                .parse::<u32>()
+                .map(|_| count)
+                .map_err(|_| CliError::Validation(format!("count {} is not a number", count)))?,
+            None => "",
+        };
+        let until = match matches.value_of("until") {
+            Some(until) => parse_utc_date("until", until)?.to_string(),
+            None => String::new(),
+        };
+        let after = match matches.value_of("after") {
+            Some(after) => Some(DateTime::from_utc(parse_utc_date("after", after)?, Utc)),
+            None => None,
+        };
+        let before = match matches.value_of("before") {
+            Some(before) => Some(DateTime::from_utc(parse_utc_date("before", before)?, Utc)),
+            None => None,
+        };
+        let output_tz = match matches.value_of("output-tz") {
+            Some(tz) => Some(parse_output_tz(tz)?),
+            None => None,
+        };
+        let holidays = match matches.value_of("holidays") {
+            Some(path) => {
+                let calendar = FileHolidayCalendar::open(path, matches.value_of("region"))
+                    .map_err(|err| CliError::Validation(err.to_string()))?;
+                let policy = matches
+                    .value_of("shift")
+                    .unwrap_or("next")
+                    .parse::<ShiftPolicy>()
+                    .map_err(CliError::Validation)?;
+                Some((calendar, policy))
+            }
+            None => None,
+        };
+        Ok(ExpandOptions {
+            count,
+            until,
+            after,
+            before,
+            cutoff: matches.is_present("cutoff"),
+            output_tz,
+            holidays,
+        })
+    }
+}
+
+fn expand(matches: &ArgMatches) -> Result<Output, CliError> {
+    let options = ExpandOptions::from_matches(matches)?;
+    if matches.is_present("batch") {
+        return expand_batch(matches, &options);
+    }
+    let format = OutputFormat::from_name(matches.value_of("format").unwrap_or("json"))
+        .unwrap_or(OutputFormat::Json);
+    if format == OutputFormat::Ics && matches.is_present("ics-rrule") {
+        return expand_ics_rrule(rrule_argument(matches)?);
+    }
+    let rrule_dates = expand_rrule(rrule_argument(matches)?, &options, options.count)?;
+    if format == OutputFormat::Json {
+        return Ok(Output::Json(json!(rrule_dates)));
+    }
+
+    let dates = rrule_dates
+        .iter()
+        .map(|date| DateTime::parse_from_rfc3339(date))
+        .collect::<Result<Vec<DateTime<FixedOffset>>, _>>()
+        .map_err(|err| CliError::Runtime(err.to_string()))?;
+    Ok(Output::Text(match format {
+        OutputFormat::Csv => format::render_csv(&dates),
+        OutputFormat::Lines => format::render_lines(&dates),
+        OutputFormat::Table => format::render_table(&dates),
+        _ => format::render_ics_events(&dates, Utc::now()),
+    }))
+}
 
-    let count = matches.value_of("count").unwrap_or("");
-    let interval = matches.value_of("until").unwrap_or("");
-    let cutoff = matches.is_present("cutoff");
-    let rrule_dates = if cutoff {
-        get_all_iter_dates_from_today(rrule, count, interval)
+/// Prints a VCALENDAR with a single VEVENT carrying the rule instead of its occurrences
+fn expand_ics_rrule(rrule_string: &str) -> Result<Output, CliError> {
+    let rrule = parse_and_validate(rrule_string)?;
+    let first = rrule
+        .get_all_iter_dates("1", "")
+        .first()
+        .map(|first| first.with_timezone(&first.offset().fix()))
+        .ok_or_else(|| CliError::Validation(String::from("the rrule has no occurrences")))?;
+
+    // TZID and DTSTART are their own property in a VEVENT
+    Ok(Output::Text(format::render_ics_rrule(
+        &first,
+        rrule.tzid(),
+        &rrule.to_ics_rrule(),
+        Utc::now(),
+    )))
+}
+
+/// Expands a single rule string into its iter dates, `count` overrides the COUNT part
+fn expand_rrule(
+    rrule_string: &str,
+    options: &ExpandOptions,
+    count: &str,
+) -> Result<Vec<String>, CliError> {
+    let rrule = match options.output_tz {
+        Some(output_tz) => parse_and_validate(rrule_string)?.with_output_timezone(output_tz),
+        None => parse_and_validate(rrule_string)?,
+    };
+    let until = &options.until;
+    if options.after.is_some() || options.before.is_some() || options.holidays.is_some() {
+        // the cutoff moves the start of the window up to today
+        let after = match (options.after, options.cutoff) {
+            (Some(after), true) => Some(after.max(Utc::now())),
+            (None, true) => Some(Utc::now()),
+            (after, false) => after,
+        };
+        let dates = match &options.holidays {
+            Some((calendar, policy)) => rrule
+                .shifted(calendar, *policy)
+                .get_all_iter_dates_between(count, until, after, options.before),
+            None => rrule.get_all_iter_dates_between(count, until, after, options.before),
+        };
+        Ok(dates
+            .iter()
+            .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, false))
+            .collect())
+    } else if options.cutoff {
+        Ok(rrule.get_all_iter_dates_from_today_iso8601(count, until))
     } else {
-        get_all_iter_dates(rrule, count, interval)
+        Ok(rrule.get_all_iter_dates_iso8601(count, until))
+    }
+}
+
+/// A line of batch input given as a JSON object
+#[derive(Deserialize)]
+struct BatchRule {
+    #[serde(default)]
+    id: Option<Value>,
+    rrule: String,
+    #[serde(default)]
+    count: Option<u32>,
+}
+
+/// Expands every line of the batch input, either a plain rrule string or a JSON object
+/// with `id`, `rrule` and an optional `count`, and writes one NDJSON line per input line.
+/// Errors for a single line are written inline so the rest of the batch still runs.
+fn expand_batch(matches: &ArgMatches, options: &ExpandOptions) -> Result<Output, CliError> {
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => jobs
+            .parse::<usize>()
+            .ok()
+            .filter(|jobs| *jobs > 0)
+            .ok_or_else(|| CliError::Validation(format!("jobs {} is not a number", jobs)))?,
+        None => 1,
     };
-    match rrule_dates {
-        Ok(rrule) => {
-            println!("{:?}", rrule);
+    let input: Box<dyn BufRead> = match matches.value_of("batch") {
+        None | Some("-") => Box::new(BufReader::new(io::stdin())),
+        Some(path) => Box::new(BufReader::new(File::open(path).map_err(|err| {
+            CliError::Runtime(format!("could not open batch file {}: {}", path, err))
+        })?)),
+    };
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    let write_error = |err: io::Error| CliError::Runtime(err.to_string());
+
+    if jobs == 1 {
+        // stream the results so long running batches can be piped
+        for (index, line) in input.lines().enumerate() {
+            let line = line.map_err(write_error)?;
+            if let Some(result) = expand_batch_line(index + 1, &line, options) {
+                write_batch_result(&mut handle, &result)?;
+            }
+        }
+    } else {
+        let lines = input
+            .lines()
+            .collect::<Result<Vec<String>, io::Error>>()
+            .map_err(write_error)?;
+        let chunk_size = lines.len().div_ceil(jobs).max(1);
+        let results: Vec<Option<BatchResult>> = thread::scope(|scope| {
+            let workers: Vec<_> = lines
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(chunk, chunk_lines)| {
+                    scope.spawn(move || {
+                        chunk_lines
+                            .iter()
+                            .enumerate()
+                            .map(|(index, line)| {
+                                expand_batch_line(chunk * chunk_size + index + 1, line, options)
+                            })
+                            .collect::<Vec<Option<BatchResult>>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        for result in results.into_iter().flatten() {
+            write_batch_result(&mut handle, &result)?;
+        }
+    }
+    Ok(Output::None)
+}
+
+fn write_batch_result(handle: &mut dyn Write, result: &BatchResult) -> Result<(), CliError> {
+    let serialized =
+        serde_json::to_string(result).map_err(|err| CliError::Runtime(err.to_string()))?;
+    writeln!(handle, "{}", serialized).map_err(|err| CliError::Runtime(err.to_string()))
+}
+
+/// The NDJSON line written for each line of batch input
+#[derive(Serialize)]
+struct BatchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dates: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+/// Expands a single line of batch input, blank lines are skipped
+fn expand_batch_line(
+    line_number: usize,
+    line: &str,
+    options: &ExpandOptions,
+) -> Option<BatchResult> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let mut id = None;
+    let expanded = if line.starts_with('{') {
+        match serde_json::from_str::<BatchRule>(line) {
+            Ok(batch_rule) => {
+                id = batch_rule.id;
+                let count = batch_rule
+                    .count
+                    .map(|count| count.to_string())
+                    .unwrap_or_else(|| options.count.to_string());
+                let rrule = batch_rule.rrule;
+                catch_panic(|| expand_rrule(&rrule, options, &count)).and_then(|dates| dates)
+            }
+            Err(err) => Err(CliError::Parse(format!("invalid batch line: {}", err))),
         }
-        Err(_) => println!("Encountered Rrule parse error"),
+    } else {
+        catch_panic(|| expand_rrule(line, options, options.count)).and_then(|dates| dates)
+    };
+    let (dates, error) = match expanded {
+        Ok(dates) => (Some(dates), None),
+        Err(err) => (None, Some(err.to_json()["error"].take())),
+    };
+    Some(BatchResult {
+        id,
+        line: line_number,
+        dates,
+        error,
+    })
+}
+
+fn rrule_argument<'m>(matches: &'m ArgMatches) -> Result<&'m str, CliError> {
+    match matches.value_of("rrule") {
+        Some(rrule) if !rrule.is_empty() => Ok(rrule),
+        _ => Err(CliError::Parse(String::from(
+            "rrule string cannot be empty, use -h argument to view help",
+        ))),
+    }
+}
+
+/// Parses the rule and validates it separately so the two failures get their own exit code
+fn parse_and_validate(rrule_string: &str) -> Result<RRule<'_>, CliError> {
+    let rrule = parse_rrule(rrule_string)
+        .map_err(|err| CliError::Parse(format!("{} in rrule {}", err, rrule_string)))?;
+    validate_rrule(&rrule).map_err(|err| CliError::Validation(err.to_string()))?;
+    Ok(rrule)
+}
+
+/// Parses a date argument given either in the rrule date format (`20220123T030000`, with an
+/// optional trailing `Z`) or as `2022-01-23 03:00:00`
+fn parse_utc_date(name: &str, date: &str) -> Result<NaiveDateTime, CliError> {
+    NaiveDateTime::parse_from_str(date.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .map_err(|_| CliError::Validation(format!("{} {} is not a valid UTC date", name, date)))
+}
+
+/// Runs `f` and turns a panic into a runtime error instead of a crash report, the panic
+/// hook is swapped once for all of `f` as it is shared between threads
+fn catch_runtime_error<T, F: FnOnce() -> T>(f: F) -> Result<T, CliError> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = catch_panic(f);
+    panic::set_hook(hook);
+    result
+}
+
+/// Turns a panic in `f` into a runtime error
+fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, CliError> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .map_err(|payload| CliError::Runtime(panic_message(payload)))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown error while evaluating the rrule")
+    }
+}
+
+fn print_output(output: &Output, pretty: bool) -> Result<(), CliError> {
+    let serialized = match output {
+        Output::Json(value) if pretty => serde_json::to_string_pretty(value),
+        Output::Json(value) => serde_json::to_string(value),
+        Output::Text(text) => Ok(text.clone()),
+        Output::None => return Ok(()),
+    }
+    .map_err(|err| CliError::Runtime(err.to_string()))?;
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    // text that brings its own line endings, like iCalendar's CRLF, is written as is
+    if serialized.ends_with('\n') {
+        write!(handle, "{}", serialized)
+    } else {
+        writeln!(handle, "{}", serialized)
     }
+    .map_err(|err| CliError::Runtime(err.to_string()))
 }
@@ -0,0 +1,359 @@
+use crate::holidays::{MAX_DROPPED_OCCURRENCES, MAX_SHIFT_DAYS};
+use crate::{convert_to_rrule, validate_rrule, HolidayCalendar, RRule, ShiftPolicy};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// Returned when the rule of a `MaterializedSchedule` is invalid, the message says why
+#[derive(Debug, Clone)]
+pub struct MaterializeError {
+    message: String,
+}
+
+impl MaterializeError {
+    fn new<S: Into<String>>(message: S) -> MaterializeError {
+        MaterializeError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for MaterializeError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "materialize error: {}", self.message)
+    }
+}
+
+impl Error for MaterializeError {
+    fn description(&self) -> &str {
+        "materialize error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// How many queries of a `MaterializedSchedule` were answered from the cache and how many
+/// had to compute occurrences
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// The occurrences worked out so far, from `start` on
+struct Materialized {
+    start: DateTime<Utc>,
+    dates: Vec<DateTime<Tz>>,
+    /// Every occurrence before this instant is in `dates`
+    complete_before: DateTime<Utc>,
+    /// The rule has no occurrences after the ones in `dates`
+    ended: bool,
+    /// The last occurrence of the rule itself that was looked at, before any shift,
+    /// where the iteration carries on from
+    cursor: Option<DateTime<Tz>>,
+    /// The occurrences of the COUNT left after `cursor`
+    remaining: Option<usize>,
+    /// The last occurrence produced, occurrences shifted onto or before it are dropped
+    last: Option<DateTime<Tz>>,
+    /// The version of the holiday calendar the occurrences were shifted with
+    holidays_version: u64,
+}
+
+impl Materialized {
+    fn new(after: DateTime<Utc>, rrule: Option<&RRule>, holidays_version: u64) -> Materialized {
+        Materialized {
+            start: after,
+            dates: Vec::new(),
+            complete_before: after,
+            ended: rrule.is_none(),
+            cursor: None,
+            remaining: rrule
+                .filter(|rrule| !rrule.count.is_empty())
+                .map(|rrule| rrule.count("")),
+            last: None,
+            holidays_version,
+        }
+    }
+}
+
+/// A rule with its occurrences cached for a rolling horizon.
+///
+/// Queries are answered from the occurrences already worked out when they cover them.
+/// Otherwise the cache is extended from where it stopped to `horizon` past the end of the
+/// query, dropping the occurrences before its start, so a schedule queried for the next
+/// few weeks every time rarely computes anything. Changing the rule, the holiday calendar
+/// or the tz database version drops the cache. A rule without DTSTART starts from the
+/// first query, or from the first one after the cache was dropped.
+///
+/// Example:
+/// ```
+/// use chrono::{Duration, TimeZone, Utc};
+/// use sundial::MaterializedSchedule;
+/// let mut schedule = MaterializedSchedule::new(
+///     "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190101T000000",
+///     Duration::weeks(8),
+/// )
+/// .unwrap();
+/// let after = Utc.ymd(2019, 4, 14).and_hms(0, 0, 0);
+/// let dates = schedule.next_dates(after, 4).to_vec();
+/// assert_eq!("2019-04-16T06:00:00+10:00", dates[0].to_rfc3339());
+/// // the next week is in the cache already
+/// schedule.next_dates(after + Duration::weeks(1), 4);
+/// assert_eq!(1, schedule.stats().hits);
+/// assert_eq!(1, schedule.stats().misses);
+/// ```
+pub struct MaterializedSchedule {
+    rule: String,
+    horizon: Duration,
+    holidays: Option<(Arc<dyn HolidayCalendar + Send + Sync>, ShiftPolicy)>,
+    tz_version: String,
+    cache: Option<Materialized>,
+    stats: CacheStats,
+}
+
+impl MaterializedSchedule {
+    /// A cache of the occurrences of `rule` computing `horizon` ahead of the queries
+    pub fn new(rule: &str, horizon: Duration) -> Result<MaterializedSchedule, MaterializeError> {
+        check_rule(rule)?;
+        if horizon <= Duration::zero() {
+            return Err(MaterializeError::new(
+                "the horizon has to be longer than zero",
+            ));
+        }
+        Ok(MaterializedSchedule {
+            rule: rule.to_string(),
+            horizon,
+            holidays: None,
+            tz_version: String::new(),
+            cache: None,
+            stats: CacheStats::default(),
+        })
+    }
+
+    /// Moves the occurrences off the holidays and weekends of `calendar` like
+    /// `RRule::shifted`. The cache is dropped when the calendar's `version` changes.
+    pub fn with_holidays(
+        mut self,
+        calendar: Arc<dyn HolidayCalendar + Send + Sync>,
+        policy: ShiftPolicy,
+    ) -> MaterializedSchedule {
+        self.set_holidays(Some((calendar, policy)));
+        self
+    }
+
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    /// Swaps the rule, dropping the cache when it is another one
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), MaterializeError> {
+        check_rule(rule)?;
+        if rule != self.rule {
+            self.rule = rule.to_string();
+            self.invalidate();
+        }
+        Ok(())
+    }
+
+    /// Swaps the holiday calendar and its shift policy, or stops shifting with `None`
+    pub fn set_holidays(
+        &mut self,
+        holidays: Option<(Arc<dyn HolidayCalendar + Send + Sync>, ShiftPolicy)>,
+    ) {
+        self.holidays = holidays;
+        self.invalidate();
+    }
+
+    pub fn tz_version(&self) -> &str {
+        &self.tz_version
+    }
+
+    /// Sets the version of the tz database the timezones come from, e.g. `2019a`,
+    /// dropping the cache when it is another one. chrono-tz can't tell which version it
+    /// was built with, so this is up to whoever updates it.
+    pub fn set_tz_version(&mut self, version: &str) {
+        if version != self.tz_version {
+            self.tz_version = version.to_string();
+            self.invalidate();
+        }
+    }
+
+    /// Drops the cached occurrences, the next query computes them again
+    pub fn invalidate(&mut self) {
+        self.cache = None;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// The occurrences at or after `after` and strictly before `before`
+    pub fn dates_between(
+        &mut self,
+        after: DateTime<Utc>,
+        before: DateTime<Utc>,
+    ) -> &[DateTime<Tz>] {
+        self.drop_stale(after);
+        let covered = self
+            .cache
+            .as_ref()
+            .is_some_and(|cache| cache.ended || before <= cache.complete_before);
+        if covered {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            self.extend(after, before + self.horizon);
+        }
+
+        let cache = self.cache.as_ref().unwrap();
+        let from = first_at_or_after(&cache.dates, after);
+        let to = first_at_or_after(&cache.dates, before);
+        &cache.dates[from..to.max(from)]
+    }
+
+    /// The first `count` occurrences at or after `after`, fewer when the rule ends before
+    pub fn next_dates(&mut self, after: DateTime<Utc>, count: usize) -> &[DateTime<Tz>] {
+        self.drop_stale(after);
+        let available =
+            |cache: &Materialized| cache.dates.len() - first_at_or_after(&cache.dates, after);
+        let covered = self
+            .cache
+            .as_ref()
+            .is_some_and(|cache| cache.ended || available(cache) >= count);
+        if covered {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            loop {
+                let target = match self.cache.as_ref() {
+                    Some(cache) if cache.ended || available(cache) >= count => break,
+                    Some(cache) => cache.complete_before.max(after) + self.horizon,
+                    None => after + self.horizon,
+                };
+                self.extend(after, target);
+            }
+        }
+
+        let cache = self.cache.as_ref().unwrap();
+        let from = first_at_or_after(&cache.dates, after);
+        &cache.dates[from..(from + count).min(cache.dates.len())]
+    }
+
+    /// Drops the cache when it starts after `after` or the holidays changed since it was
+    /// built
+    fn drop_stale(&mut self, after: DateTime<Utc>) {
+        let holidays_version = self.holidays_version();
+        if self
+            .cache
+            .as_ref()
+            .is_some_and(|cache| cache.start > after || cache.holidays_version != holidays_version)
+        {
+            self.invalidate();
+        }
+    }
+
+    fn holidays_version(&self) -> u64 {
+        self.holidays
+            .as_ref()
+            .map_or(0, |(calendar, _)| calendar.version())
+    }
+
+    /// Works out the occurrences up to `target`, starting the cache at `after` when there
+    /// is none and dropping the occurrences before `after` when there is one
+    fn extend(&mut self, after: DateTime<Utc>, target: DateTime<Utc>) {
+        let holidays_version = self.holidays_version();
+        // the rule was checked when it was set, so it always parses
+        let rrule = match convert_to_rrule(&self.rule) {
+            Ok(rrule) => rrule,
+            Err(_) => {
+                self.cache = Some(Materialized::new(after, None, holidays_version));
+                return;
+            }
+        };
+        let cache = self
+            .cache
+            .get_or_insert_with(|| Materialized::new(after, Some(&rrule), holidays_version));
+        let drop = first_at_or_after(&cache.dates, after);
+        cache.dates.drain(..drop);
+        cache.start = after;
+
+        let shifted = self
+            .holidays
+            .as_ref()
+            .map(|(calendar, policy)| rrule.shifted(calendar.as_ref(), *policy));
+        let shift = |date: DateTime<Tz>| match shifted.as_ref() {
+            Some(shifted) => shifted.shift(date),
+            None => Some(date),
+        };
+        // an occurrence can only be shifted this far, so none after it lands before target
+        let last_raw = target + Duration::days(MAX_SHIFT_DAYS);
+
+        let occurrences = occurrences_from(&rrule, cache.cursor, after);
+        let mut dropped = 0;
+        for date in occurrences.take(cache.remaining.unwrap_or(usize::MAX)) {
+            cache.cursor = Some(date);
+            if let Some(remaining) = cache.remaining.as_mut() {
+                *remaining -= 1;
+            }
+            match shift(date).filter(|date| cache.last.is_none_or(|last| *date > last)) {
+                Some(date) => {
+                    dropped = 0;
+                    cache.last = Some(date);
+                    let instant = date.with_timezone(&Utc);
+                    if instant >= cache.start {
+                        cache.dates.push(date);
+                    }
+                    if instant >= target {
+                        cache.complete_before = instant;
+                        return;
+                    }
+                }
+                None => {
+                    dropped += 1;
+                    if dropped == MAX_DROPPED_OCCURRENCES {
+                        cache.ended = true;
+                        return;
+                    }
+                }
+            }
+            if date.with_timezone(&Utc) >= last_raw {
+                cache.complete_before = target;
+                return;
+            }
+        }
+        cache.ended = true;
+    }
+}
+
+/// The occurrences of the rule itself after `cursor`, or from its start when the
+/// iteration hasn't started yet, up to its UNTIL
+fn occurrences_from<'r>(
+    rrule: &'r RRule,
+    cursor: Option<DateTime<Tz>>,
+    after: DateTime<Utc>,
+) -> Box<dyn Iterator<Item = DateTime<Tz>> + 'r> {
+    match cursor {
+        Some(cursor) => {
+            let bounded = rrule.bounded_iter_dates_from(cursor);
+            Box::new(bounded.filter(move |date| *date > cursor))
+        }
+        None => {
+            let start_date = rrule.start_date(after.with_timezone(&rrule.timezone()));
+            rrule.bounded_iter_dates_from(start_date)
+        }
+    }
+}
+
+fn first_at_or_after(dates: &[DateTime<Tz>], instant: DateTime<Utc>) -> usize {
+    dates.partition_point(|date| date.with_timezone(&Utc) < instant)
+}
+
+fn check_rule(rule: &str) -> Result<(), MaterializeError> {
+    let rrule = convert_to_rrule(rule)
+        .map_err(|err| MaterializeError::new(format!("{} in rrule {}", err, rule)))?;
+    validate_rrule(&rrule).map_err(|err| MaterializeError::new(err.to_string()))
+}
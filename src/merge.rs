@@ -0,0 +1,150 @@
+use crate::{Clock, RRule};
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// How `merge` combines the occurrences of its sources
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MergeOptions {
+    /// Yield an instant occurring in several sources once, for the first of those sources.
+    pub dedup: bool,
+    /// Convert every occurrence to this timezone, otherwise each one keeps the timezone of
+    /// its source. Sources are interleaved by instant either way.
+    pub timezone: Option<Tz>,
+}
+
+/// Merges the occurrences of several sources into one chronological stream of
+/// `(source_id, date)`, each source has to yield its occurrences in order. Occurrences at
+/// the same instant come in the order of their sources.
+///
+/// Example:
+/// ```
+/// use sundial::{convert_to_rrule, merge, MergeOptions};
+/// use chrono::{TimeZone, Utc};
+/// let bakery = convert_to_rrule("FREQ=DAILY;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne").unwrap();
+/// let dairy = convert_to_rrule("FREQ=WEEKLY;BYDAY=MO;BYHOUR=20;BYMINUTE=0;BYSECOND=0").unwrap();
+/// let after = Some(Utc.ymd(2019, 4, 14).and_hms(0, 0, 0));
+/// let timeline: Vec<(&str, String)> = merge(
+///     vec![
+///         ("bakery", bakery.iter_dates_after(after)),
+///         ("dairy", dairy.iter_dates_after(after)),
+///     ],
+///     MergeOptions::default(),
+/// )
+/// .take(3)
+/// .map(|(source, date)| (source, date.to_rfc3339()))
+/// .collect();
+/// assert_eq!(
+///     vec![
+///         ("bakery", "2019-04-15T06:00:00+10:00".to_owned()),
+///         ("bakery", "2019-04-16T06:00:00+10:00".to_owned()),
+///         ("dairy", "2019-04-15T20:00:00+00:00".to_owned()),
+///     ],
+///     timeline
+/// );
+/// ```
+pub fn merge<S, I>(sources: Vec<(S, I)>, options: MergeOptions) -> Merge<S, I>
+where
+    S: Clone,
+    I: Iterator<Item = DateTime<Tz>>,
+{
+    let mut merged = Merge {
+        heads: BinaryHeap::new(),
+        pending: Vec::with_capacity(sources.len()),
+        sources,
+        options,
+        last: None,
+    };
+    for index in 0..merged.sources.len() {
+        merged.pending.push(None);
+        merged.advance(index);
+    }
+    merged
+}
+
+/// The iterator returned by `merge`
+pub struct Merge<S, I> {
+    sources: Vec<(S, I)>,
+    /// The instant of the next occurrence of each source that has one, smallest first
+    heads: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+    pending: Vec<Option<DateTime<Tz>>>,
+    options: MergeOptions,
+    last: Option<DateTime<Utc>>,
+}
+
+impl<S, I: Iterator<Item = DateTime<Tz>>> Merge<S, I> {
+    /// Takes the next occurrence of the source at `index`
+    fn advance(&mut self, index: usize) {
+        self.pending[index] = self.sources[index].1.next();
+        if let Some(date) = self.pending[index] {
+            self.heads.push(Reverse((date.with_timezone(&Utc), index)));
+        }
+    }
+}
+
+impl<S: Clone, I: Iterator<Item = DateTime<Tz>>> Iterator for Merge<S, I> {
+    type Item = (S, DateTime<Tz>);
+
+    fn next(&mut self) -> Option<(S, DateTime<Tz>)> {
+        loop {
+            let Reverse((instant, index)) = self.heads.pop()?;
+            let date = self.pending[index].take()?;
+            self.advance(index);
+            if self.options.dedup && self.last == Some(instant) {
+                continue;
+            }
+            self.last = Some(instant);
+            let date = match self.options.timezone {
+                Some(timezone) => date.with_timezone(&timezone),
+                None => date,
+            };
+            return Some((self.sources[index].0.clone(), date));
+        }
+    }
+}
+
+impl<'a> RRule<'a> {
+    /// Iterates the occurrences at or after `after` up to the COUNT and UNTIL of the rule,
+    /// for merging several rules with `merge`. Without DTSTART the iteration starts from
+    /// `after`, or from the rule's clock when there is no `after` either.
+    pub fn iter_dates_after(
+        &self,
+        after: Option<DateTime<Utc>>,
+    ) -> impl Iterator<Item = DateTime<Tz>> + '_ {
+        let now = after.unwrap_or_else(|| self.clock.now());
+        let start_date = self.start_date(now.with_timezone(&self.timezone()));
+        self.bounded_iter_dates_from(start_date)
+            .filter(move |date| after.is_none_or(|after| date.with_timezone(&Utc) >= after))
+    }
+
+    /// The first occurrence strictly after `after`, `None` once the rule has ended. Without
+    /// DTSTART the iteration starts from `after` cut to the second, so the occurrences don't
+    /// carry its fraction of a second.
+    pub(crate) fn next_date_after(&self, after: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        self.iter_dates_strictly_after(after).next()
+    }
+
+    /// Same as `next_date_after` for a rule stepped through one occurrence at a time, which
+    /// ends once `remaining`, what's left of the `floating_count`, is down to 0
+    pub(crate) fn next_date_after_remaining(
+        &self,
+        after: DateTime<Utc>,
+        remaining: Option<usize>,
+    ) -> Option<DateTime<Tz>> {
+        if remaining == Some(0) {
+            return None;
+        }
+        self.next_date_after(after)
+    }
+
+    /// The occurrences strictly after `after`, started like `next_date_after`
+    pub(crate) fn iter_dates_strictly_after(
+        &self,
+        after: DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Tz>> + '_ {
+        let start = after.with_nanosecond(0).unwrap_or(after);
+        self.iter_dates_after(Some(start))
+            .filter(move |date| date.with_timezone(&Utc) > after)
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{convert_to_rrule, validate_rrule, Clock, RRule, SystemClock};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// The longest the scheduler sleeps before checking the clock again, so it notices the
+/// wall clock jumping, e.g. after the machine was suspended
+const MAX_SLEEP_SECONDS: u64 = 60;
+
+/// How late a run can fire and still count as on time instead of missed
+const MISSED_RUN_GRACE_SECONDS: i64 = 1;
+
+/// Most missed runs of a job fired after a pause, the ones before them are dropped
+/// whatever the policy
+const MAX_MISSED_RUNS: usize = 1000;
+
+/// Returned when a job's rule is invalid or the job doesn't exist, the message says why
+#[derive(Debug, Clone)]
+pub struct SchedulerError {
+    message: String,
+}
+
+impl SchedulerError {
+    fn new<S: Into<String>>(message: S) -> SchedulerError {
+        SchedulerError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SchedulerError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "scheduler error: {}", self.message)
+    }
+}
+
+impl Error for SchedulerError {
+    fn description(&self) -> &str {
+        "scheduler error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// What to do with the runs of a job that were due while the scheduler was paused or
+/// couldn't run, e.g. because the machine was asleep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissedRunPolicy {
+    /// Fire one run for all the missed ones, for the latest of them.
+    FireOnce,
+    /// Fire a run for every missed one, in order.
+    FireAll,
+    /// Don't fire the missed runs, only the ones from now on.
+    Skip,
+}
+
+/// Identifies a job registered with a `Scheduler`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(u64);
+
+/// A run of a job, passed to its callback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JobRun {
+    pub job: JobId,
+    /// The occurrence of the job's rule the run is for
+    pub scheduled: DateTime<Tz>,
+    /// Whether the run is late, fired for a missed occurrence by the job's `MissedRunPolicy`
+    pub missed: bool,
+}
+
+/// A clock that follows tokio's clock from a given instant, so a `Scheduler` driven by it
+/// can be tested with tokio's paused clock, where time only moves when the runtime is idle
+/// or `tokio::time::advance` is called.
+pub struct TokioClock {
+    origin: DateTime<Utc>,
+    started: Instant,
+}
+
+impl TokioClock {
+    /// A clock reading `origin` now, as told by tokio's clock
+    pub fn new(origin: DateTime<Utc>) -> TokioClock {
+        TokioClock {
+            origin,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.origin
+            + Duration::from_std(self.started.elapsed()).unwrap_or_else(|_| Duration::zero())
+    }
+}
+
+type Callback = Arc<dyn Fn(JobRun) + Send + Sync>;
+
+struct Job {
+    rrule: RRule<'static>,
+    policy: MissedRunPolicy,
+    callback: Callback,
+    /// Occurrences at or before this instant are done with, fired or not
+    after: DateTime<Utc>,
+    /// The occurrences left to a rule with a COUNT and without a DTSTART, whose
+    /// iteration starts over from `after` every time
+    remaining: Option<usize>,
+    next: Option<DateTime<Tz>>,
+}
+
+impl Job {
+    /// Takes the occurrences due by `now` off the job and returns the runs to fire for them
+    fn take_due_runs(&mut self, id: JobId, now: DateTime<Utc>) -> Vec<JobRun> {
+        if self.next.is_none_or(|next| next.with_timezone(&Utc) > now) {
+            return Vec::new();
+        }
+        let on_time_from = now - Duration::seconds(MISSED_RUN_GRACE_SECONDS);
+        if self.policy == MissedRunPolicy::Skip && self.remaining.is_none() {
+            // the missed runs are neither fired nor counted, jump straight past them
+            self.after = self.after.max(on_time_from - Duration::nanoseconds(1));
+        }
+
+        // one pass over the due occurrences, keeping the latest missed ones the policy fires
+        let kept = match self.policy {
+            MissedRunPolicy::FireAll => MAX_MISSED_RUNS,
+            MissedRunPolicy::FireOnce => 1,
+            MissedRunPolicy::Skip => 0,
+        };
+        let mut missed = VecDeque::new();
+        let mut miss = |date: DateTime<Tz>| {
+            if kept > 0 {
+                if missed.len() == kept {
+                    missed.pop_front();
+                }
+                missed.push_back(date);
+            }
+        };
+        let mut last = None;
+        let mut taken = 0;
+        self.next = None;
+        let remaining = self.remaining.unwrap_or(usize::MAX);
+        for date in self
+            .rrule
+            .iter_dates_strictly_after(self.after)
+            .take(remaining)
+        {
+            if date.with_timezone(&Utc) > now {
+                self.next = Some(date);
+                break;
+            }
+            taken += 1;
+            if let Some(previous) = last.replace(date) {
+                miss(previous);
+            }
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= taken;
+        }
+        let last = match last {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+        self.after = last.with_timezone(&Utc);
+
+        let on_time = if self.after >= on_time_from {
+            Some(last)
+        } else {
+            miss(last);
+            None
+        };
+        let run = |scheduled: DateTime<Tz>, missed: bool| JobRun {
+            job: id,
+            scheduled,
+            missed,
+        };
+        let mut runs: Vec<JobRun> = missed.into_iter().map(|date| run(date, true)).collect();
+        runs.extend(on_time.map(|date| run(date, false)));
+        runs
+    }
+}
+
+struct State {
+    jobs: BTreeMap<JobId, Job>,
+    next_id: u64,
+    paused: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Notify,
+    clock: Box<dyn Clock + Send + Sync>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // callbacks run outside the lock, so a panic never leaves the state half done
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Runs callbacks on the occurrences of rules, on a tokio runtime.
+///
+/// A single task sleeps on tokio's timer wheel until the next occurrence of any job, fires
+/// the callbacks of the jobs that are due and goes back to sleep. Callbacks run on that
+/// task, so one with slow work to do should spawn a task for it, and one that panics only
+/// loses its own run. Runs missed while the
+/// scheduler was paused, or the clock jumped ahead, are fired following the job's
+/// `MissedRunPolicy`. Jobs can be added, removed and given a new rule while the
+/// scheduler runs.
+///
+/// Example:
+/// ```
+/// use sundial::{MissedRunPolicy, Scheduler};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let scheduler = Scheduler::new();
+/// let job = scheduler
+///     .add_job("FREQ=DAILY;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne", MissedRunPolicy::FireOnce, |run| {
+///         println!("delivery run for {}", run.scheduled);
+///     })
+///     .unwrap();
+/// assert!(scheduler.next_run(job).is_some());
+/// # }
+/// ```
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    driver: JoinHandle<()>,
+}
+
+impl Scheduler {
+    /// A scheduler going by the system clock. Like `tokio::spawn` it has to be created on a
+    /// tokio runtime.
+    pub fn new() -> Scheduler {
+        Scheduler::with_clock(SystemClock)
+    }
+
+    /// A scheduler reading now from `clock`, such as a `TokioClock` in tests
+    pub fn with_clock<C: Clock + Send + Sync + 'static>(clock: C) -> Scheduler {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                jobs: BTreeMap::new(),
+                next_id: 1,
+                paused: false,
+            }),
+            changed: Notify::new(),
+            clock: Box::new(clock),
+        });
+        let driver = tokio::spawn(drive(shared.clone()));
+        Scheduler { shared, driver }
+    }
+
+    /// Registers a job firing `callback` on every occurrence of `rule` from now on
+    pub fn add_job<F>(
+        &self,
+        rule: &str,
+        policy: MissedRunPolicy,
+        callback: F,
+    ) -> Result<JobId, SchedulerError>
+    where
+        F: Fn(JobRun) + Send + Sync + 'static,
+    {
+        let rrule = parse_rule(rule)?;
+        let mut job = Job {
+            remaining: rrule.floating_count(),
+            rrule,
+            policy,
+            callback: Arc::new(callback),
+            after: self.shared.clock.now(),
+            next: None,
+        };
+        job.next = job
+            .rrule
+            .next_date_after_remaining(job.after, job.remaining);
+
+        let mut state = self.shared.state();
+        let id = JobId(state.next_id);
+        state.next_id += 1;
+        state.jobs.insert(id, job);
+        drop(state);
+        self.shared.changed.notify_one();
+        Ok(id)
+    }
+
+    /// Swaps the rule of a job, the new rule fires from now on and the runs the old one
+    /// missed are dropped
+    pub fn update_rule(&self, job: JobId, rule: &str) -> Result<(), SchedulerError> {
+        let rrule = parse_rule(rule)?;
+        let now = self.shared.clock.now();
+        let mut state = self.shared.state();
+        let job = state
+            .jobs
+            .get_mut(&job)
+            .ok_or_else(|| SchedulerError::new(format!("there is no job {}", job.0)))?;
+        job.remaining = rrule.floating_count();
+        job.rrule = rrule;
+        job.after = job.after.max(now);
+        job.next = job
+            .rrule
+            .next_date_after_remaining(job.after, job.remaining);
+        drop(state);
+        self.shared.changed.notify_one();
+        Ok(())
+    }
+
+    /// Unregisters a job, returns whether there was one
+    pub fn remove_job(&self, job: JobId) -> bool {
+        let removed = self.shared.state().jobs.remove(&job).is_some();
+        self.shared.changed.notify_one();
+        removed
+    }
+
+    /// The occurrence the job fires for next, `None` once its rule has ended or when
+    /// there is no such job
+    pub fn next_run(&self, job: JobId) -> Option<DateTime<Tz>> {
+        self.shared.state().jobs.get(&job).and_then(|job| job.next)
+    }
+
+    /// Stops firing runs until `resume`, the runs due in between are missed
+    pub fn pause(&self) {
+        self.shared.state().paused = true;
+        self.shared.changed.notify_one();
+    }
+
+    /// Fires the runs missed while paused following each job's policy and carries on
+    pub fn resume(&self) {
+        self.shared.state().paused = false;
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
+
+/// Checks the rule and parses it into one the job can keep
+fn parse_rule(rule: &str) -> Result<RRule<'static>, SchedulerError> {
+    let rrule = convert_to_rrule(rule)
+        .map_err(|err| SchedulerError::new(format!("{} in rrule {}", err, rule)))?;
+    validate_rrule(&rrule).map_err(|err| SchedulerError::new(err.to_string()))?;
+    rrule
+        .to_static()
+        .ok_or_else(|| SchedulerError::new(format!("unsupported value in rrule {}", rule)))
+}
+
+/// Fires the due runs, then sleeps until the next one or until the jobs change
+async fn drive(shared: Arc<Shared>) {
+    loop {
+        let now = shared.clock.now();
+        let mut runs: Vec<(Callback, JobRun)> = Vec::new();
+        let next = {
+            let mut state = shared.state();
+            if state.paused {
+                None
+            } else {
+                for (id, job) in state.jobs.iter_mut() {
+                    for run in job.take_due_runs(*id, now) {
+                        runs.push((job.callback.clone(), run));
+                    }
+                }
+                state
+                    .jobs
+                    .values()
+                    .filter_map(|job| job.next)
+                    .map(|next| next.with_timezone(&Utc))
+                    .min()
+            }
+        };
+        for (callback, run) in runs {
+            // a panicking callback only loses its own run, the panic hook reports it and
+            // the other jobs keep firing
+            let _ = catch_unwind(AssertUnwindSafe(|| callback(run)));
+        }
+
+        match next {
+            Some(next) => {
+                let delay = (next - shared.clock.now())
+                    .to_std()
+                    .unwrap_or_default()
+                    .min(std::time::Duration::from_secs(MAX_SLEEP_SECONDS));
+                let _ = tokio::time::timeout(delay, shared.changed.notified()).await;
+            }
+            None => shared.changed.notified().await,
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{convert_to_rrule, validate_rrule, RRule};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Returned when a schedule's rule is invalid, an acknowledgement doesn't match or the store
+/// can't be read or written, the message says why
+#[derive(Debug, Clone)]
+pub struct StoreError {
+    message: String,
+}
+
+impl StoreError {
+    fn new<S: Into<String>>(message: S) -> StoreError {
+        StoreError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "store error: {}", self.message)
+    }
+}
+
+impl Error for StoreError {
+    fn description(&self) -> &str {
+        "store error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// A rule kept in a `ScheduleStore` with the occurrence it is due at next
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSchedule {
+    pub id: String,
+    pub rule: String,
+    /// Whatever the caller keeps with the rule, e.g. the supplier a delivery is for
+    pub metadata: BTreeMap<String, String>,
+    /// The occurrence waiting to be acknowledged, `None` once the rule has ended
+    pub next_due: Option<DateTime<Utc>>,
+    /// What's left of the COUNT of a rule without DTSTART, see `RRule::floating_count`
+    remaining: Option<usize>,
+    rrule: RRule<'static>,
+}
+
+impl StoredSchedule {
+    /// A schedule due at the first occurrence of `rule` after `after`
+    fn new(
+        id: &str,
+        rule: &str,
+        metadata: BTreeMap<String, String>,
+        after: DateTime<Utc>,
+    ) -> Result<StoredSchedule, StoreError> {
+        let rrule = parse_rule(rule)?;
+        let remaining = rrule.floating_count();
+        Ok(StoredSchedule {
+            id: id.to_string(),
+            rule: rule.to_string(),
+            metadata,
+            next_due: next_due_after(&rrule, after, remaining),
+            remaining,
+            rrule,
+        })
+    }
+}
+
+/// Checks the rule and parses it into one the schedule can keep
+fn parse_rule(rule: &str) -> Result<RRule<'static>, StoreError> {
+    let rrule = convert_to_rrule(rule)
+        .map_err(|err| StoreError::new(format!("{} in rrule {}", err, rule)))?;
+    validate_rrule(&rrule).map_err(|err| StoreError::new(err.to_string()))?;
+    rrule
+        .to_static()
+        .ok_or_else(|| StoreError::new(format!("unsupported value in rrule {}", rule)))
+}
+
+fn next_due_after(
+    rrule: &RRule,
+    after: DateTime<Utc>,
+    remaining: Option<usize>,
+) -> Option<DateTime<Utc>> {
+    rrule
+        .next_date_after_remaining(after, remaining)
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Keeps rules with their ids and metadata, and which occurrence each one is due at next.
+///
+/// A dispatcher asks for the schedules `due_before` now, handles them and `acknowledge`s
+/// each occurrence it handled, which moves the schedule on to its next occurrence. Runs that
+/// are never acknowledged stay due, so after a crash the dispatcher picks up where it left
+/// off, one occurrence at a time.
+pub trait ScheduleStore {
+    /// Stores `rule` under `id`, replacing the schedule already there, due at the first
+    /// occurrence of the rule after `after`
+    fn put(
+        &mut self,
+        id: &str,
+        rule: &str,
+        metadata: BTreeMap<String, String>,
+        after: DateTime<Utc>,
+    ) -> Result<StoredSchedule, StoreError>;
+
+    fn get(&self, id: &str) -> Result<Option<StoredSchedule>, StoreError>;
+
+    /// Deletes the schedule, returns whether there was one
+    fn remove(&mut self, id: &str) -> Result<bool, StoreError>;
+
+    /// The schedules due strictly before `before`, in the order they are due, the ones due
+    /// at the same time by id
+    fn due_before(&self, before: DateTime<Utc>) -> Result<Vec<StoredSchedule>, StoreError>;
+
+    /// Marks the occurrence `due` of the schedule as handled and returns the occurrence
+    /// the schedule is due at next. Acknowledging an occurrence again is fine and changes
+    /// nothing, acknowledging one the schedule hasn't got to yet is an error.
+    fn acknowledge(
+        &mut self,
+        id: &str,
+        due: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, StoreError>;
+}
+
+/// How a schedule is written in the file of a `FileScheduleStore`
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScheduleRecord {
+    id: String,
+    rule: String,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_due: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoreFile {
+    /// Goes up every time the file is written, acknowledgements logged with an older one
+    /// are in the file already
+    #[serde(default)]
+    generation: u64,
+    schedules: Vec<ScheduleRecord>,
+}
+
+/// How an acknowledgement is written in the log of a `FileScheduleStore`, as the state of
+/// the schedule after it
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcknowledgementRecord {
+    generation: u64,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_due: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<usize>,
+}
+
+/// Fewest acknowledgements logged before the log is folded into the file, past that it is
+/// folded once it has as many lines as there are schedules
+const MIN_LOGGED_ACKNOWLEDGEMENTS: usize = 100;
+
+/// A `ScheduleStore` kept in a JSON file.
+///
+/// The schedules are held in memory with an index ordered by the occurrence they are due
+/// at, so `due_before` only looks at the schedules it returns. Putting or removing a
+/// schedule rewrites the whole file, to a temporary file next to it that then replaces it,
+/// so a crash never leaves it half written. Acknowledging an occurrence appends a line to
+/// a log next to the file, `<path>.log`, which is folded into the file once it has as many
+/// lines as there are schedules, so handling a run doesn't write every schedule. Changes
+/// are written before they are made in memory, a change that can't be written isn't made.
+/// Only one store should have a file open at a time.
+///
+/// Example:
+/// ```
+/// use chrono::{TimeZone, Utc};
+/// use std::collections::BTreeMap;
+/// use sundial::{FileScheduleStore, ScheduleStore};
+/// # let path = std::env::temp_dir().join(format!("sundial-doc-{}.json", std::process::id()));
+/// let mut store = FileScheduleStore::open(&path).unwrap();
+/// store
+///     .put(
+///         "bakery",
+///         "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+///         BTreeMap::new(),
+///         Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+///     )
+///     .unwrap();
+/// let due = store.due_before(Utc.ymd(2019, 4, 16).and_hms(0, 0, 0)).unwrap();
+/// assert_eq!(Some(Utc.ymd(2019, 4, 15).and_hms(20, 0, 0)), due[0].next_due);
+/// let next_due = store.acknowledge("bakery", Utc.ymd(2019, 4, 15).and_hms(20, 0, 0)).unwrap();
+/// assert_eq!(Some(Utc.ymd(2019, 4, 22).and_hms(20, 0, 0)), next_due);
+/// # std::fs::remove_file(&path).unwrap();
+/// # std::fs::remove_file(path.with_extension("json.log")).unwrap();
+/// ```
+pub struct FileScheduleStore {
+    path: PathBuf,
+    schedules: BTreeMap<String, StoredSchedule>,
+    due: BTreeSet<(DateTime<Utc>, String)>,
+    generation: u64,
+    /// Acknowledgements in the log since the file was last written
+    logged: usize,
+}
+
+impl FileScheduleStore {
+    /// Opens the store kept in the file at `path`, an empty one when there is no file yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileScheduleStore, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let invalid = |message: String| {
+            StoreError::new(format!(
+                "invalid store file {}: {}",
+                path.display(),
+                message
+            ))
+        };
+        let (file, exists): (StoreFile, bool) = match fs::read_to_string(&path) {
+            Ok(contents) => (
+                serde_json::from_str(&contents).map_err(|err| invalid(err.to_string()))?,
+                true,
+            ),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => (StoreFile::default(), false),
+            Err(err) => {
+                return Err(StoreError::new(format!(
+                    "can't read {}: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        };
+
+        let mut store = FileScheduleStore {
+            path: path.clone(),
+            schedules: BTreeMap::new(),
+            due: BTreeSet::new(),
+            generation: file.generation,
+            logged: 0,
+        };
+        for record in file.schedules {
+            let rrule = parse_rule(&record.rule).map_err(|err| invalid(err.message))?;
+            store.insert(StoredSchedule {
+                next_due: parse_next_due(&record.next_due, &record.id)?,
+                id: record.id,
+                rule: record.rule,
+                metadata: record.metadata,
+                remaining: record.remaining,
+                rrule,
+            });
+        }
+        if exists {
+            store.replay_log()?;
+        } else {
+            // the file is written before anything is logged, so this log belongs to a
+            // store that was deleted
+            let _ = fs::remove_file(store.log_path());
+        }
+        Ok(store)
+    }
+
+    /// Adds the schedule to the map and the index, replacing the one with the same id
+    fn insert(&mut self, schedule: StoredSchedule) {
+        self.unindex(&schedule.id);
+        if let Some(next_due) = schedule.next_due {
+            self.due.insert((next_due, schedule.id.clone()));
+        }
+        self.schedules.insert(schedule.id.clone(), schedule);
+    }
+
+    fn unindex(&mut self, id: &str) {
+        if let Some(next_due) = self.schedules.get(id).and_then(|old| old.next_due) {
+            self.due.remove(&(next_due, id.to_string()));
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        let mut log = self.path.clone().into_os_string();
+        log.push(".log");
+        PathBuf::from(log)
+    }
+
+    /// Writes the schedules with the one under `id` replaced by `schedule`, or without it
+    /// for `None`, to a temporary file that then replaces the store's, and starts a new log
+    fn save(&mut self, id: &str, schedule: Option<&StoredSchedule>) -> Result<(), StoreError> {
+        let mut schedules: BTreeMap<&str, &StoredSchedule> = self
+            .schedules
+            .iter()
+            .map(|(id, schedule)| (id.as_str(), schedule))
+            .collect();
+        match schedule {
+            Some(schedule) => schedules.insert(id, schedule),
+            None => schedules.remove(id),
+        };
+        let file = StoreFile {
+            generation: self.generation + 1,
+            schedules: schedules
+                .values()
+                .map(|schedule| ScheduleRecord {
+                    id: schedule.id.clone(),
+                    rule: schedule.rule.clone(),
+                    metadata: schedule.metadata.clone(),
+                    next_due: schedule.next_due.map(|date| date.to_rfc3339()),
+                    remaining: schedule.remaining,
+                })
+                .collect(),
+        };
+        let contents = serde_json::to_string_pretty(&file)
+            .map_err(|err| StoreError::new(format!("can't serialize the store: {}", err)))?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        // the contents are on disk before the rename and the rename is before the old log
+        // is removed, so a crash leaves either the old or the new file with a log it matches
+        File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(contents.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .and_then(|_| sync_parent_directory(&self.path))
+            .map_err(|err| {
+                StoreError::new(format!("can't write {}: {}", self.path.display(), err))
+            })?;
+
+        self.generation += 1;
+        self.logged = 0;
+        // the logged acknowledgements are in the file now, and when the log can't be
+        // removed its lines are ignored for their older generation
+        let _ = fs::remove_file(self.log_path());
+        Ok(())
+    }
+
+    /// Appends the state of the schedule after an acknowledgement to the log
+    fn log(&self, schedule: &StoredSchedule) -> Result<(), StoreError> {
+        let record = AcknowledgementRecord {
+            generation: self.generation,
+            id: schedule.id.clone(),
+            next_due: schedule.next_due.map(|date| date.to_rfc3339()),
+            remaining: schedule.remaining,
+        };
+        let mut line = serde_json::to_string(&record)
+            .map_err(|err| StoreError::new(format!("can't serialize the store: {}", err)))?;
+        line.push('\n');
+
+        let path = self.log_path();
+        let error = |err: std::io::Error| {
+            StoreError::new(format!("can't write {}: {}", path.display(), err))
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(error)?;
+        let length = file.metadata().map_err(error)?.len();
+        // the acknowledgement only returns once its line is on disk
+        if let Err(err) = file
+            .write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+        {
+            // don't leave half a line for the next acknowledgement to be appended to
+            let _ = file.set_len(length);
+            return Err(error(err));
+        }
+        Ok(())
+    }
+
+    /// Applies the acknowledgements logged since the file was written
+    fn replay_log(&mut self) -> Result<(), StoreError> {
+        let path = self.log_path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(StoreError::new(format!(
+                    "can't read {}: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        };
+        // a line without its newline was cut short by a crash, its acknowledgement never
+        // returned so it is dropped
+        let complete = contents.rfind('\n').map_or(0, |newline| newline + 1);
+        if complete < contents.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_len(complete as u64))
+                .map_err(|err| {
+                    StoreError::new(format!("can't write {}: {}", path.display(), err))
+                })?;
+        }
+
+        for line in contents[..complete].lines() {
+            let record: AcknowledgementRecord = serde_json::from_str(line).map_err(|err| {
+                StoreError::new(format!("invalid store log {}: {}", path.display(), err))
+            })?;
+            if record.generation != self.generation {
+                continue;
+            }
+            let mut schedule = match self.schedules.get(&record.id) {
+                Some(schedule) => schedule.clone(),
+                None => continue,
+            };
+            schedule.next_due = parse_next_due(&record.next_due, &record.id)?;
+            schedule.remaining = record.remaining;
+            self.insert(schedule);
+            self.logged += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the next due date written for the schedule `id`
+fn parse_next_due(
+    next_due: &Option<String>,
+    id: &str,
+) -> Result<Option<DateTime<Utc>>, StoreError> {
+    match next_due {
+        Some(next_due) => DateTime::parse_from_rfc3339(next_due)
+            .map(|date| Some(date.with_timezone(&Utc)))
+            .map_err(|_| {
+                StoreError::new(format!(
+                    "invalid next due date {:?} of schedule {}",
+                    next_due, id
+                ))
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Flushes the directory entry of `path` so a rename onto it survives a crash. Only unix
+/// can open a directory to sync it, elsewhere the rename is left to the filesystem.
+fn sync_parent_directory(path: &Path) -> std::io::Result<()> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+impl ScheduleStore for FileScheduleStore {
+    fn put(
+        &mut self,
+        id: &str,
+        rule: &str,
+        metadata: BTreeMap<String, String>,
+        after: DateTime<Utc>,
+    ) -> Result<StoredSchedule, StoreError> {
+        let schedule = StoredSchedule::new(id, rule, metadata, after)?;
+        self.save(id, Some(&schedule))?;
+        self.insert(schedule.clone());
+        Ok(schedule)
+    }
+
+    fn get(&self, id: &str) -> Result<Option<StoredSchedule>, StoreError> {
+        Ok(self.schedules.get(id).cloned())
+    }
+
+    fn remove(&mut self, id: &str) -> Result<bool, StoreError> {
+        if !self.schedules.contains_key(id) {
+            return Ok(false);
+        }
+        self.save(id, None)?;
+        self.unindex(id);
+        self.schedules.remove(id);
+        Ok(true)
+    }
+
+    fn due_before(&self, before: DateTime<Utc>) -> Result<Vec<StoredSchedule>, StoreError> {
+        // the empty id sorts first, so the range stops at the first schedule due at `before`
+        Ok(self
+            .due
+            .range(..(before, String::new()))
+            .filter_map(|(_, id)| self.schedules.get(id).cloned())
+            .collect())
+    }
+
+    fn acknowledge(
+        &mut self,
+        id: &str,
+        due: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, StoreError> {
+        let mut schedule = self
+            .schedules
+            .get(id)
+            .cloned()
+            .ok_or_else(|| StoreError::new(format!("there is no schedule {}", id)))?;
+        match schedule.next_due {
+            Some(next_due) if next_due == due => {}
+            Some(next_due) if next_due < due => {
+                return Err(StoreError::new(format!(
+                    "schedule {} is due at {} first, not {}",
+                    id,
+                    next_due.to_rfc3339(),
+                    due.to_rfc3339()
+                )))
+            }
+            // acknowledged already
+            _ => return Ok(schedule.next_due),
+        }
+
+        if let Some(remaining) = schedule.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        schedule.next_due = next_due_after(&schedule.rrule, due, schedule.remaining);
+        let next_due = schedule.next_due;
+        if self.logged + 1 >= self.schedules.len().max(MIN_LOGGED_ACKNOWLEDGEMENTS) {
+            self.save(id, Some(&schedule))?;
+        } else {
+            self.log(&schedule)?;
+            self.logged += 1;
+        }
+        self.insert(schedule);
+        Ok(next_due)
+    }
+}
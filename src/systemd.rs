@@ -0,0 +1,322 @@
+use crate::fields::{join_day_ranges, join_field, CalendarFields};
+use crate::{validate_rrule, RRule};
+use chrono::Weekday;
+use chrono_tz::Tz;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Weekdays in systemd order, the week starts on Monday
+const SYSTEMD_WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+const SYSTEMD_WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+const SYSTEMD_WEEKDAY_FULL_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Returned when a systemd calendar event can't be turned into a rule or a rule can't be
+/// expressed as one, the message says why
+#[derive(Debug, Clone)]
+pub struct OnCalendarError {
+    message: String,
+}
+
+impl OnCalendarError {
+    fn new<S: Into<String>>(message: S) -> OnCalendarError {
+        OnCalendarError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for OnCalendarError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "systemd calendar event conversion error: {}",
+            self.message
+        )
+    }
+}
+
+impl Error for OnCalendarError {
+    fn description(&self) -> &str {
+        "systemd calendar event conversion error"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl RRule<'static> {
+    /// Builds a rule from a systemd `OnCalendar=` calendar event such as
+    /// `Mon..Fri *-*-* 09:30:00 Australia/Sydney`, in the `[weekdays] [date] [time]
+    /// [timezone]` form or one of the `minutely`, `hourly`, `daily`, `weekly`, `monthly`
+    /// and `yearly` shorthands. Any year is supported, the other date and time fields take
+    /// lists, `..` ranges and `/` repetitions, which become the list of values they match.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::RRule;
+    /// let rrule = RRule::from_on_calendar("Mon..Fri *-*-* 09:30:00 Australia/Sydney").unwrap();
+    /// assert_eq!(
+    ///     "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;BYSECOND=0;TZID=Australia/Sydney",
+    ///     rrule.to_rrule_string()
+    /// );
+    /// ```
+    pub fn from_on_calendar(expression: &str) -> Result<RRule<'static>, OnCalendarError> {
+        let mut tokens: Vec<&str> = expression.split_whitespace().collect();
+        let mut tzid = "";
+        if let Some(last) = tokens.last() {
+            if tokens.len() > 1 && last.parse::<Tz>().is_ok() {
+                tzid = last;
+                tokens.pop();
+            }
+        }
+        let tokens: Vec<&str> = match tokens.as_slice() {
+            ["minutely"] => vec!["*-*-*", "*:*:00"],
+            ["hourly"] => vec!["*-*-*", "*:00:00"],
+            ["daily"] => vec!["*-*-*", "00:00:00"],
+            ["weekly"] => vec!["Mon", "*-*-*", "00:00:00"],
+            ["monthly"] => vec!["*-*-01", "00:00:00"],
+            ["quarterly"] => vec!["*-01,04,07,10-01", "00:00:00"],
+            ["semiannually"] => vec!["*-01,07-01", "00:00:00"],
+            ["yearly"] | ["annually"] => vec!["*-01-01", "00:00:00"],
+            [] => return Err(OnCalendarError::new("the calendar event is empty")),
+            tokens => tokens.to_vec(),
+        };
+
+        let mut fields = CalendarFields {
+            seconds: vec![0],
+            minutes: vec![0],
+            hours: vec![0],
+            tzid: tzid.to_string(),
+            ..CalendarFields::default()
+        };
+        let mut rest = tokens.as_slice();
+        if let Some((first, others)) = rest.split_first() {
+            if first.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                fields.weekdays = parse_weekdays(first.trim_end_matches(','))?;
+                rest = others;
+            }
+        }
+        if let Some((first, others)) = rest.split_first() {
+            if first.contains('-') && !first.contains(':') {
+                parse_date(first, &mut fields)?;
+                rest = others;
+            }
+        }
+        if let Some((first, others)) = rest.split_first() {
+            if first.contains(':') {
+                parse_time(first, &mut fields)?;
+                rest = others;
+            }
+        }
+        if let Some(unsupported) = rest.first() {
+            return Err(OnCalendarError::new(format!(
+                "unsupported or misplaced part {:?}, expected [weekdays] [date] [time] [timezone]",
+                unsupported
+            )));
+        }
+        if !fields.month_days.is_empty() && !fields.weekdays.is_empty() {
+            return Err(OnCalendarError::new(
+                "the event runs on the days matching both the date and the weekdays, a rule can't restrict both",
+            ));
+        }
+
+        let rrule = fields.into_rrule().map_err(OnCalendarError::new)?;
+        validate_rrule(&rrule).map_err(|error| OnCalendarError::new(error.to_string()))?;
+        Ok(rrule)
+    }
+}
+
+impl<'a> RRule<'a> {
+    /// Builds the systemd `OnCalendar=` calendar event the rule occurs on, in the normalized
+    /// form `systemd-analyze calendar` prints. DTSTART only provides the time of day, or
+    /// the day for weekly and coarser rules without BY parts. Rules a calendar event can't
+    /// express, such as ones with an INTERVAL, COUNT or UNTIL, return an error saying why.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;TZID=Australia/Sydney").unwrap();
+    /// assert_eq!(
+    ///     "Mon..Fri *-*-* 09:30:00 Australia/Sydney",
+    ///     rrule.to_on_calendar().unwrap()
+    /// );
+    /// ```
+    pub fn to_on_calendar(&self) -> Result<String, OnCalendarError> {
+        let fields = self
+            .calendar_fields("a systemd calendar event")
+            .map_err(OnCalendarError::new)?;
+        let field = |values: &[u32]| join_field(values, "..", |value| format!("{:02}", value));
+
+        let mut expression = String::new();
+        if !fields.weekdays.is_empty() {
+            let mut weekdays: Vec<u32> = fields
+                .weekdays
+                .iter()
+                .map(|weekday| weekday.num_days_from_monday())
+                .collect();
+            weekdays.sort_unstable();
+            weekdays.dedup();
+            expression.push_str(&join_day_ranges(&weekdays, "..", |day| {
+                SYSTEMD_WEEKDAY_NAMES[day as usize].to_string()
+            }));
+            expression.push(' ');
+        }
+        let seconds = if fields.seconds.is_empty() {
+            vec![0]
+        } else {
+            fields.seconds
+        };
+        expression.push_str(&format!(
+            "*-{}-{} {}:{}:{}",
+            field(&fields.months),
+            field(&fields.month_days),
+            field(&fields.hours),
+            field(&fields.minutes),
+            field(&seconds)
+        ));
+        if !fields.tzid.is_empty() {
+            expression.push_str(&format!(" {}", fields.tzid));
+        }
+        Ok(expression)
+    }
+}
+
+/// Weekday names or abbreviations separated by `,`, with `..` ranges
+fn parse_weekdays(spec: &str) -> Result<Vec<Weekday>, OnCalendarError> {
+    let weekday = |name: &str| {
+        SYSTEMD_WEEKDAY_NAMES
+            .iter()
+            .zip(SYSTEMD_WEEKDAY_FULL_NAMES.iter())
+            .position(|(short_name, full_name)| {
+                short_name.eq_ignore_ascii_case(name) || full_name.eq_ignore_ascii_case(name)
+            })
+            .ok_or_else(|| OnCalendarError::new(format!("unknown weekday {:?}", name)))
+    };
+
+    let mut weekdays = Vec::new();
+    for part in spec.split(',').filter(|part| !part.is_empty()) {
+        let (start, end) = match part.split_once("..") {
+            Some((start, end)) => (weekday(start)?, weekday(end)?),
+            None => (weekday(part)?, weekday(part)?),
+        };
+        if start > end {
+            return Err(OnCalendarError::new(format!(
+                "the weekday range {:?} runs backwards",
+                part
+            )));
+        }
+        weekdays.extend_from_slice(&SYSTEMD_WEEKDAYS[start..=end]);
+    }
+    Ok(weekdays)
+}
+
+/// `[year-]month-day`, the year can only be `*`
+fn parse_date(date: &str, fields: &mut CalendarFields) -> Result<(), OnCalendarError> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let (month, day) = match parts.as_slice() {
+        ["*", month, day] => (*month, *day),
+        [_, _, _] => {
+            return Err(OnCalendarError::new(
+                "a calendar event in a specific year can't be expressed as a recurring rule",
+            ))
+        }
+        [month, day] => (*month, *day),
+        _ => return Err(OnCalendarError::new(format!("invalid date {:?}", date))),
+    };
+    if day.contains('~') {
+        return Err(OnCalendarError::new(
+            "days counted from the end of the month with ~ aren't supported",
+        ));
+    }
+    fields.months = parse_value(month, "month", 1, 12)?;
+    fields.month_days = parse_value(day, "day", 1, 31)?;
+    Ok(())
+}
+
+/// `hour:minute[:second]`
+fn parse_time(time: &str, fields: &mut CalendarFields) -> Result<(), OnCalendarError> {
+    let parts: Vec<&str> = time.split(':').collect();
+    let (hour, minute, second) = match parts.as_slice() {
+        [hour, minute] => (*hour, *minute, "00"),
+        [hour, minute, second] => (*hour, *minute, *second),
+        _ => return Err(OnCalendarError::new(format!("invalid time {:?}", time))),
+    };
+    fields.hours = parse_value(hour, "hour", 0, 23)?;
+    fields.minutes = parse_value(minute, "minute", 0, 59)?;
+    fields.seconds = parse_value(second, "second", 0, 59)?;
+    if fields.seconds.is_empty() {
+        return Err(OnCalendarError::new(
+            "sundial can't run a calendar event on every second",
+        ));
+    }
+    Ok(())
+}
+
+/// `*` or a list of numbers, `a..b` ranges and `a/n` or `a..b/n` repetitions, as the
+/// values they match. Like `*`, a list matching every value is empty.
+fn parse_value(value: &str, name: &str, min: u32, max: u32) -> Result<Vec<u32>, OnCalendarError> {
+    if value == "*" {
+        return Ok(Vec::new());
+    }
+    let invalid = || {
+        OnCalendarError::new(format!(
+            "invalid {} {:?}, values are {}-{}",
+            name, value, min, max
+        ))
+    };
+    let number = |number: &str| match number.parse::<u32>() {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(invalid()),
+    };
+
+    let mut values = Vec::new();
+    for part in value.split(',') {
+        let (range, repetition) = match part.split_once('/') {
+            Some((range, repetition)) => (
+                range,
+                Some(repetition.parse::<u32>().map_err(|_| invalid())?),
+            ),
+            None => (part, None),
+        };
+        if repetition == Some(0) {
+            return Err(invalid());
+        }
+        let (start, end) = match range.split_once("..") {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => {
+                let start = number(range)?;
+                (start, if repetition.is_some() { max } else { start })
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        values.extend((start..=end).step_by(repetition.unwrap_or(1) as usize));
+    }
+    values.sort_unstable();
+    values.dedup();
+    if values.len() as u32 == max - min + 1 {
+        return Ok(Vec::new());
+    }
+    Ok(values)
+}
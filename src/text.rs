@@ -0,0 +1,869 @@
+use crate::{
+    chrono_weekday_to_rrule_byday, parse_local_date, split_byday, validate_rrule, Clock, DateForm,
+    RRule, SystemClock,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike, Weekday};
+use chrono_tz::Tz;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+impl<'a> RRule<'a> {
+    /// Describes the rule in English, in the style of rrule.js `toText`
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::convert_to_rrule;
+    /// let rrule = convert_to_rrule("FREQ=WEEKLY;INTERVAL=2;COUNT=3;BYDAY=TU;BYHOUR=23;BYMINUTE=54;TZID=Australia/Melbourne").unwrap();
+    /// assert_eq!(
+    ///     "every 2 weeks on Tuesday at 11:54 PM (Australia/Melbourne), 3 times",
+    ///     rrule.to_text()
+    /// );
+    /// ```
+    pub fn to_text(&self) -> String {
+        let interval: u32 = self.interval.parse().unwrap_or(1);
+        let (interval, unit) = match self.frequency.as_str() {
+            "YEARLY" => (interval, "year"),
+            "MONTHLY" => (interval, "month"),
+            "FORTNIGHTLY" => (interval * 2, "week"),
+            "WEEKLY" => (interval, "week"),
+            "DAILY" => (interval, "day"),
+            "HOURLY" => (interval, "hour"),
+            "MINUTELY" => (interval, "minute"),
+            "SECONDLY" => (interval, "second"),
+            _ => return String::from("never"),
+        };
+        let mut text = if interval == 1 {
+            format!("every {}", unit)
+        } else {
+            format!("every {} {}s", interval, unit)
+        };
+
+        if !self.by_month.is_empty() {
+            let months = self
+                .by_month
+                .iter()
+                .filter_map(|month| month.parse::<usize>().ok())
+                .filter_map(|month| MONTH_NAMES.get(month.wrapping_sub(1)))
+                .map(|month| month.to_string())
+                .collect::<Vec<String>>();
+            text.push_str(&format!(" in {}", join_list(&months)));
+        }
+        if !self.by_month_day.is_empty() {
+            let days = self
+                .by_month_day
+                .iter()
+                .filter_map(|day| day.parse::<u32>().ok())
+                .map(ordinal)
+                .collect::<Vec<String>>();
+            text.push_str(&format!(" on the {}", join_list(&days)));
+        }
+        if !self.by_year_day.is_empty() {
+            let days = self
+                .by_year_day
+                .iter()
+                .filter_map(|day| day.parse::<u32>().ok())
+                .map(ordinal)
+                .collect::<Vec<String>>();
+            text.push_str(&format!(" on the {} day of the year", join_list(&days)));
+        }
+        if !self.by_day.is_empty() {
+            text.push_str(&format!(" on {}", describe_weekdays(&self.by_day)));
+        }
+
+        let times = self.describe_times();
+        if !times.is_empty() {
+            text.push_str(&format!(" at {}", join_list(&times)));
+            if !self.tzid.is_empty() {
+                text.push_str(&format!(" ({})", self.tzid));
+            }
+        }
+
+        if !self.until.is_empty() {
+            let until = parse_local_date(&self.until);
+            text.push_str(&format!(
+                ", until {} {}, {}",
+                MONTH_NAMES[until.month0() as usize],
+                until.day(),
+                until.year()
+            ));
+        }
+        match self.count.parse::<u32>() {
+            Ok(1) => text.push_str(", once"),
+            Ok(count) => text.push_str(&format!(", {} times", count)),
+            Err(_) => {}
+        }
+        text
+    }
+
+    /// The times of day the rule occurs at, only known for daily and coarser rules with BYHOUR
+    fn describe_times(&self) -> Vec<String> {
+        if self.is_sub_daily() || self.by_hour.is_empty() {
+            return Vec::new();
+        }
+        // like the iteration, missing minutes and seconds are taken from DTSTART
+        let dtstart = self.local_dtstart();
+        let hours = numbers(&self.by_hour, None);
+        let minutes = numbers(&self.by_minute, dtstart.map(|dtstart| dtstart.minute()));
+        let seconds = numbers(&self.by_second, dtstart.map(|dtstart| dtstart.second()));
+        let mut times = Vec::new();
+        for hour in &hours {
+            for minute in &minutes {
+                for second in &seconds {
+                    times.push(format_time(*hour, *minute, *second));
+                }
+            }
+        }
+        times
+    }
+}
+
+/// The values of a part, or the one from DTSTART or 0 when the rule doesn't list any
+fn numbers(values: &[&str], from_dtstart: Option<u32>) -> Vec<u32> {
+    let mut numbers: Vec<u32> = values
+        .iter()
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    if numbers.is_empty() {
+        numbers.push(from_dtstart.unwrap_or(0));
+    }
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+}
+
+/// Formats a time of day on a 12 hour clock, seconds are only shown when set
+fn format_time(hour: u32, minute: u32, second: u32) -> String {
+    let meridiem = if hour < 12 { "AM" } else { "PM" };
+    let hour = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    if second == 0 {
+        format!("{}:{:02} {}", hour, minute, meridiem)
+    } else {
+        format!("{}:{:02}:{:02} {}", hour, minute, second, meridiem)
+    }
+}
+
+fn describe_weekdays(by_day: &[&str]) -> String {
+    let days = by_day
+        .iter()
+        .filter_map(|day| split_byday(day))
+        .collect::<Vec<(i32, Weekday)>>();
+    if days.iter().any(|(ordinal, _)| *ordinal != 0) {
+        return format!(
+            "the {} of the month",
+            join_list(
+                &days
+                    .iter()
+                    .map(|(ordinal, weekday)| describe_ordinal_weekday(*ordinal, *weekday))
+                    .collect::<Vec<String>>()
+            )
+        );
+    }
+    let weekdays = days
+        .iter()
+        .map(|(_, weekday)| *weekday)
+        .collect::<Vec<Weekday>>();
+    let weekdays_from_monday = |count: u32| {
+        weekdays.len() == count as usize
+            && (0..count).all(|day| {
+                weekdays
+                    .iter()
+                    .any(|weekday| weekday.num_days_from_monday() == day)
+            })
+    };
+    if weekdays_from_monday(7) {
+        String::from("every day of the week")
+    } else if weekdays_from_monday(5) {
+        String::from("weekdays")
+    } else {
+        join_list(
+            &weekdays
+                .iter()
+                .map(|weekday| weekday_name(*weekday).to_string())
+                .collect::<Vec<String>>(),
+        )
+    }
+}
+
+/// "last Friday", "2nd to last Monday" or "1st Tuesday"
+fn describe_ordinal_weekday(ordinal: i32, weekday: Weekday) -> String {
+    match ordinal {
+        0 => weekday_name(weekday).to_string(),
+        -1 => format!("last {}", weekday_name(weekday)),
+        ordinal if ordinal < 0 => format!(
+            "{} to last {}",
+            self::ordinal(-ordinal as u32),
+            weekday_name(weekday)
+        ),
+        ordinal => format!(
+            "{} {}",
+            self::ordinal(ordinal as u32),
+            weekday_name(weekday)
+        ),
+    }
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// 1st, 2nd, 3rd, 4th, ..., 11th, 12th, 13th, ..., 21st
+fn ordinal(number: u32) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+/// Joins words as English does, "a", "a and b", "a, b and c"
+fn join_list(words: &[String]) -> String {
+    match words.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// The numbers BY part values are taken from when a rule is built from text or cron, so
+/// the rule doesn't borrow from its input
+pub(crate) const NUMBERS: [&str; 61] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+    "17", "18", "19", "20", "21", "22", "23", "24", "25", "26", "27", "28", "29", "30", "31", "32",
+    "33", "34", "35", "36", "37", "38", "39", "40", "41", "42", "43", "44", "45", "46", "47", "48",
+    "49", "50", "51", "52", "53", "54", "55", "56", "57", "58", "59", "60",
+];
+
+/// BYDAY values with an ordinal, the rows are -1 (last) and 1 to 5, the columns start on Monday
+const ORDINAL_WEEKDAYS: [[&str; 7]; 6] = [
+    ["-1MO", "-1TU", "-1WE", "-1TH", "-1FR", "-1SA", "-1SU"],
+    ["1MO", "1TU", "1WE", "1TH", "1FR", "1SA", "1SU"],
+    ["2MO", "2TU", "2WE", "2TH", "2FR", "2SA", "2SU"],
+    ["3MO", "3TU", "3WE", "3TH", "3FR", "3SA", "3SU"],
+    ["4MO", "4TU", "4WE", "4TH", "4FR", "4SA", "4SU"],
+    ["5MO", "5TU", "5WE", "5TH", "5FR", "5SA", "5SU"],
+];
+
+const WEEKDAYS: [Weekday; 5] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+];
+
+/// Returned when `RRule::from_text` can't turn a phrase into a rule
+#[derive(Debug, Clone)]
+pub struct TextParseError {
+    message: String,
+}
+
+impl TextParseError {
+    fn new(message: String) -> TextParseError {
+        TextParseError { message }
+    }
+}
+
+impl Display for TextParseError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "could not understand the schedule: {}", self.message)
+    }
+}
+
+impl Error for TextParseError {
+    fn description(&self) -> &str {
+        "could not understand the schedule"
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl RRule<'static> {
+    /// Builds a rule from an English phrase such as "every other Tuesday at 9am until end
+    /// of March", "weekdays at 7:30" or "last Friday of each month". The rule is validated
+    /// and phrases that aren't supported are returned as an error naming the words.
+    ///
+    /// Example:
+    /// ```
+    /// use sundial::RRule;
+    /// let rrule = RRule::from_text("weekdays at 7:30").unwrap();
+    /// assert_eq!(
+    ///     "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=7;BYMINUTE=30;BYSECOND=0",
+    ///     rrule.to_rrule_string()
+    /// );
+    /// ```
+    pub fn from_text(text: &str) -> Result<RRule<'static>, TextParseError> {
+        RRule::from_text_with_clock(text, &SystemClock)
+    }
+
+    /// Same as `from_text` but reads today's date from the given `clock`, which is used
+    /// to pick the year of dates given without one such as "until end of March"
+    pub fn from_text_with_clock(
+        text: &str,
+        clock: &dyn Clock,
+    ) -> Result<RRule<'static>, TextParseError> {
+        let mut words = Words::new(text);
+        let mut phrase = Phrase::default();
+        let today = clock.now().naive_utc().date();
+        while let Some(word) = words.next() {
+            phrase.read(&word, &mut words, today)?;
+        }
+        let rrule = phrase.into_rrule()?;
+        validate_rrule(&rrule).map_err(|error| TextParseError::new(error.to_string()))?;
+        Ok(rrule)
+    }
+}
+
+/// The words of a phrase, lower cased for matching. The original words are kept for
+/// timezone names.
+struct Words<'t> {
+    words: Vec<&'t str>,
+    position: usize,
+}
+
+impl<'t> Words<'t> {
+    fn new(text: &'t str) -> Words<'t> {
+        Words {
+            words: text
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .map(|word| word.trim_end_matches('.'))
+                .filter(|word| !word.is_empty())
+                .collect(),
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<String> {
+        self.words
+            .get(self.position)
+            .map(|word| word.to_lowercase())
+    }
+
+    fn original(&self) -> Option<&'t str> {
+        self.position
+            .checked_sub(1)
+            .and_then(|position| self.words.get(position).copied())
+    }
+
+    /// Consumes the next word when it is one of `expected`
+    fn next_if(&mut self, expected: &[&str]) -> bool {
+        match self.peek() {
+            Some(word) if expected.contains(&word.as_str()) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'t> Iterator for Words<'t> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let word = self.peek();
+        if word.is_some() {
+            self.position += 1;
+        }
+        word
+    }
+}
+
+/// The parts of a rule read from a phrase so far
+#[derive(Default)]
+struct Phrase {
+    frequency: Option<&'static str>,
+    interval: u32,
+    weekdays: Vec<Weekday>,
+    ordinal_weekday: Option<(i32, Weekday)>,
+    month_day: Option<u32>,
+    time: Option<(u32, u32)>,
+    until: Option<NaiveDate>,
+    count: Option<u32>,
+    start: Option<NaiveDate>,
+    tzid: Option<String>,
+}
+
+impl Phrase {
+    fn read(
+        &mut self,
+        word: &str,
+        words: &mut Words,
+        today: NaiveDate,
+    ) -> Result<(), TextParseError> {
+        match word {
+            "and" | "on" | "the" => {}
+            "every" | "each" => self.read_every(words)?,
+            "daily" => self.set_frequency("DAILY", 1)?,
+            "weekly" => self.set_frequency("WEEKLY", 1)?,
+            "fortnightly" => self.set_frequency("WEEKLY", 2)?,
+            "monthly" => self.set_frequency("MONTHLY", 1)?,
+            "yearly" | "annually" => self.set_frequency("YEARLY", 1)?,
+            "hourly" => self.set_frequency("HOURLY", 1)?,
+            "weekday" | "weekdays" => self.weekdays.extend_from_slice(&WEEKDAYS),
+            "weekend" | "weekends" => self
+                .weekdays
+                .extend_from_slice(&[Weekday::Sat, Weekday::Sun]),
+            "of" => {
+                words.next_if(&["each", "every", "the"]);
+                if !words.next_if(&["month"]) {
+                    return Err(unsupported("of", words));
+                }
+                self.set_frequency("MONTHLY", 1)?;
+            }
+            "at" => {
+                if self.time.is_some() {
+                    return Err(TextParseError::new(String::from(
+                        "only one time of day is supported",
+                    )));
+                }
+                self.time = Some(read_time(words)?);
+            }
+            "until" => {
+                self.until = Some(read_date(words, today).ok_or_else(|| {
+                    TextParseError::new(String::from(
+                        "\"until\" needs a date such as \"March 31\", \"2019-03-31\" or \"end of March\"",
+                    ))
+                })?);
+            }
+            "starting" | "from" | "beginning" => {
+                words.next_if(&["on"]);
+                self.start = Some(read_date(words, today).ok_or_else(|| {
+                    TextParseError::new(format!(
+                        "\"{}\" needs a date such as \"March 31\" or \"2019-03-31\"",
+                        word
+                    ))
+                })?);
+            }
+            "once" => self.count = Some(1),
+            "twice" => self.count = Some(2),
+            "for" => {
+                let count = words.next().and_then(|count| parse_number(&count));
+                match count {
+                    Some(count) if words.next_if(&["times", "occurrences"]) => {
+                        self.count = Some(count)
+                    }
+                    _ => {
+                        return Err(TextParseError::new(String::from(
+                            "\"for\" needs a number of times such as \"for 5 times\"",
+                        )))
+                    }
+                }
+            }
+            "in" => {
+                let tzid = words.next().and(words.original()).unwrap_or("");
+                if !tzid.contains('/') {
+                    return Err(unsupported("in", words));
+                }
+                self.tzid = Some(tzid.to_string());
+            }
+            _ => self.read_other(word, words)?,
+        }
+        Ok(())
+    }
+
+    /// Words that aren't keywords: weekdays, ordinals, counts, times and timezones
+    fn read_other(&mut self, word: &str, words: &mut Words) -> Result<(), TextParseError> {
+        if let Some(weekday) = parse_weekday(word) {
+            self.weekdays.push(weekday);
+        } else if let Some(ordinal) = parse_ordinal(word) {
+            match words.peek().and_then(|next| parse_weekday(&next)) {
+                Some(weekday) if (-1..=5).contains(&ordinal) => {
+                    words.next();
+                    if self.ordinal_weekday.is_some() {
+                        return Err(TextParseError::new(String::from(
+                            "only one weekday of the month is supported",
+                        )));
+                    }
+                    self.ordinal_weekday = Some((ordinal, weekday));
+                }
+                _ if word.chars().next().is_some_and(|c| c.is_ascii_digit()) => {
+                    if self.month_day.is_some() {
+                        return Err(TextParseError::new(String::from(
+                            "only one day of the month is supported",
+                        )));
+                    }
+                    self.month_day = Some(ordinal as u32);
+                }
+                _ => return Err(unsupported(word, words)),
+            }
+        } else if let Some(count) = parse_number(word).filter(|_| words.next_if(&["times"])) {
+            self.count = Some(count);
+        } else if word.starts_with('(') && word.ends_with(')') && word.contains('/') {
+            let tzid = words.original().unwrap_or("");
+            self.tzid = Some(tzid[1..tzid.len() - 1].to_string());
+        } else if let Some(time) = parse_time(word, None).filter(|_| word.ends_with('m')) {
+            self.time = Some(time);
+        } else {
+            return Err(unsupported(word, words));
+        }
+        Ok(())
+    }
+
+    /// "every" followed by an optional interval and a unit or weekday
+    fn read_every(&mut self, words: &mut Words) -> Result<(), TextParseError> {
+        let interval = if words.next_if(&["other"]) {
+            2
+        } else {
+            match words.peek().and_then(|word| parse_number(&word)) {
+                Some(interval) => {
+                    words.next();
+                    interval
+                }
+                None => 1,
+            }
+        };
+        let unit = words.next().unwrap_or_default();
+        match unit.trim_end_matches('s') {
+            "second" => self.set_frequency("SECONDLY", interval),
+            "minute" => self.set_frequency("MINUTELY", interval),
+            "hour" => self.set_frequency("HOURLY", interval),
+            "day" => self.set_frequency("DAILY", interval),
+            "week" => self.set_frequency("WEEKLY", interval),
+            "fortnight" => self.set_frequency("WEEKLY", interval * 2),
+            "month" => self.set_frequency("MONTHLY", interval),
+            "year" => self.set_frequency("YEARLY", interval),
+            "weekday" if interval == 1 => {
+                self.weekdays.extend_from_slice(&WEEKDAYS);
+                Ok(())
+            }
+            "weekend" => {
+                self.weekdays
+                    .extend_from_slice(&[Weekday::Sat, Weekday::Sun]);
+                self.set_frequency("WEEKLY", interval)
+            }
+            _ => match parse_weekday(&unit) {
+                Some(weekday) => {
+                    self.weekdays.push(weekday);
+                    self.set_frequency("WEEKLY", interval)
+                }
+                None => Err(TextParseError::new(format!(
+                    "\"every\" needs a unit such as \"day\", \"week\" or \"Tuesday\", not {:?}",
+                    unit
+                ))),
+            },
+        }
+    }
+
+    fn set_frequency(
+        &mut self,
+        frequency: &'static str,
+        interval: u32,
+    ) -> Result<(), TextParseError> {
+        match self.frequency {
+            Some(current) if current != frequency || self.interval != interval => {
+                Err(TextParseError::new(String::from(
+                    "the phrase says how often it repeats more than once",
+                )))
+            }
+            _ => {
+                self.frequency = Some(frequency);
+                self.interval = interval;
+                Ok(())
+            }
+        }
+    }
+
+    fn into_rrule(self) -> Result<RRule<'static>, TextParseError> {
+        let frequency = match self.frequency {
+            Some(frequency) => frequency,
+            None if self.ordinal_weekday.is_some() || self.month_day.is_some() => "MONTHLY",
+            None if !self.weekdays.is_empty() => "WEEKLY",
+            None if self.time.is_some() => "DAILY",
+            None => {
+                return Err(TextParseError::new(String::from(
+                    "the phrase doesn't say how often it repeats, such as \"every day\"",
+                )))
+            }
+        };
+        let sub_daily = ["HOURLY", "MINUTELY", "SECONDLY"].contains(&frequency);
+        if self.time.is_some() && sub_daily {
+            return Err(TextParseError::new(String::from(
+                "a time of day can't be given for rules repeating more than once a day",
+            )));
+        }
+        if (self.ordinal_weekday.is_some() || self.month_day.is_some()) && frequency != "MONTHLY" {
+            return Err(TextParseError::new(String::from(
+                "days of the month such as \"last Friday\" or \"the 15th\" need a monthly rule",
+            )));
+        }
+        if self.ordinal_weekday.is_some() && (!self.weekdays.is_empty() || self.month_day.is_some())
+        {
+            return Err(TextParseError::new(String::from(
+                "a weekday of the month can't be combined with other days",
+            )));
+        }
+
+        let timezone: Tz = match &self.tzid {
+            Some(tzid) => tzid
+                .parse()
+                .map_err(|_| TextParseError::new(format!("unknown timezone {:?}", tzid)))?,
+            None => Tz::UTC,
+        };
+        let local_to_utc = |date: NaiveDateTime| {
+            timezone
+                .from_local_datetime(&date)
+                .earliest()
+                .map(|date| date.naive_utc().to_string())
+                .unwrap_or_else(|| date.to_string())
+        };
+
+        let mut rrule = RRule::new();
+        rrule.frequency = frequency.to_string();
+        if self.interval > 1 {
+            rrule.interval = self.interval.to_string();
+        }
+        if let Some(count) = self.count {
+            rrule.count = count.to_string();
+        }
+        if let Some(until) = self.until {
+            rrule.until = local_to_utc(until.and_hms(23, 59, 59));
+            rrule.until_form = DateForm::Utc;
+        }
+        if let Some(start) = self.start {
+            rrule.dtstart = local_to_utc(start.and_hms(0, 0, 0));
+            rrule.dtstart_form = DateForm::Utc;
+        }
+        if let Some(tzid) = self.tzid {
+            rrule.tzid = tzid;
+        }
+        let mut weekdays = self.weekdays;
+        weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+        weekdays.dedup();
+        rrule.by_day = weekdays
+            .into_iter()
+            .map(chrono_weekday_to_rrule_byday)
+            .collect();
+        if let Some((ordinal, weekday)) = self.ordinal_weekday {
+            let row = if ordinal < 0 { 0 } else { ordinal as usize };
+            rrule.by_day = vec![ORDINAL_WEEKDAYS[row][weekday.num_days_from_monday() as usize]];
+        }
+        if let Some(month_day) = self.month_day {
+            rrule.by_month_day =
+                vec![NUMBERS.get(month_day as usize).copied().ok_or_else(|| {
+                    TextParseError::new(format!(
+                        "there is no {} day of the month",
+                        ordinal(month_day)
+                    ))
+                })?];
+        }
+        if let Some((hour, minute)) = self.time {
+            rrule.by_hour = vec![NUMBERS[hour as usize]];
+            rrule.by_minute = vec![NUMBERS[minute as usize]];
+            rrule.by_second = vec![NUMBERS[0]];
+        }
+        Ok(rrule)
+    }
+}
+
+fn unsupported(word: &str, words: &Words) -> TextParseError {
+    TextParseError::new(format!(
+        "unsupported phrase {:?} at word {}",
+        word, words.position
+    ))
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    let word = word.trim_end_matches('s');
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .iter()
+    .copied()
+    .find(|weekday| {
+        let name = weekday_name(*weekday).to_lowercase();
+        word == name || (word.len() >= 3 && name.starts_with(word))
+    })
+}
+
+fn parse_month(word: &str) -> Option<u32> {
+    MONTH_NAMES
+        .iter()
+        .position(|month| {
+            let month = month.to_lowercase();
+            word == month || (word.len() >= 3 && month.starts_with(word))
+        })
+        .map(|month| month as u32 + 1)
+}
+
+/// "two" to "twelve" and digits
+fn parse_number(word: &str) -> Option<u32> {
+    const WORDS: [&str; 11] = [
+        "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    ];
+    match WORDS.iter().position(|number| *number == word) {
+        Some(position) => Some(position as u32 + 2),
+        None => word.parse().ok(),
+    }
+}
+
+/// "first" to "fifth", "last" as -1 and numbers such as "15th"
+fn parse_ordinal(word: &str) -> Option<i32> {
+    const WORDS: [&str; 5] = ["first", "second", "third", "fourth", "fifth"];
+    if word == "last" {
+        return Some(-1);
+    }
+    if let Some(position) = WORDS.iter().position(|ordinal| *ordinal == word) {
+        return Some(position as i32 + 1);
+    }
+    let number = word
+        .strip_suffix("st")
+        .or_else(|| word.strip_suffix("nd"))
+        .or_else(|| word.strip_suffix("rd"))
+        .or_else(|| word.strip_suffix("th"))?;
+    number.parse().ok().filter(|number| *number > 0)
+}
+
+/// The time after "at", "9am", "9 am", "7:30", "17:00", "noon" or "midnight"
+fn read_time(words: &mut Words) -> Result<(u32, u32), TextParseError> {
+    let word = words.next().unwrap_or_default();
+    let time = match word.as_str() {
+        "noon" | "midday" => Some((12, 0)),
+        "midnight" => Some((0, 0)),
+        _ => {
+            let meridiem = words.peek().filter(|next| next == "am" || next == "pm");
+            if meridiem.is_some() {
+                words.next();
+            }
+            parse_time(&word, meridiem.as_deref())
+        }
+    };
+    time.ok_or_else(|| {
+        TextParseError::new(format!(
+            "\"at\" needs a time such as 9am or 17:30, not {:?}",
+            word
+        ))
+    })
+}
+
+/// Parses "9", "9:30", "9am" or "9:30pm", with `meridiem` given as a separate word
+fn parse_time(word: &str, meridiem: Option<&str>) -> Option<(u32, u32)> {
+    let (time, meridiem) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(time), _) => (time, Some("am")),
+        (_, Some(time)) => (time, Some("pm")),
+        _ => (word, meridiem),
+    };
+    let mut parts = time.splitn(2, ':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = match parts.next() {
+        Some(minute) if minute.len() == 2 => minute.parse().ok()?,
+        Some(_) => return None,
+        None => 0,
+    };
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some("am") => hour % 12,
+        Some(_) => hour % 12 + 12,
+        None => hour,
+    };
+    if hour > 23 || minute > 59 {
+        None
+    } else {
+        Some((hour, minute))
+    }
+}
+
+/// The date after "until" or "starting": "2019-03-31", "March 31 2019", "31st of March",
+/// or "end of March". Dates without a year are the next one from `today`.
+fn read_date(words: &mut Words, today: NaiveDate) -> Option<NaiveDate> {
+    words.next_if(&["the"]);
+    let word = words.next()?;
+    if let Ok(date) = NaiveDate::parse_from_str(&word, "%Y-%m-%d") {
+        return Some(date);
+    }
+
+    let (month, day) = if word == "end" {
+        words.next_if(&["of"]);
+        (parse_month(&words.next()?)?, None)
+    } else if let Some(month) = parse_month(&word) {
+        let day = words.peek().and_then(|day| parse_day(&day));
+        if day.is_some() {
+            words.next();
+        }
+        (month, day)
+    } else {
+        let day = parse_day(&word)?;
+        words.next_if(&["of"]);
+        (parse_month(&words.next()?)?, Some(day))
+    };
+
+    let year = words
+        .peek()
+        .filter(|year| year.len() == 4)
+        .and_then(|year| year.parse::<i32>().ok());
+    if year.is_some() {
+        words.next();
+    }
+    let date_in = |year: i32| match day {
+        Some(day) => NaiveDate::from_ymd_opt(year, month, day),
+        None => NaiveDate::from_ymd_opt(year, month, 1).map(|first| add_months(first, 1).pred()),
+    };
+    match year {
+        Some(year) => date_in(year),
+        None => {
+            let date = date_in(today.year())?;
+            if date < today {
+                date_in(today.year() + 1)
+            } else {
+                Some(date)
+            }
+        }
+    }
+}
+
+fn parse_day(word: &str) -> Option<u32> {
+    let day = parse_ordinal(word)
+        .map(|day| day as u32)
+        .or_else(|| word.parse().ok())?;
+    if (1..=31).contains(&day) {
+        Some(day)
+    } else {
+        None
+    }
+}
+
+/// The first day of the month `months` after the month of `date`
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    let month_index = date.year() * 12 + date.month0() as i32 + months as i32;
+    NaiveDate::from_ymd(
+        month_index.div_euclid(12),
+        month_index.rem_euclid(12) as u32 + 1,
+        1,
+    )
+}
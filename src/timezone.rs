@@ -0,0 +1,300 @@
+use chrono::{FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+
+/// The rules of a timezone, i.e. which UTC offset is in effect at any given time.
+///
+/// Implemented for chrono-tz's `Tz` and for zones defined by a VTIMEZONE component,
+/// see `VTimeZone`.
+pub trait ZoneRules: Debug + Send + Sync {
+    /// The UTC offset in effect at the given UTC date time
+    fn offset_from_utc(&self, utc: &NaiveDateTime) -> FixedOffset;
+
+    /// The UTC offsets a local wall clock time can have, none when it falls in a gap
+    /// and two when it happens twice
+    fn offset_from_local(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset>;
+}
+
+impl ZoneRules for Tz {
+    fn offset_from_utc(&self, utc: &NaiveDateTime) -> FixedOffset {
+        self.offset_from_utc_datetime(utc).fix()
+    }
+
+    fn offset_from_local(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+        self.offset_from_local_datetime(local)
+            .map(|offset| offset.fix())
+    }
+}
+
+/// Looks up the rules of a timezone from the TZID of a rule
+pub trait TimeZoneProvider {
+    fn zone(&self, tzid: &str) -> Option<Box<dyn ZoneRules>>;
+}
+
+/// Provides the IANA timezones known to chrono-tz, Windows timezone names such as the
+/// ones used in Outlook exports are mapped to their IANA equivalent.
+///
+/// Example:
+/// ```
+/// use sundial::{ChronoTzProvider, TimeZoneProvider};
+/// assert!(ChronoTzProvider.zone("Australia/Melbourne").is_some());
+/// assert!(ChronoTzProvider.zone("AUS Eastern Standard Time").is_some());
+/// assert!(ChronoTzProvider.zone("Middle Earth/Mordor").is_none());
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChronoTzProvider;
+
+impl TimeZoneProvider for ChronoTzProvider {
+    fn zone(&self, tzid: &str) -> Option<Box<dyn ZoneRules>> {
+        parse_tzid(tzid).map(|tz| Box::new(tz) as Box<dyn ZoneRules>)
+    }
+}
+
+/// Parses an IANA timezone name, falling back to the Windows timezone names
+pub(crate) fn parse_tzid(tzid: &str) -> Option<Tz> {
+    tzid.parse::<Tz>()
+        .ok()
+        .or_else(|| windows_to_iana(tzid).and_then(|iana| iana.parse().ok()))
+}
+
+/// Maps a Windows timezone name to the IANA timezone CLDR considers its main equivalent
+///
+/// Example:
+/// ```
+/// use sundial::windows_to_iana;
+/// assert_eq!(Some("Australia/Perth"), windows_to_iana("W. Australia Standard Time"));
+/// ```
+pub fn windows_to_iana(windows_name: &str) -> Option<&'static str> {
+    WINDOWS_ZONES
+        .iter()
+        .find(|(windows, _)| windows.eq_ignore_ascii_case(windows_name.trim()))
+        .map(|(_, iana)| *iana)
+}
+
+/// Windows timezone names and their IANA equivalent for the "001" territory of the
+/// CLDR windowsZones table
+pub(crate) const WINDOWS_ZONES: &[(&str, &str)] = &[
+    ("Dateline Standard Time", "Etc/GMT+12"),
+    ("UTC-11", "Etc/GMT+11"),
+    ("Aleutian Standard Time", "America/Adak"),
+    ("Hawaiian Standard Time", "Pacific/Honolulu"),
+    ("Marquesas Standard Time", "Pacific/Marquesas"),
+    ("Alaskan Standard Time", "America/Anchorage"),
+    ("UTC-09", "Etc/GMT+9"),
+    ("Pacific Standard Time (Mexico)", "America/Tijuana"),
+    ("UTC-08", "Etc/GMT+8"),
+    ("Pacific Standard Time", "America/Los_Angeles"),
+    ("US Mountain Standard Time", "America/Phoenix"),
+    ("Mountain Standard Time (Mexico)", "America/Chihuahua"),
+    ("Mountain Standard Time", "America/Denver"),
+    ("Central America Standard Time", "America/Guatemala"),
+    ("Central Standard Time", "America/Chicago"),
+    ("Easter Island Standard Time", "Pacific/Easter"),
+    ("Central Standard Time (Mexico)", "America/Mexico_City"),
+    ("Canada Central Standard Time", "America/Regina"),
+    ("SA Pacific Standard Time", "America/Bogota"),
+    ("Eastern Standard Time (Mexico)", "America/Cancun"),
+    ("Eastern Standard Time", "America/New_York"),
+    ("Haiti Standard Time", "America/Port-au-Prince"),
+    ("Cuba Standard Time", "America/Havana"),
+    ("US Eastern Standard Time", "America/Indiana/Indianapolis"),
+    ("Turks And Caicos Standard Time", "America/Grand_Turk"),
+    ("Paraguay Standard Time", "America/Asuncion"),
+    ("Atlantic Standard Time", "America/Halifax"),
+    ("Venezuela Standard Time", "America/Caracas"),
+    ("Central Brazilian Standard Time", "America/Cuiaba"),
+    ("SA Western Standard Time", "America/La_Paz"),
+    ("Pacific SA Standard Time", "America/Santiago"),
+    ("Newfoundland Standard Time", "America/St_Johns"),
+    ("Tocantins Standard Time", "America/Araguaina"),
+    ("E. South America Standard Time", "America/Sao_Paulo"),
+    ("SA Eastern Standard Time", "America/Cayenne"),
+    ("Argentina Standard Time", "America/Argentina/Buenos_Aires"),
+    ("Greenland Standard Time", "America/Godthab"),
+    ("Montevideo Standard Time", "America/Montevideo"),
+    ("Magallanes Standard Time", "America/Punta_Arenas"),
+    ("Saint Pierre Standard Time", "America/Miquelon"),
+    ("Bahia Standard Time", "America/Bahia"),
+    ("UTC-02", "Etc/GMT+2"),
+    ("Azores Standard Time", "Atlantic/Azores"),
+    ("Cape Verde Standard Time", "Atlantic/Cape_Verde"),
+    ("UTC", "Etc/UTC"),
+    ("GMT Standard Time", "Europe/London"),
+    ("Greenwich Standard Time", "Atlantic/Reykjavik"),
+    ("Sao Tome Standard Time", "Africa/Sao_Tome"),
+    ("Morocco Standard Time", "Africa/Casablanca"),
+    ("W. Europe Standard Time", "Europe/Berlin"),
+    ("Central Europe Standard Time", "Europe/Budapest"),
+    ("Romance Standard Time", "Europe/Paris"),
+    ("Central European Standard Time", "Europe/Warsaw"),
+    ("W. Central Africa Standard Time", "Africa/Lagos"),
+    ("Jordan Standard Time", "Asia/Amman"),
+    ("GTB Standard Time", "Europe/Bucharest"),
+    ("Middle East Standard Time", "Asia/Beirut"),
+    ("Egypt Standard Time", "Africa/Cairo"),
+    ("E. Europe Standard Time", "Europe/Chisinau"),
+    ("Syria Standard Time", "Asia/Damascus"),
+    ("West Bank Standard Time", "Asia/Hebron"),
+    ("South Africa Standard Time", "Africa/Johannesburg"),
+    ("FLE Standard Time", "Europe/Kiev"),
+    ("Israel Standard Time", "Asia/Jerusalem"),
+    ("Kaliningrad Standard Time", "Europe/Kaliningrad"),
+    ("Sudan Standard Time", "Africa/Khartoum"),
+    ("Libya Standard Time", "Africa/Tripoli"),
+    ("Namibia Standard Time", "Africa/Windhoek"),
+    ("Arabic Standard Time", "Asia/Baghdad"),
+    ("Turkey Standard Time", "Europe/Istanbul"),
+    ("Arab Standard Time", "Asia/Riyadh"),
+    ("Belarus Standard Time", "Europe/Minsk"),
+    ("Russian Standard Time", "Europe/Moscow"),
+    ("E. Africa Standard Time", "Africa/Nairobi"),
+    ("Volgograd Standard Time", "Europe/Volgograd"),
+    ("Iran Standard Time", "Asia/Tehran"),
+    ("Arabian Standard Time", "Asia/Dubai"),
+    ("Astrakhan Standard Time", "Europe/Astrakhan"),
+    ("Azerbaijan Standard Time", "Asia/Baku"),
+    ("Russia Time Zone 3", "Europe/Samara"),
+    ("Mauritius Standard Time", "Indian/Mauritius"),
+    ("Saratov Standard Time", "Europe/Saratov"),
+    ("Georgian Standard Time", "Asia/Tbilisi"),
+    ("Caucasus Standard Time", "Asia/Yerevan"),
+    ("Afghanistan Standard Time", "Asia/Kabul"),
+    ("West Asia Standard Time", "Asia/Tashkent"),
+    ("Ekaterinburg Standard Time", "Asia/Yekaterinburg"),
+    ("Pakistan Standard Time", "Asia/Karachi"),
+    ("India Standard Time", "Asia/Kolkata"),
+    ("Sri Lanka Standard Time", "Asia/Colombo"),
+    ("Nepal Standard Time", "Asia/Kathmandu"),
+    ("Central Asia Standard Time", "Asia/Almaty"),
+    ("Bangladesh Standard Time", "Asia/Dhaka"),
+    ("Omsk Standard Time", "Asia/Omsk"),
+    ("Myanmar Standard Time", "Asia/Yangon"),
+    ("SE Asia Standard Time", "Asia/Bangkok"),
+    ("Altai Standard Time", "Asia/Barnaul"),
+    ("W. Mongolia Standard Time", "Asia/Hovd"),
+    ("North Asia Standard Time", "Asia/Krasnoyarsk"),
+    ("N. Central Asia Standard Time", "Asia/Novosibirsk"),
+    ("Tomsk Standard Time", "Asia/Tomsk"),
+    ("China Standard Time", "Asia/Shanghai"),
+    ("North Asia East Standard Time", "Asia/Irkutsk"),
+    ("Singapore Standard Time", "Asia/Singapore"),
+    ("W. Australia Standard Time", "Australia/Perth"),
+    ("Taipei Standard Time", "Asia/Taipei"),
+    ("Ulaanbaatar Standard Time", "Asia/Ulaanbaatar"),
+    ("Aus Central W. Standard Time", "Australia/Eucla"),
+    ("Transbaikal Standard Time", "Asia/Chita"),
+    ("Tokyo Standard Time", "Asia/Tokyo"),
+    ("North Korea Standard Time", "Asia/Pyongyang"),
+    ("Korea Standard Time", "Asia/Seoul"),
+    ("Yakutsk Standard Time", "Asia/Yakutsk"),
+    ("Cen. Australia Standard Time", "Australia/Adelaide"),
+    ("AUS Central Standard Time", "Australia/Darwin"),
+    ("E. Australia Standard Time", "Australia/Brisbane"),
+    ("AUS Eastern Standard Time", "Australia/Sydney"),
+    ("West Pacific Standard Time", "Pacific/Port_Moresby"),
+    ("Tasmania Standard Time", "Australia/Hobart"),
+    ("Vladivostok Standard Time", "Asia/Vladivostok"),
+    ("Lord Howe Standard Time", "Australia/Lord_Howe"),
+    ("Bougainville Standard Time", "Pacific/Bougainville"),
+    ("Russia Time Zone 10", "Asia/Srednekolymsk"),
+    ("Magadan Standard Time", "Asia/Magadan"),
+    ("Norfolk Standard Time", "Pacific/Norfolk"),
+    ("Sakhalin Standard Time", "Asia/Sakhalin"),
+    ("Central Pacific Standard Time", "Pacific/Guadalcanal"),
+    ("Russia Time Zone 11", "Asia/Kamchatka"),
+    ("New Zealand Standard Time", "Pacific/Auckland"),
+    ("UTC+12", "Etc/GMT-12"),
+    ("Fiji Standard Time", "Pacific/Fiji"),
+    ("Chatham Islands Standard Time", "Pacific/Chatham"),
+    ("UTC+13", "Etc/GMT-13"),
+    ("Tonga Standard Time", "Pacific/Tongatapu"),
+    ("Samoa Standard Time", "Pacific/Apia"),
+    ("Line Islands Standard Time", "Pacific/Kiritimati"),
+];
+
+/// The rules of a zone only a `TimeZoneProvider` knows about, resolved when the rule was
+/// parsed. Rules are compared by their TZID, so any two provided zones compare equal.
+#[derive(Debug, Clone)]
+pub(crate) struct ProvidedZone(Arc<dyn ZoneRules>);
+
+impl ProvidedZone {
+    pub(crate) fn new(rules: Box<dyn ZoneRules>) -> ProvidedZone {
+        ProvidedZone(Arc::from(rules))
+    }
+
+    pub(crate) fn rules(&self) -> &dyn ZoneRules {
+        self.0.as_ref()
+    }
+}
+
+impl PartialEq for ProvidedZone {
+    fn eq(&self, _other: &ProvidedZone) -> bool {
+        true
+    }
+}
+
+/// The zone a rule is evaluated in, adapting chrono-tz's `Tz` and the `ZoneRules` of a
+/// `TimeZoneProvider` to chrono's `TimeZone`
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RuleTz<'z> {
+    Chrono(Tz),
+    Provided(&'z dyn ZoneRules),
+}
+
+impl<'z> RuleTz<'z> {
+    fn rules(&self) -> &dyn ZoneRules {
+        match self {
+            RuleTz::Chrono(tz) => tz,
+            RuleTz::Provided(rules) => *rules,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RuleOffset<'z> {
+    zone: RuleTz<'z>,
+    offset: FixedOffset,
+}
+
+impl<'z> Offset for RuleOffset<'z> {
+    fn fix(&self) -> FixedOffset {
+        self.offset
+    }
+}
+
+impl<'z> Display for RuleOffset<'z> {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.offset)
+    }
+}
+
+impl<'z> TimeZone for RuleTz<'z> {
+    type Offset = RuleOffset<'z>;
+
+    fn from_offset(offset: &RuleOffset<'z>) -> RuleTz<'z> {
+        offset.zone
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<RuleOffset<'z>> {
+        self.offset_from_local_datetime(&local.and_hms(0, 0, 0))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<RuleOffset<'z>> {
+        let zone = *self;
+        self.rules()
+            .offset_from_local(local)
+            .map(|offset| RuleOffset { zone, offset })
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> RuleOffset<'z> {
+        self.offset_from_utc_datetime(&utc.and_hms(0, 0, 0))
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> RuleOffset<'z> {
+        RuleOffset {
+            zone: *self,
+            offset: self.rules().offset_from_utc(utc),
+        }
+    }
+}
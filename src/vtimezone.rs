@@ -0,0 +1,446 @@
+use crate::event::unfold_ics_lines;
+use crate::split_byday;
+use crate::timezone::{ChronoTzProvider, TimeZoneProvider, ZoneRules};
+use chrono::{Datelike, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Weekday};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// A timezone defined by an iCalendar VTIMEZONE component, as embedded in calendar
+/// exports from Outlook and other clients that don't use IANA timezone names.
+///
+/// Each STANDARD and DAYLIGHT sub-component is an observance with its own onset and
+/// yearly RRULE, e.g. `FREQ=YEARLY;BYMONTH=10;BYDAY=1SU`.
+///
+/// Example:
+/// ```
+/// use sundial::VTimeZone;
+/// let vtimezone = VTimeZone::parse(
+///     "BEGIN:VTIMEZONE\n\
+///      TZID:Customized Time Zone\n\
+///      BEGIN:STANDARD\n\
+///      DTSTART:16010101T030000\n\
+///      TZOFFSETFROM:+1100\n\
+///      TZOFFSETTO:+1000\n\
+///      RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=4\n\
+///      END:STANDARD\n\
+///      BEGIN:DAYLIGHT\n\
+///      DTSTART:16010101T020000\n\
+///      TZOFFSETFROM:+1000\n\
+///      TZOFFSETTO:+1100\n\
+///      RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=10\n\
+///      END:DAYLIGHT\n\
+///      END:VTIMEZONE",
+/// )
+/// .unwrap();
+/// assert_eq!("Customized Time Zone", vtimezone.tzid());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VTimeZone {
+    tzid: String,
+    observances: Vec<Observance>,
+}
+
+/// A STANDARD or DAYLIGHT sub-component
+#[derive(Debug, Clone, PartialEq)]
+struct Observance {
+    dtstart: NaiveDateTime,
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    recurrence: Option<YearlyRecurrence>,
+    rdates: Vec<NaiveDateTime>,
+}
+
+/// The subset of RRULE used by observances, a yearly onset in a given month
+#[derive(Debug, Clone, PartialEq)]
+struct YearlyRecurrence {
+    month: u32,
+    by_day: Option<(i32, Weekday)>,
+    by_month_day: Vec<u32>,
+    until: Option<NaiveDateTime>,
+}
+
+impl VTimeZone {
+    /// Parses a single `BEGIN:VTIMEZONE` ... `END:VTIMEZONE` block
+    pub fn parse(vtimezone: &str) -> Result<VTimeZone, TimeZoneParseError> {
+        let mut zones = parse_vtimezones(vtimezone)?;
+        if zones.len() == 1 {
+            Ok(zones.remove(0))
+        } else {
+            Err(TimeZoneParseError::new(format!(
+                "expected exactly one VTIMEZONE component, found {}",
+                zones.len()
+            )))
+        }
+    }
+
+    pub fn tzid(&self) -> &str {
+        &self.tzid
+    }
+
+    /// The latest transition at or before `utc` with the offset it switched to
+    fn last_transition(&self, utc: &NaiveDateTime) -> Option<(NaiveDateTime, FixedOffset)> {
+        self.observances
+            .iter()
+            .filter_map(|observance| {
+                observance
+                    .last_onset(utc)
+                    .map(|onset| (onset, observance.offset_to))
+            })
+            .max_by_key(|(onset, _)| *onset)
+    }
+
+    /// The offsets used anywhere in the zone
+    fn offsets(&self) -> Vec<FixedOffset> {
+        let mut offsets: Vec<FixedOffset> = Vec::new();
+        for observance in &self.observances {
+            for offset in &[observance.offset_from, observance.offset_to] {
+                if !offsets.contains(offset) {
+                    offsets.push(*offset);
+                }
+            }
+        }
+        offsets
+    }
+}
+
+impl ZoneRules for VTimeZone {
+    fn offset_from_utc(&self, utc: &NaiveDateTime) -> FixedOffset {
+        match self.last_transition(utc) {
+            Some((_, offset)) => offset,
+            // before the first onset the offset the first observance changes from applies
+            None => self
+                .observances
+                .iter()
+                .min_by_key(|observance| observance.dtstart)
+                .map(|observance| observance.offset_from)
+                .unwrap_or_else(|| FixedOffset::east(0)),
+        }
+    }
+
+    fn offset_from_local(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+        let mut valid_offsets: Vec<FixedOffset> = self
+            .offsets()
+            .into_iter()
+            .filter(|offset| self.offset_from_utc(&(*local - offset_duration(*offset))) == *offset)
+            .collect();
+        // the larger the offset the earlier the instant
+        valid_offsets.sort_by_key(|offset| -offset.local_minus_utc());
+        match valid_offsets.as_slice() {
+            [] => LocalResult::None,
+            [offset] => LocalResult::Single(*offset),
+            [earliest, .., latest] => LocalResult::Ambiguous(*earliest, *latest),
+        }
+    }
+}
+
+impl Observance {
+    /// The latest onset of the observance at or before `utc`, as a UTC date time
+    fn last_onset(&self, utc: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let to_utc = |local: NaiveDateTime| local - offset_duration(self.offset_from);
+        let mut onsets: Vec<NaiveDateTime> = Vec::new();
+        onsets.push(to_utc(self.dtstart));
+        onsets.extend(self.rdates.iter().map(|rdate| to_utc(*rdate)));
+
+        if let Some(recurrence) = &self.recurrence {
+            let last_year = match recurrence.until {
+                Some(until) => until.year().min(utc.year()),
+                None => utc.year(),
+            };
+            // the onset of the last year might still be ahead of us, so look at the year before too
+            for year in (self.dtstart.year().max(last_year - 1)..=last_year).rev() {
+                let onset = match recurrence.onset(year, self.dtstart) {
+                    Some(onset) => onset,
+                    None => continue,
+                };
+                let onset_utc = to_utc(onset);
+                if onset >= self.dtstart
+                    && onset_utc <= *utc
+                    && recurrence.until.is_none_or(|until| onset_utc <= until)
+                {
+                    onsets.push(onset_utc);
+                    break;
+                }
+            }
+        }
+
+        onsets.into_iter().filter(|onset| onset <= utc).max()
+    }
+}
+
+impl YearlyRecurrence {
+    /// The local onset of the recurrence in the given year
+    fn onset(&self, year: i32, dtstart: NaiveDateTime) -> Option<NaiveDateTime> {
+        let date = match self.by_day {
+            Some((ordinal, weekday)) if ordinal != 0 => {
+                nth_weekday_of_month(year, self.month, ordinal, weekday)
+            }
+            Some((_, weekday)) => self
+                .by_month_day
+                .iter()
+                .filter_map(|day| NaiveDate::from_ymd_opt(year, self.month, *day))
+                .find(|date| date.weekday() == weekday),
+            None => NaiveDate::from_ymd_opt(
+                year,
+                self.month,
+                self.by_month_day
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| dtstart.day()),
+            ),
+        };
+        date.map(|date| date.and_time(dtstart.time()))
+    }
+}
+
+/// Provides the timezones defined by VTIMEZONE components, falling back to chrono-tz
+/// (and the Windows timezone names) for any other TZID.
+#[derive(Debug, Clone, Default)]
+pub struct VTimeZoneProvider {
+    zones: Vec<VTimeZone>,
+}
+
+impl VTimeZoneProvider {
+    pub fn new(zones: Vec<VTimeZone>) -> VTimeZoneProvider {
+        VTimeZoneProvider { zones }
+    }
+
+    /// Reads every VTIMEZONE component of an iCalendar file, other components are ignored
+    pub fn from_ics(ics: &str) -> Result<VTimeZoneProvider, TimeZoneParseError> {
+        Ok(VTimeZoneProvider::new(parse_vtimezones(ics)?))
+    }
+}
+
+impl TimeZoneProvider for VTimeZoneProvider {
+    fn zone(&self, tzid: &str) -> Option<Box<dyn ZoneRules>> {
+        match self.zones.iter().find(|zone| zone.tzid == tzid) {
+            Some(zone) => Some(Box::new(zone.clone())),
+            None => ChronoTzProvider.zone(tzid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeZoneParseError {
+    message: String,
+}
+
+impl TimeZoneParseError {
+    fn new(message: String) -> TimeZoneParseError {
+        TimeZoneParseError { message }
+    }
+}
+
+impl Display for TimeZoneParseError {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        write!(f, "VTIMEZONE parse error encountered: {}", self.message)
+    }
+}
+
+impl Error for TimeZoneParseError {}
+
+/// Parses all the VTIMEZONE components in the given iCalendar text
+fn parse_vtimezones(ics: &str) -> Result<Vec<VTimeZone>, TimeZoneParseError> {
+    let mut zones: Vec<VTimeZone> = Vec::new();
+    let mut zone: Option<VTimeZone> = None;
+    let mut observance: Option<Vec<(String, String)>> = None;
+
+    for line in unfold_ics_lines(ics) {
+        let (name, value) = split_content_line(&line);
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VTIMEZONE") => {
+                zone = Some(VTimeZone {
+                    tzid: String::new(),
+                    observances: Vec::new(),
+                })
+            }
+            ("BEGIN", "STANDARD") | ("BEGIN", "DAYLIGHT") if zone.is_some() => {
+                observance = Some(Vec::new())
+            }
+            ("END", "STANDARD") | ("END", "DAYLIGHT") => {
+                if let (Some(zone), Some(properties)) = (zone.as_mut(), observance.take()) {
+                    zone.observances.push(parse_observance(&properties)?);
+                }
+            }
+            ("END", "VTIMEZONE") => {
+                if let Some(zone) = zone.take() {
+                    if zone.tzid.is_empty() {
+                        return Err(TimeZoneParseError::new(
+                            "VTIMEZONE is missing its TZID".to_string(),
+                        ));
+                    }
+                    if zone.observances.is_empty() {
+                        return Err(TimeZoneParseError::new(format!(
+                            "VTIMEZONE {} has no STANDARD or DAYLIGHT components",
+                            zone.tzid
+                        )));
+                    }
+                    zones.push(zone);
+                }
+            }
+            _ => {
+                if let Some(properties) = observance.as_mut() {
+                    properties.push((name, value));
+                } else if let Some(zone) = zone.as_mut() {
+                    if name == "TZID" {
+                        zone.tzid = value;
+                    }
+                }
+            }
+        }
+    }
+    Ok(zones)
+}
+
+fn parse_observance(properties: &[(String, String)]) -> Result<Observance, TimeZoneParseError> {
+    let property = |name: &str| {
+        properties
+            .iter()
+            .find(|(property_name, _)| property_name == name)
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| TimeZoneParseError::new(format!("observance is missing {}", name)))
+    };
+
+    let dtstart = parse_date_time(property("DTSTART")?)?;
+    let offset_from = parse_utc_offset(property("TZOFFSETFROM")?)?;
+    let offset_to = parse_utc_offset(property("TZOFFSETTO")?)?;
+    let recurrence = match property("RRULE") {
+        Ok(rrule) => Some(parse_yearly_recurrence(rrule, dtstart, offset_from)?),
+        Err(_) => None,
+    };
+    let mut rdates: Vec<NaiveDateTime> = Vec::new();
+    for (_, value) in properties.iter().filter(|(name, _)| name == "RDATE") {
+        for rdate in value.split(',') {
+            rdates.push(parse_date_time(rdate)?);
+        }
+    }
+
+    Ok(Observance {
+        dtstart,
+        offset_from,
+        offset_to,
+        recurrence,
+        rdates,
+    })
+}
+
+fn parse_yearly_recurrence(
+    rrule: &str,
+    dtstart: NaiveDateTime,
+    offset_from: FixedOffset,
+) -> Result<YearlyRecurrence, TimeZoneParseError> {
+    let mut recurrence = YearlyRecurrence {
+        month: dtstart.month(),
+        by_day: None,
+        by_month_day: Vec::new(),
+        until: None,
+    };
+
+    for part in rrule.split(';') {
+        let mut key_value = part.splitn(2, '=');
+        let key = key_value.next().unwrap_or("");
+        let value = key_value.next().unwrap_or("");
+        let invalid = || TimeZoneParseError::new(format!("unsupported observance RRULE {}", rrule));
+        match key {
+            "FREQ" if value != "YEARLY" => return Err(invalid()),
+            "BYMONTH" => recurrence.month = value.parse().map_err(|_| invalid())?,
+            "BYMONTHDAY" => {
+                recurrence.by_month_day = value
+                    .split(',')
+                    .map(|day| day.parse().map_err(|_| invalid()))
+                    .collect::<Result<Vec<u32>, TimeZoneParseError>>()?
+            }
+            "BYDAY" => {
+                let by_day = value.split(',').next().unwrap_or("");
+                recurrence.by_day = Some(split_byday(by_day).ok_or_else(invalid)?);
+            }
+            "UNTIL" => {
+                let until = parse_date_time(value)?;
+                recurrence.until = Some(if value.ends_with('Z') {
+                    until
+                } else {
+                    until - offset_duration(offset_from)
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(recurrence)
+}
+
+/// Splits a content line into its upper case name, without any parameters, and its value
+fn split_content_line(line: &str) -> (String, String) {
+    let mut name_value = line.splitn(2, ':');
+    let name = name_value.next().unwrap_or("");
+    let value = name_value.next().unwrap_or("");
+    let name = name.split(';').next().unwrap_or("").to_uppercase();
+    (name, value.trim().to_string())
+}
+
+fn parse_date_time(value: &str) -> Result<NaiveDateTime, TimeZoneParseError> {
+    NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        .map_err(|_| TimeZoneParseError::new(format!("invalid DATE-TIME {}", value)))
+}
+
+/// Parses UTC offsets such as `+1000`, `-0500` or `+053000`
+fn parse_utc_offset(value: &str) -> Result<FixedOffset, TimeZoneParseError> {
+    let invalid = || TimeZoneParseError::new(format!("invalid UTC offset {}", value));
+    let sign = match value.get(..1) {
+        Some("+") => 1,
+        Some("-") => -1,
+        _ => return Err(invalid()),
+    };
+    let digits = &value[1..];
+    if (digits.len() != 4 && digits.len() != 6) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours: i32 = digits[0..2].parse().map_err(|_| invalid())?;
+    let minutes: i32 = digits[2..4].parse().map_err(|_| invalid())?;
+    let seconds: i32 = digits
+        .get(4..6)
+        .unwrap_or("0")
+        .parse()
+        .map_err(|_| invalid())?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds)).ok_or_else(invalid)
+}
+
+fn offset_duration(offset: FixedOffset) -> Duration {
+    Duration::seconds(i64::from(offset.local_minus_utc()))
+}
+
+/// The `ordinal` `weekday` of the month, counting from the end of the month when negative
+pub(crate) fn nth_weekday_of_month(
+    year: i32,
+    month: u32,
+    ordinal: i32,
+    weekday: Weekday,
+) -> Option<NaiveDate> {
+    let first_day = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let days_in_month = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    }
+    .signed_duration_since(first_day)
+    .num_days() as i32;
+
+    let day = if ordinal > 0 {
+        let first_weekday = (weekday.num_days_from_monday() as i32
+            - first_day.weekday().num_days_from_monday() as i32
+            + 7)
+            % 7;
+        1 + first_weekday + 7 * (ordinal - 1)
+    } else {
+        let last_day = first_day + Duration::days(i64::from(days_in_month - 1));
+        let last_weekday = (last_day.weekday().num_days_from_monday() as i32
+            - weekday.num_days_from_monday() as i32
+            + 7)
+            % 7;
+        days_in_month - last_weekday - 7 * (-ordinal - 1)
+    };
+
+    if day < 1 || day > days_in_month {
+        None
+    } else {
+        NaiveDate::from_ymd_opt(year, month, day as u32)
+    }
+}
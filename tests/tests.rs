@@ -1,11 +1,46 @@
 #[cfg(test)]
 mod tests {
-    use chrono::{Datelike, TimeZone, Timelike, Utc, Weekday};
+    use chrono::LocalResult;
+    use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc, Weekday};
     use chrono_tz::Etc::UTC;
+    use chrono_tz::Tz;
+    use std::collections::BTreeMap;
+    use std::io::Write;
     use std::iter::Iterator;
-    use sundial::{convert_to_rrule, validate_rrule, RRule, RuleParseError};
+    use std::process::{Command, Stdio};
+    use std::sync::Arc;
+    use sundial::{
+        convert_to_rrule, convert_to_rrule_with_provider, find_conflicts, format_ics_duration,
+        free_busy, next_free_slot, parse_rrule, validate_rrule, CacheStats, CutoffOffset, DateForm,
+        DerivedSchedule, DiffWindow, DstPolicy, Event, EventLength, FileHolidayCalendar,
+        FileScheduleStore, FixedClock, GapPolicy, HolidayCalendar, InMemoryHolidayCalendar,
+        MaterializedSchedule, MergeOptions, OverlapPolicy, PartChange, Period, RRule,
+        RuleParseError, ScheduleStore, ShiftPolicy, SlotConstraints, StoredSchedule, VTimeZone,
+        VTimeZoneProvider, ZoneRules,
+    };
 
-    fn generate_rrule_from_json(json: &str) -> Result<RRule, RuleParseError> {
+    const OUTLOOK_MELBOURNE_VTIMEZONE: &str = "BEGIN:VCALENDAR\r
+PRODID:-//Microsoft Corporation//Outlook 16.0 MIMEDIR//EN\r
+VERSION:2.0\r
+BEGIN:VTIMEZONE\r
+TZID:Customized Time Zone\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+1100\r
+TZOFFSETTO:+1000\r
+RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=4\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+1000\r
+TZOFFSETTO:+1100\r
+RRULE:FREQ=YEARLY;BYDAY=1SU;BYMONTH=10\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+END:VCALENDAR\r
+";
+
+    fn generate_rrule_from_json(json: &str) -> Result<RRule<'_>, RuleParseError> {
         let rrule = serde_json::from_str(json).unwrap();
         match validate_rrule(&rrule) {
             Ok(()) => Ok(rrule),
@@ -58,11 +93,11 @@ mod tests {
             },
             RRuleTestCase {
                 rrule_string: "DTSTART;TZID=Australia/Sydney:19970714T133000;FREQ=WEEKLY;INTERVAL=1;BYHOUR=8,12;BYMINUTE=30,45;BYDAY=TU,SU",
-                expected_flat_json: r#"{"dtstart":"1997-07-14 13:30:00 AEST","frequency":"WEEKLY","interval":"1","byHour":["8","12"],"byMinute":["30","45"],"byDay":["TU","SU"]}"#,
+                expected_flat_json: r#"{"tzid":"Australia/Sydney","dtstart":"1997-07-14 13:30:00 AEST","frequency":"WEEKLY","interval":"1","byHour":["8","12"],"byMinute":["30","45"],"byDay":["TU","SU"]}"#,
             },
             RRuleTestCase {
                 rrule_string: "DTSTART;TZID=Europe/London:19970714T133000;FREQ=WEEKLY;INTERVAL=1;BYHOUR=8,12;BYMINUTE=30,45;BYDAY=TU,SU",
-                expected_flat_json: r#"{"dtstart":"1997-07-14 13:30:00 BST","frequency":"WEEKLY","interval":"1","byHour":["8","12"],"byMinute":["30","45"],"byDay":["TU","SU"]}"#,
+                expected_flat_json: r#"{"tzid":"Europe/London","dtstart":"1997-07-14 13:30:00 BST","frequency":"WEEKLY","interval":"1","byHour":["8","12"],"byMinute":["30","45"],"byDay":["TU","SU"]}"#,
             },
             RRuleTestCase {
                 rrule_string: "DTSTART=19970714T133000;FREQ=WEEKLY;INTERVAL=1;BYHOUR=8,12;BYMINUTE=30,45;BYDAY=TU,SU",
@@ -119,7 +154,7 @@ mod tests {
             Vec::new(),
             Vec::new(),
         );
-        assert!(validate_rrule(&rrule).is_err(), true);
+        assert!(validate_rrule(&rrule).is_err());
     }
 
     #[test]
@@ -140,7 +175,7 @@ mod tests {
             Vec::new(),
             Vec::new(),
         );
-        assert!(validate_rrule(&rrule).is_err(), true);
+        assert!(validate_rrule(&rrule).is_err());
     }
 
     #[test]
@@ -161,7 +196,7 @@ mod tests {
             vec!["32"],
             Vec::new(),
         );
-        assert!(validate_rrule(&rrule).is_err(), true);
+        assert!(validate_rrule(&rrule).is_err());
     }
 
     #[test]
@@ -182,7 +217,7 @@ mod tests {
             vec!["22"],
             Vec::new(),
         );
-        assert!(validate_rrule(&rrule).is_err(), true);
+        assert!(validate_rrule(&rrule).is_err());
     }
 
     #[test]
@@ -451,6 +486,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn we_include_the_occurrence_later_in_the_starting_hour() {
+        // 00:45 is the first time from DTSTART with minute 45, the next hour is one too late
+        let test_cases = vec![
+            (
+                "FREQ=HOURLY;COUNT=2;BYMINUTE=45;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec!["2019-01-01T00:45:00+00:00", "2019-01-01T01:45:00+00:00"],
+            ),
+            (
+                "FREQ=HOURLY;COUNT=2;BYHOUR=0;BYMINUTE=45;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec!["2019-01-01T00:45:00+00:00", "2019-01-02T00:45:00+00:00"],
+            ),
+            (
+                "FREQ=MINUTELY;COUNT=2;BYSECOND=30;DTSTART=20190101T000000Z",
+                vec!["2019-01-01T00:00:30+00:00", "2019-01-01T00:01:30+00:00"],
+            ),
+            // DTSTART itself isn't repeated when it matches
+            (
+                "FREQ=HOURLY;COUNT=2;BYMINUTE=45;BYSECOND=0;DTSTART=20190101T004500Z",
+                vec!["2019-01-01T01:45:00+00:00", "2019-01-01T02:45:00+00:00"],
+            ),
+        ];
+        for (rrule_string, dates) in test_cases {
+            let rrule = convert_to_rrule(rrule_string).unwrap();
+            assert_eq!(
+                dates,
+                rrule.get_all_iter_dates_iso8601("", ""),
+                "{}",
+                rrule_string
+            );
+        }
+
+        let rrule =
+            convert_to_rrule("FREQ=HOURLY;COUNT=2;BYMINUTE=45;BYSECOND=0;DTSTART=20190101T000000")
+                .unwrap();
+        assert_eq!(
+            vec![
+                NaiveDate::from_ymd(2019, 1, 1).and_hms(0, 45, 0),
+                NaiveDate::from_ymd(2019, 1, 1).and_hms(1, 45, 0),
+            ],
+            rrule.get_all_floating_iter_dates("", "")
+        );
+    }
+
     #[test]
     fn test_minutely_rules_work_1() {
         let rrule_result =
@@ -557,7 +636,7 @@ mod tests {
         let iter_dates = rrule_result.get_all_iter_dates("", "");
         for date in iter_dates.iter() {
             assert_eq!(Weekday::Tue, date.weekday());
-            assert_eq!(00, date.hour());
+            assert_eq!(0, date.hour());
             assert_eq!(15, date.minute());
             assert_eq!(48, date.second());
         }
@@ -571,8 +650,8 @@ mod tests {
             println!("Checking for date {:?}", date);
             assert_eq!(Weekday::Thu, date.weekday());
             assert_eq!(17, date.hour());
-            assert_eq!(00, date.minute());
-            assert_eq!(03, date.second());
+            assert_eq!(0, date.minute());
+            assert_eq!(3, date.second());
         }
     }
 
@@ -584,8 +663,8 @@ mod tests {
             println!("Checking for date {:?}", date);
             assert_eq!(Weekday::Thu, date.weekday());
             assert_eq!(17, date.hour());
-            assert_eq!(00, date.minute());
-            assert_eq!(03, date.second());
+            assert_eq!(0, date.minute());
+            assert_eq!(3, date.second());
         }
     }
 
@@ -680,14 +759,8 @@ mod tests {
         // test we get the right next date
         let rrule_result =
             convert_to_rrule("FREQ=MONTHLY;INTERVAL=1;COUNT=1;BYMONTHDAY=12").unwrap();
-        let test_start_date = Utc
-            .ymd(2019, 04, 13)
-            .and_hms(01, 12, 13)
-            .with_timezone(&UTC);
-        let expected_next_date = Utc
-            .ymd(2019, 05, 12)
-            .and_hms(01, 12, 13)
-            .with_timezone(&UTC);
+        let test_start_date = Utc.ymd(2019, 4, 13).and_hms(1, 12, 13).with_timezone(&UTC);
+        let expected_next_date = Utc.ymd(2019, 5, 12).and_hms(1, 12, 13).with_timezone(&UTC);
         assert_eq!(
             expected_next_date,
             rrule_result
@@ -702,28 +775,16 @@ mod tests {
             convert_to_rrule("FREQ=MONTHLY;INTERVAL=1;COUNT=1;BYMONTHDAY=12;BYHOUR=17").unwrap();
         let cases = vec![
             (
-                Utc.ymd(2019, 04, 12)
-                    .and_hms(01, 12, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2019, 04, 12)
-                    .and_hms(17, 12, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 12).and_hms(1, 12, 13).with_timezone(&UTC),
+                Utc.ymd(2019, 4, 12).and_hms(17, 12, 13).with_timezone(&UTC),
             ),
             (
-                Utc.ymd(2019, 04, 12)
-                    .and_hms(18, 12, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2019, 05, 12)
-                    .and_hms(17, 12, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 12).and_hms(18, 12, 13).with_timezone(&UTC),
+                Utc.ymd(2019, 5, 12).and_hms(17, 12, 13).with_timezone(&UTC),
             ),
             (
-                Utc.ymd(2019, 04, 12)
-                    .and_hms(17, 12, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2019, 05, 12)
-                    .and_hms(17, 12, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 12).and_hms(17, 12, 13).with_timezone(&UTC),
+                Utc.ymd(2019, 5, 12).and_hms(17, 12, 13).with_timezone(&UTC),
             ),
         ];
 
@@ -743,28 +804,16 @@ mod tests {
                 .unwrap();
         let cases = vec![
             (
-                Utc.ymd(2019, 04, 12)
-                    .and_hms(17, 13, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2019, 04, 13)
-                    .and_hms(17, 13, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 12).and_hms(17, 13, 13).with_timezone(&UTC),
+                Utc.ymd(2019, 4, 13).and_hms(17, 13, 13).with_timezone(&UTC),
             ),
             (
-                Utc.ymd(2019, 04, 13)
-                    .and_hms(17, 13, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2020, 04, 13)
-                    .and_hms(17, 13, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 13).and_hms(17, 13, 13).with_timezone(&UTC),
+                Utc.ymd(2020, 4, 13).and_hms(17, 13, 13).with_timezone(&UTC),
             ),
             (
-                Utc.ymd(2019, 04, 13)
-                    .and_hms(18, 13, 13)
-                    .with_timezone(&UTC),
-                Utc.ymd(2020, 04, 13)
-                    .and_hms(17, 13, 13)
-                    .with_timezone(&UTC),
+                Utc.ymd(2019, 4, 13).and_hms(18, 13, 13).with_timezone(&UTC),
+                Utc.ymd(2020, 4, 13).and_hms(17, 13, 13).with_timezone(&UTC),
             ),
         ];
 
@@ -780,10 +829,7 @@ mod tests {
     fn we_support_yearly_rules_properly() {
         // test we get the right next date
         let rrule_result = convert_to_rrule("FREQ=YEARLY;COUNT=2;INTERVAL=1").unwrap();
-        let test_start_date = Utc
-            .ymd(2019, 03, 15)
-            .and_hms(01, 12, 13)
-            .with_timezone(&UTC);
+        let test_start_date = Utc.ymd(2019, 3, 15).and_hms(1, 12, 13).with_timezone(&UTC);
         assert_eq!(
             test_start_date.with_year(2020).unwrap(),
             rrule_result
@@ -826,4 +872,2961 @@ mod tests {
                 .len()
         )
     }
+
+    #[test]
+    fn we_can_scope_returned_results_8601_from_a_fixed_clock() {
+        let rrule_result =
+            convert_to_rrule("FREQ=WEEKLY;INTERVAL=1;COUNT=3;BYDAY=TU;BYHOUR=23;BYMINUTE=54;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20180110T034500")
+                .unwrap();
+        // midnight of the 15th April 2019 in Melbourne
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 14).and_hms(14, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-04-16T23:54:00+10:00".to_owned(),
+                "2019-04-23T23:54:00+10:00".to_owned(),
+                "2019-04-30T23:54:00+10:00".to_owned(),
+            ],
+            rrule_result
+                .with_clock(clock)
+                .get_all_iter_dates_from_today_iso8601("", "")
+        )
+    }
+
+    #[test]
+    fn we_use_the_clock_when_dtstart_is_missing() {
+        let rrule_result =
+            convert_to_rrule("FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=30;BYSECOND=0").unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(6, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-04-15 09:30:00".to_owned(),
+                "2019-04-16 09:30:00".to_owned(),
+                "2019-04-17 09:30:00".to_owned(),
+            ],
+            rrule_result
+                .with_clock(clock)
+                .get_all_iter_dates("", "")
+                .iter()
+                .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_start_from_the_cutoff_when_dtstart_is_missing() {
+        let rrule_result =
+            convert_to_rrule("FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=30;BYSECOND=0").unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(6, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-04-15T09:30:00+00:00".to_owned(),
+                "2019-04-16T09:30:00+00:00".to_owned(),
+                "2019-04-17T09:30:00+00:00".to_owned(),
+            ],
+            rrule_result
+                .with_clock(clock)
+                .get_all_iter_dates_from_today_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_only_get_next_iter_dates_after_the_clock() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=MONTHLY;COUNT=6;BYMONTHDAY=1;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+        )
+        .unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-05-01 09:00:00".to_owned(),
+                "2019-06-01 09:00:00".to_owned(),
+            ],
+            rrule_result
+                .with_clock(clock)
+                .get_next_iter_dates("", "")
+                .iter()
+                .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_keep_the_wall_clock_time_across_dst_transitions() {
+        // starts at 10:00 AEST, daylight saving begins on the 6th of October
+        let rrule_result =
+            convert_to_rrule("FREQ=DAILY;COUNT=4;TZID=Australia/Melbourne;DTSTART=20191004T000000")
+                .unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-10-05T10:00:00+10:00".to_owned(),
+                "2019-10-06T10:00:00+11:00".to_owned(),
+                "2019-10-07T10:00:00+11:00".to_owned(),
+                "2019-10-08T10:00:00+11:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_handle_nonexistent_local_times_with_the_gap_policy() {
+        let rrule_string = "FREQ=DAILY;COUNT=4;BYHOUR=2;BYMINUTE=30;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20191003T150000";
+        let cases = vec![
+            (GapPolicy::Rfc, "2019-10-06T03:30:00+11:00"),
+            (GapPolicy::ShiftForward, "2019-10-06T03:00:00+11:00"),
+            (GapPolicy::Skip, "2019-10-07T02:30:00+11:00"),
+        ];
+
+        for (gap, expected_third_date) in cases {
+            let rrule_result = convert_to_rrule(rrule_string)
+                .unwrap()
+                .with_dst_policy(DstPolicy {
+                    gap,
+                    overlap: OverlapPolicy::Earliest,
+                });
+            let dates = rrule_result.get_all_iter_dates_iso8601("", "");
+
+            assert_eq!("2019-10-04T02:30:00+10:00", dates[0]);
+            assert_eq!("2019-10-05T02:30:00+10:00", dates[1]);
+            assert_eq!(expected_third_date, dates[2]);
+            // the shifted occurrence does not drift the ones that follow
+            assert!(dates.contains(&"2019-10-07T02:30:00+11:00".to_owned()));
+        }
+    }
+
+    #[test]
+    fn we_handle_ambiguous_local_times_with_the_overlap_policy() {
+        // daylight saving in New York ends at 02:00 on the 3rd of November, 01:30 happens twice
+        let rrule_string = "FREQ=DAILY;COUNT=3;BYHOUR=1;BYMINUTE=30;BYSECOND=0;TZID=America/New_York;DTSTART=20191102T040000";
+        let cases = vec![
+            (OverlapPolicy::Earliest, "2019-11-03T01:30:00-04:00"),
+            (OverlapPolicy::Latest, "2019-11-03T01:30:00-05:00"),
+        ];
+
+        for (overlap, expected_second_date) in cases {
+            let rrule_result = convert_to_rrule(rrule_string)
+                .unwrap()
+                .with_dst_policy(DstPolicy {
+                    gap: GapPolicy::Rfc,
+                    overlap,
+                });
+
+            assert_eq!(
+                vec![
+                    "2019-11-02T01:30:00-04:00".to_owned(),
+                    expected_second_date.to_owned(),
+                    "2019-11-04T01:30:00-05:00".to_owned(),
+                ],
+                rrule_result.get_all_iter_dates_iso8601("", "")
+            );
+        }
+    }
+
+    #[test]
+    fn we_handle_weekly_rules_across_dst_transitions() {
+        // daylight saving in New York starts at 02:00 on the 10th of March
+        let rrule_result = convert_to_rrule("FREQ=WEEKLY;COUNT=3;BYDAY=SU;BYHOUR=2;BYMINUTE=30;BYSECOND=0;TZID=America/New_York;DTSTART=20190301T050000").unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-03-03T02:30:00-05:00".to_owned(),
+                "2019-03-10T03:30:00-04:00".to_owned(),
+                "2019-03-17T02:30:00-04:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_can_get_floating_iter_dates() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=WEEKLY;COUNT=3;BYDAY=TU;BYHOUR=7;BYMINUTE=30;BYSECOND=0;DTSTART=20191001T000000",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-10-01 07:30:00".to_owned(),
+                "2019-10-08 07:30:00".to_owned(),
+                "2019-10-15 07:30:00".to_owned(),
+            ],
+            rrule_result
+                .get_all_floating_iter_dates("", "")
+                .iter()
+                .map(|date| date.to_string())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_can_bind_floating_iter_dates_to_any_timezone() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=DAILY;COUNT=3;BYHOUR=7;BYMINUTE=30;BYSECOND=0;DTSTART=20191005T000000",
+        )
+        .unwrap();
+        let floating_dates = rrule_result.get_all_floating_iter_dates("", "");
+
+        assert_eq!(
+            vec![
+                "2019-10-05T07:30:00+10:00".to_owned(),
+                "2019-10-06T07:30:00+11:00".to_owned(),
+                "2019-10-07T07:30:00+11:00".to_owned(),
+            ],
+            rrule_result
+                .bind_floating_dates(&floating_dates, chrono_tz::Australia::Sydney)
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(
+            vec![
+                "2019-10-05T07:30:00+08:00".to_owned(),
+                "2019-10-06T07:30:00+08:00".to_owned(),
+                "2019-10-07T07:30:00+08:00".to_owned(),
+            ],
+            rrule_result
+                .bind_floating_dates(&floating_dates, chrono_tz::Australia::Perth)
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_read_floating_dtstart_and_until_as_local_times() {
+        let rrule_result = convert_to_rrule(
+            "DTSTART;TZID=Australia/Sydney:20191005T220000;FREQ=HOURLY;INTERVAL=2;UNTIL=20191006T040000",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-10-06 00:00:00".to_owned(),
+                "2019-10-06 02:00:00".to_owned(),
+                "2019-10-06 04:00:00".to_owned(),
+            ],
+            rrule_result
+                .get_all_floating_iter_dates("", "")
+                .iter()
+                .map(|date| date.to_string())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_record_how_dtstart_and_until_were_written() {
+        let floating =
+            convert_to_rrule("FREQ=DAILY;DTSTART=20190101T090000;UNTIL=20190110T090000").unwrap();
+        assert_eq!(DateForm::Floating, floating.dtstart_form());
+        assert_eq!(DateForm::Floating, floating.until_form());
+
+        let utc =
+            convert_to_rrule("FREQ=DAILY;DTSTART=20190101T090000Z;UNTIL=20190110T090000Z").unwrap();
+        assert_eq!(DateForm::Utc, utc.dtstart_form());
+        assert_eq!(DateForm::Utc, utc.until_form());
+
+        let zoned =
+            convert_to_rrule("DTSTART;TZID=Australia/Sydney:20190101T090000;FREQ=DAILY").unwrap();
+        assert_eq!(DateForm::Zoned, zoned.dtstart_form());
+    }
+
+    #[test]
+    fn we_can_output_iter_dates_in_another_timezone() {
+        let rrule_result = convert_to_rrule("FREQ=WEEKLY;COUNT=2;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Perth;DTSTART=20190101T000000").unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-01-01T12:00:00+11:00".to_owned(),
+                "2019-01-08T12:00:00+11:00".to_owned(),
+            ],
+            rrule_result
+                .clone()
+                .with_output_timezone(chrono_tz::Australia::Sydney)
+                .get_all_iter_dates_iso8601("", "")
+        );
+        assert_eq!(
+            vec![
+                "2019-01-01T01:00:00+00:00".to_owned(),
+                "2019-01-08T01:00:00+00:00".to_owned(),
+            ],
+            rrule_result
+                .clone()
+                .with_output_timezone(UTC)
+                .get_all_iter_dates_iso8601("", "")
+        );
+        let rrule_result = rrule_result.with_output_timezone(chrono_tz::Australia::Sydney);
+        assert_eq!(
+            "2019-01-08T12:00:00+11:00",
+            rrule_result.get_all_iter_dates_between(
+                "",
+                "",
+                Some(Utc.ymd(2019, 1, 5).and_hms(0, 0, 0)),
+                Some(Utc.ymd(2019, 1, 10).and_hms(0, 0, 0)),
+            )[0]
+            .to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn we_only_output_iter_dates_in_another_timezone_after_the_clock() {
+        let rrule_result = convert_to_rrule("FREQ=WEEKLY;COUNT=2;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Perth;DTSTART=20190101T000000").unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 1, 10).and_hms(0, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-01-15T12:00:00+11:00".to_owned(),
+                "2019-01-22T12:00:00+11:00".to_owned(),
+            ],
+            rrule_result
+                .with_output_timezone(chrono_tz::Australia::Sydney)
+                .with_clock(clock)
+                .get_all_iter_dates_from_today_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_can_evaluate_rules_in_a_vtimezone() {
+        let provider = VTimeZoneProvider::from_ics(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        let rrule_result = convert_to_rrule_with_provider(
+            "FREQ=WEEKLY;COUNT=6;BYDAY=SU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Customized Time Zone;DTSTART=20190320T000000",
+            &provider,
+        )
+        .unwrap();
+        let expected_rrule = convert_to_rrule(
+            "FREQ=WEEKLY;COUNT=6;BYDAY=SU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190320T000000",
+        )
+        .unwrap();
+
+        assert_eq!(
+            expected_rrule.get_all_iter_dates_iso8601("", ""),
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_keep_the_vtimezone_on_the_rule() {
+        let provider = VTimeZoneProvider::from_ics(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        let rrule_result = convert_to_rrule_with_provider(
+            "FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Customized Time Zone;DTSTART=20190405T000000",
+            &provider,
+        )
+        .unwrap();
+        let expected_rrule = convert_to_rrule(
+            "FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190405T000000",
+        )
+        .unwrap();
+
+        // chrono-tz doesn't know the zone, so the same instants are given in UTC
+        assert_eq!(
+            expected_rrule
+                .get_all_iter_dates("", "")
+                .iter()
+                .map(|date| date.with_timezone(&Utc))
+                .collect::<Vec<DateTime<Utc>>>(),
+            rrule_result
+                .get_all_iter_dates("", "")
+                .iter()
+                .map(|date| date.with_timezone(&Utc))
+                .collect::<Vec<DateTime<Utc>>>()
+        );
+        assert_eq!(
+            "2019-04-07T09:00:00+10:00",
+            rrule_result.get_all_iter_dates_iso8601("", "")[2]
+        );
+        assert!(rrule_result.to_text().contains("Customized Time Zone"));
+        assert!(rrule_result.to_cron().is_err());
+    }
+
+    #[test]
+    fn we_start_rules_in_a_vtimezone_from_the_clock() {
+        let provider = VTimeZoneProvider::from_ics(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        let rrule_result = convert_to_rrule_with_provider(
+            "FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Customized Time Zone",
+            &provider,
+        )
+        .unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 14).and_hms(20, 0, 0));
+
+        assert_eq!(
+            vec![
+                "2019-04-15T09:00:00+10:00".to_owned(),
+                "2019-04-16T09:00:00+10:00".to_owned(),
+            ],
+            rrule_result
+                .with_clock(clock)
+                .get_all_iter_dates_from_today_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_can_resolve_vtimezone_transitions() {
+        let provider = VTimeZoneProvider::from_ics(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        let vtimezone = VTimeZone::parse(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        assert!(
+            convert_to_rrule_with_provider("FREQ=DAILY;TZID=Customized Time Zone", &provider)
+                .is_ok()
+        );
+
+        // daylight saving starts at 02:00 on the 6th of October 2019
+        let gap = chrono::NaiveDate::from_ymd(2019, 10, 6).and_hms(2, 30, 0);
+        assert_eq!(LocalResult::None, vtimezone.offset_from_local(&gap));
+        // and ends at 03:00 on the 7th of April 2019
+        let overlap = chrono::NaiveDate::from_ymd(2019, 4, 7).and_hms(2, 30, 0);
+        assert_eq!(
+            LocalResult::Ambiguous(
+                chrono::FixedOffset::east(11 * 3600),
+                chrono::FixedOffset::east(10 * 3600)
+            ),
+            vtimezone.offset_from_local(&overlap)
+        );
+        let winter = chrono::NaiveDate::from_ymd(2019, 7, 1).and_hms(0, 0, 0);
+        assert_eq!(
+            chrono::FixedOffset::east(10 * 3600),
+            vtimezone.offset_from_utc(&winter)
+        );
+    }
+
+    #[test]
+    fn we_report_invalid_vtimezones() {
+        let missing_offset = "BEGIN:VTIMEZONE\nTZID:Broken\nBEGIN:STANDARD\nDTSTART:16010101T030000\nTZOFFSETFROM:+1100\nEND:STANDARD\nEND:VTIMEZONE";
+        assert!(VTimeZone::parse(missing_offset).is_err());
+        assert!(VTimeZone::parse("BEGIN:VCALENDAR\nEND:VCALENDAR").is_err());
+        // a BYDAY whose last two bytes aren't characters on their own
+        let non_ascii_byday = "BEGIN:VTIMEZONE\nTZID:Broken\nBEGIN:STANDARD\nDTSTART:16010101T030000\nTZOFFSETFROM:+1100\nTZOFFSETTO:+1000\nRRULE:FREQ=YEARLY;BYMONTH=4;BYDAY=1€\nEND:STANDARD\nEND:VTIMEZONE";
+        assert!(VTimeZone::parse(non_ascii_byday).is_err());
+    }
+
+    #[test]
+    fn we_support_windows_timezone_names() {
+        let rrule_result = convert_to_rrule("FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=AUS Eastern Standard Time;DTSTART=20191003T210000").unwrap();
+
+        assert_eq!(
+            vec![
+                "2019-10-04T09:00:00+10:00".to_owned(),
+                "2019-10-05T09:00:00+10:00".to_owned(),
+                "2019-10-06T09:00:00+11:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_support_timezone_names_without_a_slash_in_dtstart() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=DAILY;COUNT=2;DTSTART;TZID=AUS Eastern Standard Time:20191005T090000",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "2019-10-06T09:00:00+11:00".to_owned(),
+                "2019-10-07T09:00:00+11:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+        assert_eq!(
+            "FREQ=DAILY;COUNT=2;DTSTART;TZID=AUS Eastern Standard Time:20191005T090000",
+            rrule_result.to_rrule_string()
+        );
+
+        let provider = VTimeZoneProvider::from_ics(OUTLOOK_MELBOURNE_VTIMEZONE).unwrap();
+        let rrule_result = convert_to_rrule_with_provider(
+            "FREQ=DAILY;COUNT=2;DTSTART;TZID=Customized Time Zone:20191005T090000",
+            &provider,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "2019-10-06T09:00:00+11:00".to_owned(),
+                "2019-10-07T09:00:00+11:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+        assert!(
+            convert_to_rrule("FREQ=DAILY;DTSTART;TZID=Customized Time Zone:20191005T090000")
+                .is_err()
+        );
+        // 02:30 is skipped when daylight saving starts on the 6th of October
+        assert!(convert_to_rrule_with_provider(
+            "FREQ=DAILY;DTSTART;TZID=Customized Time Zone:20191006T023000",
+            &provider
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn we_tell_parse_errors_from_validation_errors() {
+        assert!(parse_rrule("FREQ=DAILY;EVERY=DAY").is_err());
+        assert!(parse_rrule("FREQ=DAILY;DTSTART=2019").is_err());
+
+        let rrule_result = parse_rrule("FREQ=DAILY;BYHOUR=24;").unwrap();
+        assert!(validate_rrule(&rrule_result).is_err());
+        assert!(convert_to_rrule("FREQ=DAILY;BYHOUR=24").is_err());
+    }
+
+    fn run_cli(args: &[&str]) -> (Option<i32>, String, String) {
+        let output = Command::new(env!("CARGO_BIN_EXE_sundial"))
+            .args(args)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    }
+
+    #[test]
+    fn we_print_json_from_the_cli() {
+        let (code, stdout, _) = run_cli(&["FREQ=WEEKLY;COUNT=2;BYDAY=TU;BYHOUR=9;BYMINUTE=0;TZID=Australia/Perth;DTSTART=20190101T000000"]);
+        assert_eq!(Some(0), code);
+        let dates: Vec<String> = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(
+            vec!["2019-01-01T09:00:00+08:00", "2019-01-08T09:00:00+08:00"],
+            dates
+        );
+
+        let (code, stdout, _) = run_cli(&[
+            "FREQ=DAILY;COUNT=1;BYHOUR=9;BYMINUTE=0;DTSTART=20190101T000000",
+            "--pretty",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!("[\n  \"2019-01-01T09:00:00+00:00\"\n]\n", stdout);
+    }
+
+    #[test]
+    fn we_exit_with_distinct_codes_for_cli_errors() {
+        let (code, stdout, stderr) = run_cli(&["FREQ=DAILY;EVERY=DAY"]);
+        assert_eq!(Some(2), code);
+        assert!(stdout.is_empty());
+        let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+        assert_eq!("parse", error["error"]["kind"]);
+
+        let (code, _, stderr) = run_cli(&["FREQ=DAILY;BYHOUR=24"]);
+        assert_eq!(Some(3), code);
+        let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+        assert_eq!("validation", error["error"]["kind"]);
+
+        let (code, _, _) = run_cli(&["FREQ=DAILY", "--output-tz", "Mars/Olympus_Mons"]);
+        assert_eq!(Some(3), code);
+    }
+
+    #[test]
+    fn we_validate_the_count_and_interval() {
+        assert!(convert_to_rrule("FREQ=DAILY;COUNT=0").is_ok());
+        assert!(convert_to_rrule("FREQ=DAILY;COUNT=2147483647").is_ok());
+        assert!(convert_to_rrule("FREQ=DAILY;COUNT=99999999999").is_err());
+        assert!(convert_to_rrule("FREQ=DAILY;INTERVAL=65535").is_ok());
+        assert!(convert_to_rrule("FREQ=DAILY;INTERVAL=0").is_err());
+        assert!(convert_to_rrule("FREQ=DAILY;INTERVAL=4294967295").is_err());
+
+        let (code, _, stderr) = run_cli(&["FREQ=DAILY;COUNT=99999999999"]);
+        assert_eq!(Some(3), code);
+        let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+        assert_eq!(
+            "RRule validation errors encountered: COUNT can only be in range 0-2147483647 | Provided value \"99999999999\"",
+            error["error"]["message"]
+        );
+
+        // a count too large for the rule's own COUNT is still just a lot of occurrences
+        let (code, stdout, _) = run_cli(&[
+            "FREQ=DAILY;UNTIL=20190103T000000Z;DTSTART=20190101T000000Z",
+            "--count",
+            "4294967295",
+        ]);
+        assert_eq!(Some(0), code);
+        let dates: Vec<String> = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(2, dates.len());
+    }
+
+    #[test]
+    fn we_parse_month_and_year_days_with_zeros() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=MONTHLY;COUNT=2;BYMONTHDAY=20;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "2019-01-20T09:00:00+00:00".to_owned(),
+                "2019-02-20T09:00:00+00:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+        assert!(convert_to_rrule("FREQ=YEARLY;BYYEARDAY=100").is_ok());
+        assert!(convert_to_rrule("FREQ=MONTHLY;BYMONTHDAY=32").is_err());
+    }
+
+    #[test]
+    fn we_parse_more_than_two_by_values() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=MONTHLY;BYMONTH=1,4,7,10;BYMONTHDAY=1,15,28;BYHOUR=8,12,16;BYMINUTE=0,15,30,45;BYSECOND=0,20,40",
+        )
+        .unwrap();
+        assert_eq!(
+            r#"{"frequency":"MONTHLY","byMonth":["1","4","7","10"],"byHour":["8","12","16"],"byMinute":["0","15","30","45"],"bySecond":["0","20","40"],"byMonthDay":["1","15","28"]}"#,
+            rrule_result.to_json()
+        );
+        assert!(convert_to_rrule("FREQ=YEARLY;BYYEARDAY=1,100,200").is_ok());
+
+        let (code, _, _) = run_cli(&["FREQ=DAILY;COUNT=3;BYHOUR=8,12,16"]);
+        assert_eq!(Some(0), code);
+        let (code, _, _) = run_cli(&["FREQ=MONTHLY;COUNT=3;BYMONTHDAY=1,15,28"]);
+        assert_eq!(Some(0), code);
+        let (code, _, _) = run_cli(&["FREQ=DAILY;BYHOUR=8,12,24"]);
+        assert_eq!(Some(3), code);
+    }
+
+    #[test]
+    fn we_iterate_every_value_of_a_by_list() {
+        let test_cases = vec![
+            (
+                "FREQ=HOURLY;COUNT=4;BYMINUTE=0,30;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec![
+                    "2019-01-01T00:30:00+00:00",
+                    "2019-01-01T01:00:00+00:00",
+                    "2019-01-01T01:30:00+00:00",
+                    "2019-01-01T02:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=DAILY;COUNT=3;BYHOUR=8,12;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec![
+                    "2019-01-01T08:00:00+00:00",
+                    "2019-01-01T12:00:00+00:00",
+                    "2019-01-02T08:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=MONTHLY;COUNT=3;BYMONTHDAY=1,15;BYHOUR=0;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec![
+                    "2019-01-15T00:00:00+00:00",
+                    "2019-02-01T00:00:00+00:00",
+                    "2019-02-15T00:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=DAILY;COUNT=3;BYMONTH=1,6;BYHOUR=0;BYMINUTE=0;BYSECOND=0;DTSTART=20190130T000000Z",
+                vec![
+                    "2019-01-31T00:00:00+00:00",
+                    "2019-06-01T00:00:00+00:00",
+                    "2019-06-02T00:00:00+00:00",
+                ],
+            ),
+            // the same occurrence from two values is only counted once
+            (
+                "FREQ=DAILY;COUNT=2;BYHOUR=8,08;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000Z",
+                vec!["2019-01-01T08:00:00+00:00", "2019-01-02T08:00:00+00:00"],
+            ),
+        ];
+        for (rrule_string, dates) in test_cases {
+            let rrule = convert_to_rrule(rrule_string).unwrap();
+            assert_eq!(
+                dates,
+                rrule.get_all_iter_dates_iso8601("", ""),
+                "{}",
+                rrule_string
+            );
+        }
+
+        let rrule = convert_to_rrule(
+            "FREQ=DAILY;COUNT=3;BYHOUR=8,12;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                NaiveDate::from_ymd(2019, 1, 1).and_hms(8, 0, 0),
+                NaiveDate::from_ymd(2019, 1, 1).and_hms(12, 0, 0),
+                NaiveDate::from_ymd(2019, 1, 2).and_hms(8, 0, 0),
+            ],
+            rrule.get_all_floating_iter_dates("", "")
+        );
+    }
+
+    #[test]
+    fn we_can_normalize_rrules() {
+        let rrule_result = convert_to_rrule("DTSTART=20190101T000000;TZID=Australia/Sydney;BYHOUR=09,9;WKST=MO;INTERVAL=01;UNTIL=20190201T000000;FREQ=WEEKLY;BYDAY=TU").unwrap();
+        let normalized = rrule_result.to_rrule_string();
+        assert_eq!(
+            "FREQ=WEEKLY;UNTIL=20190201T000000;BYDAY=TU;BYHOUR=9;TZID=Australia/Sydney;DTSTART=20190101T000000",
+            normalized
+        );
+        // the canonical string evaluates to the same dates
+        assert_eq!(
+            rrule_result.get_all_iter_dates_iso8601("", ""),
+            convert_to_rrule(&normalized)
+                .unwrap()
+                .get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_keep_the_meaning_of_rules_when_normalizing() {
+        let test_cases = vec![
+            (
+                "DTSTART;TZID=Australia/Sydney:19970714T133000;FREQ=WEEKLY;COUNT=2",
+                "FREQ=WEEKLY;COUNT=2;DTSTART;TZID=Australia/Sydney:19970714T133000",
+            ),
+            (
+                "DTSTART=19970714T133000Z;FREQ=WEEKLY;COUNT=2;UNTIL=19970801T000000Z",
+                "FREQ=WEEKLY;COUNT=2;UNTIL=19970801T000000Z;DTSTART=19970714T133000Z",
+            ),
+            (
+                "DTSTART=19970714T133000;FREQ=WEEKLY;COUNT=2;TZID=Australia/Perth",
+                "FREQ=WEEKLY;COUNT=2;TZID=Australia/Perth;DTSTART=19970714T133000",
+            ),
+        ];
+        for (rrule_string, expected_normalized) in test_cases {
+            let rrule_result = convert_to_rrule(rrule_string).unwrap();
+            let normalized = rrule_result.to_rrule_string();
+            assert_eq!(expected_normalized, normalized);
+            let normalized_rrule = convert_to_rrule(&normalized).unwrap();
+            assert_eq!(rrule_result.dtstart_form(), normalized_rrule.dtstart_form());
+            assert_eq!(
+                rrule_result.get_all_iter_dates_iso8601("", ""),
+                normalized_rrule.get_all_iter_dates_iso8601("", "")
+            );
+        }
+        assert_eq!(
+            vec![
+                "1997-07-21T13:30:00+10:00".to_owned(),
+                "1997-07-28T13:30:00+10:00".to_owned(),
+            ],
+            convert_to_rrule("DTSTART;TZID=Australia/Sydney:19970714T133000;FREQ=WEEKLY;COUNT=2")
+                .unwrap()
+                .get_all_iter_dates_iso8601("", "")
+        );
+        assert!(convert_to_rrule(
+            "DTSTART;TZID=Australia/Sydney:19970714T133000;FREQ=WEEKLY;TZID=Australia/Perth"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn we_can_get_iter_dates_between_two_dates() {
+        let rrule_result = convert_to_rrule("FREQ=WEEKLY;COUNT=3;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Perth;DTSTART=20190101T000000").unwrap();
+        let iter_dates = rrule_result.get_all_iter_dates_between(
+            "",
+            "",
+            Some(Utc.ymd(2019, 1, 8).and_hms(0, 0, 0)),
+            Some(Utc.ymd(2019, 2, 5).and_hms(1, 0, 0)),
+        );
+        // the count applies to the dates inside the window and the before date is exclusive
+        assert_eq!(
+            vec![
+                "2019-01-08T09:00:00+08:00",
+                "2019-01-15T09:00:00+08:00",
+                "2019-01-22T09:00:00+08:00",
+            ],
+            iter_dates
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+
+        let iter_dates = rrule_result.get_all_iter_dates_between(
+            "",
+            "",
+            None,
+            Some(Utc.ymd(2019, 1, 8).and_hms(1, 0, 0)),
+        );
+        assert_eq!(1, iter_dates.len());
+    }
+
+    #[test]
+    fn we_start_iter_dates_between_from_the_clock() {
+        let rrule_result =
+            convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Perth")
+                .unwrap();
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 14).and_hms(20, 0, 0));
+        let iter_dates = rrule_result.with_clock(clock).get_all_iter_dates_between(
+            "",
+            "",
+            None,
+            Some(Utc.ymd(2019, 4, 17).and_hms(0, 0, 0)),
+        );
+        assert_eq!(
+            vec!["2019-04-15T09:00:00+08:00", "2019-04-16T09:00:00+08:00"],
+            iter_dates
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_support_cli_subcommands() {
+        let rrule = "FREQ=DAILY;COUNT=3;BYHOUR=09;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000";
+
+        let (code, stdout, _) = run_cli(&["parse", rrule]);
+        assert_eq!(Some(0), code);
+        let parsed: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!("DAILY", parsed["frequency"]);
+
+        let (code, _, _) = run_cli(&["validate", rrule]);
+        assert_eq!(Some(0), code);
+        let (code, _, stderr) = run_cli(&["validate", "FREQ=DAILY;BYMONTH=13"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("BYMONTH can only be in range 1-12"));
+
+        let (code, stdout, _) = run_cli(&[
+            "expand",
+            rrule,
+            "--after",
+            "20190102T000000",
+            "--before",
+            "2019-01-03 09:00:00",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!("[\"2019-01-02T09:00:00+00:00\"]\n", stdout);
+
+        let (code, stdout, _) = run_cli(&["normalize", rrule]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000\n",
+            stdout
+        );
+    }
+
+    #[test]
+    fn we_can_expand_a_batch_of_rrules() {
+        let batch = "FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;DTSTART=20190101T000000
+
+{\"id\":\"supplier-1\",\"rrule\":\"FREQ=WEEKLY;BYDAY=MO;BYHOUR=9;BYMINUTE=0;DTSTART=20190101T000000\",\"count\":1}
+FREQ=DAILY;BYHOUR=25
+";
+        let expected = vec![
+            "{\"line\":1,\"dates\":[\"2019-01-01T09:00:00+00:00\",\"2019-01-02T09:00:00+00:00\"]}",
+            "{\"id\":\"supplier-1\",\"line\":3,\"dates\":[\"2019-01-07T09:00:00+00:00\"]}",
+        ];
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sundial"))
+            .args(["expand", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(batch.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(Some(0), output.status.code());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(expected, lines[..2].to_vec());
+        let error: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+        assert_eq!(4, error["line"]);
+        assert_eq!("validation", error["error"]["kind"]);
+
+        // running in parallel keeps the input order
+        let path = std::env::temp_dir().join("sundial_batch_test.ndjson");
+        std::fs::write(&path, batch).unwrap();
+        let (code, parallel_stdout, _) =
+            run_cli(&["expand", "--batch", path.to_str().unwrap(), "--jobs", "3"]);
+        assert_eq!(Some(0), code);
+        assert_eq!(stdout, parallel_stdout);
+    }
+
+    #[test]
+    fn we_support_cli_output_formats() {
+        let rrule = "FREQ=WEEKLY;COUNT=2;BYDAY=TU;BYHOUR=23;BYMINUTE=54;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190410T034500";
+
+        let (_, stdout, _) = run_cli(&[rrule, "--format", "lines"]);
+        assert_eq!(
+            "2019-04-16T23:54:00+10:00\n2019-04-23T23:54:00+10:00\n",
+            stdout
+        );
+
+        let (_, stdout, _) = run_cli(&["expand", rrule, "--format", "csv"]);
+        assert_eq!(
+            "index,datetime,weekday,utc
+1,2019-04-16T23:54:00+10:00,Tuesday,2019-04-16T13:54:00Z
+2,2019-04-23T23:54:00+10:00,Tuesday,2019-04-23T13:54:00Z
+",
+            stdout
+        );
+
+        let (_, stdout, _) = run_cli(&["expand", rrule, "--format", "table"]);
+        assert_eq!(
+            "#  Weekday  Date        Time      Offset  UTC
+1  Tuesday  2019-04-16  23:54:00  +10:00  2019-04-16T13:54:00Z
+2  Tuesday  2019-04-23  23:54:00  +10:00  2019-04-23T13:54:00Z
+",
+            stdout
+        );
+
+        let (_, stdout, _) = run_cli(&["expand", rrule, "--format", "ics"]);
+        assert_eq!(2, stdout.matches("BEGIN:VEVENT\r\n").count());
+        assert!(stdout.contains("DTSTART:20190423T135400Z\r\n"));
+        assert!(stdout.ends_with("END:VCALENDAR\r\n"));
+
+        let (_, stdout, _) = run_cli(&["expand", rrule, "--format", "ics", "--ics-rrule"]);
+        assert_eq!(1, stdout.matches("BEGIN:VEVENT\r\n").count());
+        assert!(stdout.contains("DTSTART;TZID=Australia/Melbourne:20190416T235400\r\n"));
+        assert!(stdout
+            .contains("RRULE:FREQ=WEEKLY;COUNT=2;BYDAY=TU;BYHOUR=23;BYMINUTE=54;BYSECOND=0\r\n"));
+
+        let (_, stdout, _) = run_cli(&[
+            "expand",
+            "FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART;TZID=Australia/Sydney:20190101T000000",
+            "--format",
+            "ics",
+            "--ics-rrule",
+        ]);
+        assert!(stdout.contains("DTSTART;TZID=Australia/Sydney:20190101T090000\r\n"));
+        assert!(stdout.contains("RRULE:FREQ=DAILY;COUNT=2;BYHOUR=9;BYMINUTE=0;BYSECOND=0\r\n"));
+    }
+
+    #[test]
+    fn we_can_render_a_calendar_from_the_cli() {
+        let (code, stdout, _) = run_cli(&[
+            "cal",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU;BYHOUR=9;TZID=Australia/Melbourne;DTSTART=20190101T000000;WKST=SU",
+            "--months",
+            "2",
+        ]);
+        assert_eq!(Some(0), code);
+        // stdout is not a terminal here so the occurrences are marked with a *
+        assert_eq!(
+            "    January 2019           February 2019
+ Su Mo Tu We Th Fr Sa   Su Mo Tu We Th Fr Sa
+        1  2  3  4  5                   1  2
+  6  7  8  9 10 11 12    3  4  5  6  7  8  9
+ 13 14*15 16 17 18 19   10 11*12 13 14 15 16
+ 20 21 22 23 24 25 26   17 18 19 20 21 22 23
+ 27 28*29 30 31         24 25*26 27 28
+",
+            stdout
+        );
+
+        let (_, stdout, _) = run_cli(&[
+            "cal",
+            "FREQ=MONTHLY;BYMONTHDAY=1;DTSTART=20190101T000000",
+            "--months",
+            "1",
+            "--start",
+            "2019-09",
+        ]);
+        assert!(stdout.starts_with("   September 2019\n Mo Tu We Th Fr Sa Su\n"));
+    }
+
+    #[test]
+    fn we_can_describe_rrules_in_english() {
+        let test_cases = vec![
+            (
+                "FREQ=WEEKLY;INTERVAL=2;COUNT=3;BYDAY=TU;BYHOUR=23;BYMINUTE=54;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20180110T034500",
+                "every 2 weeks on Tuesday at 11:54 PM (Australia/Melbourne), 3 times",
+            ),
+            (
+                "FREQ=MONTHLY;INTERVAL=1;COUNT=12;BYMONTH=11;BYMONTHDAY=2;DTSTART=20190402T011213;TZID=Australia/Sydney",
+                "every month in November on the 2nd, 12 times",
+            ),
+            (
+                "FREQ=DAILY;BYDAY=MO,FR;BYHOUR=9,17;UNTIL=20200612T030000",
+                "every day on Monday and Friday at 9:00 AM and 5:00 PM, until June 12, 2020",
+            ),
+            (
+                "FREQ=DAILY;BYHOUR=0;DTSTART=20190101T000730",
+                "every day at 12:07:30 AM",
+            ),
+            (
+                "FREQ=DAILY;BYHOUR=9,10;BYMINUTE=0,30",
+                "every day at 9:00 AM, 9:30 AM, 10:00 AM and 10:30 AM",
+            ),
+            ("FREQ=HOURLY;INTERVAL=3;COUNT=1", "every 3 hours, once"),
+            ("FREQ=FORTNIGHTLY", "every 2 weeks"),
+        ];
+        for (rrule_string, text) in test_cases {
+            assert_eq!(text, convert_to_rrule(rrule_string).unwrap().to_text());
+        }
+
+        let (code, stdout, _) = run_cli(&["explain", "FREQ=YEARLY;BYMONTH=6;BYHOUR=12"]);
+        assert_eq!(Some(0), code);
+        assert_eq!("every year in June at 12:00 PM\n", stdout);
+    }
+
+    #[test]
+    fn we_can_iterate_multiple_and_ordinal_weekdays() {
+        let test_cases = vec![
+            (
+                "FREQ=WEEKLY;COUNT=6;BYDAY=MO,TU,WE,TH,FR;BYHOUR=7;BYMINUTE=30;BYSECOND=0;DTSTART=20190101T000000",
+                vec![
+                    "2019-01-01T07:30:00+00:00",
+                    "2019-01-02T07:30:00+00:00",
+                    "2019-01-03T07:30:00+00:00",
+                    "2019-01-04T07:30:00+00:00",
+                    "2019-01-07T07:30:00+00:00",
+                    "2019-01-08T07:30:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,TH;DTSTART=20190101T090000",
+                vec![
+                    "2019-01-03T09:00:00+00:00",
+                    "2019-01-15T09:00:00+00:00",
+                    "2019-01-17T09:00:00+00:00",
+                    "2019-01-29T09:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=DAILY;COUNT=3;BYDAY=SA,SU;DTSTART=20190101T090000",
+                vec![
+                    "2019-01-05T09:00:00+00:00",
+                    "2019-01-06T09:00:00+00:00",
+                    "2019-01-12T09:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=MONTHLY;COUNT=3;BYDAY=-1FR;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+                vec![
+                    "2019-01-25T09:00:00+00:00",
+                    "2019-02-22T09:00:00+00:00",
+                    "2019-03-29T09:00:00+00:00",
+                ],
+            ),
+            (
+                "FREQ=MONTHLY;COUNT=3;BYDAY=5MO;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+                vec![
+                    "2019-04-29T09:00:00+00:00",
+                    "2019-07-29T09:00:00+00:00",
+                    "2019-09-30T09:00:00+00:00",
+                ],
+            ),
+        ];
+        for (rrule_string, dates) in test_cases {
+            assert_eq!(
+                dates,
+                convert_to_rrule(rrule_string)
+                    .unwrap()
+                    .get_all_iter_dates_iso8601("", "")
+            );
+        }
+        assert!(convert_to_rrule("FREQ=WEEKLY;BYDAY=-1FR").is_err());
+        assert!(convert_to_rrule("FREQ=MONTHLY;BYDAY=6FR").is_err());
+        assert_eq!(
+            "every month on the last Friday of the month",
+            convert_to_rrule("FREQ=MONTHLY;BYDAY=-1FR")
+                .unwrap()
+                .to_text()
+        );
+    }
+
+    #[test]
+    fn we_can_build_rrules_from_text() {
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0));
+        let test_cases = vec![
+            (
+                "every other Tuesday at 9am until end of March",
+                "FREQ=WEEKLY;INTERVAL=2;UNTIL=20200331T235959Z;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0",
+            ),
+            (
+                "weekdays at 7:30",
+                "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=7;BYMINUTE=30;BYSECOND=0",
+            ),
+            ("last Friday of each month", "FREQ=MONTHLY;BYDAY=-1FR"),
+            (
+                "every day at 5:30 pm in Australia/Sydney, 10 times",
+                "FREQ=DAILY;COUNT=10;BYHOUR=17;BYMINUTE=30;BYSECOND=0;TZID=Australia/Sydney",
+            ),
+            (
+                "on the 15th of every month at noon until June 30 2019",
+                "FREQ=MONTHLY;UNTIL=20190630T235959Z;BYMONTHDAY=15;BYHOUR=12;BYMINUTE=0;BYSECOND=0",
+            ),
+            (
+                "the first Monday of the month starting 2019-01-01",
+                "FREQ=MONTHLY;BYDAY=1MO;DTSTART=20190101T000000Z",
+            ),
+            ("every 15 minutes on weekends", "FREQ=MINUTELY;INTERVAL=15;BYDAY=SA,SU"),
+            ("Mondays and Fridays at 8", "FREQ=WEEKLY;BYDAY=MO,FR;BYHOUR=8;BYMINUTE=0;BYSECOND=0"),
+        ];
+        for (text, rrule_string) in test_cases {
+            assert_eq!(
+                rrule_string,
+                RRule::from_text_with_clock(text, &clock)
+                    .unwrap()
+                    .to_rrule_string()
+            );
+        }
+
+        let errors = vec![
+            (
+                "weekdays at 7:30 sharp",
+                "unsupported phrase \"sharp\" at word 4",
+            ),
+            ("every blue moon", "\"every\" needs a unit"),
+            ("every hour at 9am", "a time of day can't be given"),
+            ("until March 31", "doesn't say how often it repeats"),
+        ];
+        for (text, error) in errors {
+            let message = RRule::from_text_with_clock(text, &clock)
+                .unwrap_err()
+                .to_string();
+            assert!(message.contains(error), "{}", message);
+        }
+
+        let (code, stdout, _) = run_cli(&["from-text", "last", "Friday", "of", "each", "month"]);
+        assert_eq!(Some(0), code);
+        assert_eq!("FREQ=MONTHLY;BYDAY=-1FR\n", stdout);
+        let (code, _, stderr) = run_cli(&["from-text", "every blue moon"]);
+        assert_eq!(Some(2), code);
+        assert!(stderr.contains("\"kind\":\"parse\""));
+    }
+
+    #[test]
+    fn we_can_convert_cron_expressions() {
+        let test_cases = vec![
+            (
+                "30 9 * * 1-5",
+                "Australia/Sydney",
+                "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;BYSECOND=0;TZID=Australia/Sydney",
+            ),
+            (
+                "0 17 * * sat,SUN",
+                "",
+                "FREQ=WEEKLY;BYDAY=SA,SU;BYHOUR=17;BYMINUTE=0;BYSECOND=0",
+            ),
+            (
+                "0 9 15 * *",
+                "",
+                "FREQ=MONTHLY;BYMONTHDAY=15;BYHOUR=9;BYMINUTE=0;BYSECOND=0",
+            ),
+            ("45 * * * *", "", "FREQ=HOURLY;BYMINUTE=45;BYSECOND=0"),
+            ("* 3 * * *", "", "FREQ=MINUTELY;BYHOUR=3;BYSECOND=0"),
+            (
+                "CRON_TZ=Europe/London @daily",
+                "Australia/Sydney",
+                "FREQ=DAILY;BYHOUR=0;BYMINUTE=0;BYSECOND=0;TZID=Europe/London",
+            ),
+            (
+                "*/15 * * * *",
+                "",
+                "FREQ=HOURLY;BYMINUTE=0,15,30,45;BYSECOND=0",
+            ),
+            (
+                "0 0 1,15 * *",
+                "",
+                "FREQ=MONTHLY;BYMONTHDAY=1,15;BYHOUR=0;BYMINUTE=0;BYSECOND=0",
+            ),
+            (
+                "0 */6 * * *",
+                "",
+                "FREQ=DAILY;BYHOUR=0,6,12,18;BYMINUTE=0;BYSECOND=0",
+            ),
+            (
+                "0,30 9-11 * * 1-5",
+                "",
+                "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9,10,11;BYMINUTE=0,30;BYSECOND=0",
+            ),
+            ("*/1 * * * *", "", "FREQ=MINUTELY;BYSECOND=0"),
+        ];
+        for (expression, tzid, rrule_string) in test_cases {
+            let rrule = RRule::from_cron(expression, tzid).unwrap();
+            assert_eq!(rrule_string, rrule.to_rrule_string());
+        }
+
+        // steps and lists run on every value they match, like cron
+        let test_cases = vec![
+            (
+                "*/15 * * * *",
+                vec![
+                    "2019-01-01T00:15:00+00:00",
+                    "2019-01-01T00:30:00+00:00",
+                    "2019-01-01T00:45:00+00:00",
+                    "2019-01-01T01:00:00+00:00",
+                    "2019-01-01T01:15:00+00:00",
+                ],
+            ),
+            (
+                "0 0 1,15 * *",
+                vec![
+                    "2019-01-15T00:00:00+00:00",
+                    "2019-02-01T00:00:00+00:00",
+                    "2019-02-15T00:00:00+00:00",
+                    "2019-03-01T00:00:00+00:00",
+                    "2019-03-15T00:00:00+00:00",
+                ],
+            ),
+        ];
+        let clock = FixedClock::new(Utc.ymd(2019, 1, 1).and_hms(0, 0, 0));
+        for (expression, dates) in test_cases {
+            let rrule = RRule::from_cron(expression, "").unwrap();
+            assert_eq!(
+                dates,
+                rrule
+                    .with_clock(clock)
+                    .get_all_iter_dates_from_today_iso8601("5", ""),
+                "{}",
+                expression
+            );
+        }
+
+        let errors = vec![
+            ("*/15 * 1 * *", "day of month of an hourly or minutely rule"),
+            ("0 9 1 * 1", "day of month or the day of week"),
+            ("0 24 * * *", "invalid hour field"),
+            ("0 9 * *", "expected 5 fields"),
+        ];
+        for (expression, error) in errors {
+            let message = RRule::from_cron(expression, "").unwrap_err().to_string();
+            assert!(message.contains(error), "{}", message);
+        }
+
+        let test_cases = vec![
+            (
+                "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;TZID=Australia/Sydney",
+                "CRON_TZ=Australia/Sydney 30 9 * * 1-5",
+            ),
+            (
+                "FREQ=DAILY;BYDAY=SA,SU,MO,WE;DTSTART=20190101T083000Z",
+                "30 8 * * 0,1,3,6",
+            ),
+            (
+                "FREQ=MONTHLY;BYMONTHDAY=15;BYHOUR=9;BYMINUTE=0;BYSECOND=0",
+                "0 9 15 * *",
+            ),
+            ("FREQ=YEARLY;DTSTART=20190704T120000Z", "0 12 4 7 *"),
+            (
+                "FREQ=WEEKLY;TZID=Australia/Sydney;DTSTART=20190101T000000Z",
+                "CRON_TZ=Australia/Sydney 0 11 * * 2",
+            ),
+        ];
+        for (rrule_string, expression) in test_cases {
+            assert_eq!(
+                expression,
+                convert_to_rrule(rrule_string).unwrap().to_cron().unwrap()
+            );
+        }
+
+        // lists and steps convert back to the values they match
+        let test_cases = vec![
+            ("*/15 * * * *", "0,15,30,45 * * * *"),
+            ("0 0 1,15 * *", "0 0 1,15 * *"),
+            ("0 */6 * * *", "0 0,6,12,18 * * *"),
+            ("0,30 9-11 * * 1-5", "0,30 9-11 * * 1-5"),
+            ("0 9 * 1-3,12 *", "0 9 * 1-3,12 *"),
+        ];
+        for (expression, converted) in test_cases {
+            let rrule = RRule::from_cron(expression, "").unwrap();
+            assert_eq!(converted, rrule.to_cron().unwrap(), "{}", expression);
+            assert_eq!(
+                rrule.to_rrule_string(),
+                RRule::from_cron(converted, "").unwrap().to_rrule_string()
+            );
+        }
+
+        let errors = vec![
+            (
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO;BYHOUR=9;BYMINUTE=0",
+                "every 2 weeks",
+            ),
+            ("FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0", "never end"),
+            (
+                "FREQ=MONTHLY;BYDAY=-1FR;BYHOUR=9;BYMINUTE=0",
+                "ordinal weekday",
+            ),
+            (
+                "FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=30",
+                "precision of a minute",
+            ),
+            ("FREQ=DAILY", "no BYMINUTE or DTSTART"),
+        ];
+        for (rrule_string, error) in errors {
+            let message = convert_to_rrule(rrule_string)
+                .unwrap()
+                .to_cron()
+                .unwrap_err()
+                .to_string();
+            assert!(message.contains(error), "{}", message);
+        }
+
+        let (code, stdout, _) = run_cli(&["from-cron", "30 9 * * 1-5", "--tz", "Australia/Sydney"]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;BYSECOND=0;TZID=Australia/Sydney\n",
+            stdout
+        );
+        let (code, stdout, _) = run_cli(&["to-cron", "FREQ=HOURLY;BYMINUTE=5"]);
+        assert_eq!(Some(0), code);
+        assert_eq!("5 * * * *\n", stdout);
+        let (code, _, stderr) = run_cli(&[
+            "to-cron",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO;BYHOUR=9;BYMINUTE=0",
+        ]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("can't skip occurrences"));
+    }
+
+    #[test]
+    fn we_can_convert_systemd_calendar_events() {
+        // the normalized forms from the systemd.time(7) examples
+        let test_cases = vec![
+            ("Wed, 17:48", "Wed *-*-* 17:48:00"),
+            ("*-*-7 0:0:0", "*-*-07 00:00:00"),
+            ("10-15", "*-10-15 00:00:00"),
+            ("monday *-12-* 17:00", "Mon *-12-* 17:00:00"),
+            ("03-05 08:05:40", "*-03-05 08:05:40"),
+            ("08:05:40", "*-*-* 08:05:40"),
+            ("05:40", "*-*-* 05:40:00"),
+            ("Sat,Sun 08:05:40", "Sat,Sun *-*-* 08:05:40"),
+            (
+                "Sat,Thu,Mon..Wed,Sat..Sun",
+                "Mon..Thu,Sat,Sun *-*-* 00:00:00",
+            ),
+            ("minutely", "*-*-* *:*:00"),
+            ("hourly", "*-*-* *:00:00"),
+            ("daily", "*-*-* 00:00:00"),
+            ("daily UTC", "*-*-* 00:00:00 UTC"),
+            ("monthly", "*-*-01 00:00:00"),
+            ("weekly", "Mon *-*-* 00:00:00"),
+            (
+                "weekly Pacific/Auckland",
+                "Mon *-*-* 00:00:00 Pacific/Auckland",
+            ),
+            ("yearly", "*-01-01 00:00:00"),
+            ("annually", "*-01-01 00:00:00"),
+            ("quarterly", "*-01,04,07,10-01 00:00:00"),
+            ("semiannually", "*-01,07-01 00:00:00"),
+            // lists, ranges and repetitions run on every value they match
+            ("*:0/15", "*-*-* *:00,15,30,45:00"),
+            ("12..14:10,20,30", "*-*-* 12..14:10,20,30:00"),
+            ("*-*-1..7/3 9:00", "*-*-01,04,07 09:00:00"),
+            ("*-*-* 0..23:00:00", "*-*-* *:00:00"),
+        ];
+        for (expression, normalized) in test_cases {
+            let rrule = RRule::from_on_calendar(expression).unwrap();
+            assert_eq!(
+                normalized,
+                rrule.to_on_calendar().unwrap(),
+                "{}",
+                expression
+            );
+        }
+
+        let errors = vec![
+            ("Wed *-1", "a rule can't restrict both"),
+            ("2003-03-05 05:40", "specific year"),
+            ("*:2/0", "invalid minute"),
+            ("14..12:00", "invalid hour"),
+            ("*-*-1,32", "invalid day"),
+            ("Mon 09:30 tomorrow", "unsupported or misplaced part"),
+        ];
+        for (expression, error) in errors {
+            let message = RRule::from_on_calendar(expression).unwrap_err().to_string();
+            assert!(message.contains(error), "{}", message);
+        }
+
+        // the leap day iterations from the systemd-analyze(1) calendar example
+        let rrule = RRule::from_on_calendar("*-2-29 0:0:0").unwrap();
+        assert_eq!(
+            vec![
+                "2020-02-29T00:00:00+00:00",
+                "2024-02-29T00:00:00+00:00",
+                "2028-02-29T00:00:00+00:00",
+                "2032-02-29T00:00:00+00:00",
+                "2036-02-29T00:00:00+00:00",
+            ],
+            rrule
+                .get_all_iter_dates_between(
+                    "5",
+                    "",
+                    Some(Utc.ymd(2019, 3, 14).and_hms(0, 0, 0)),
+                    None
+                )
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+
+        let rrule = RRule::from_on_calendar("Mon..Fri *-*-* 09:30:00 Australia/Sydney").unwrap();
+        assert_eq!(
+            vec![
+                "2019-01-04T09:30:00+11:00",
+                "2019-01-07T09:30:00+11:00",
+                "2019-01-08T09:30:00+11:00",
+            ],
+            rrule
+                .get_all_iter_dates_between(
+                    "3",
+                    "",
+                    Some(Utc.ymd(2019, 1, 3).and_hms(12, 0, 0)),
+                    None
+                )
+                .iter()
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+
+        assert_eq!(
+            "*-*-01,15 09,17:00:00",
+            convert_to_rrule("FREQ=MONTHLY;BYMONTHDAY=15,1;BYHOUR=9,17;BYMINUTE=0")
+                .unwrap()
+                .to_on_calendar()
+                .unwrap()
+        );
+
+        let errors = vec![
+            (
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO;BYHOUR=9;BYMINUTE=0",
+                "can't skip occurrences",
+            ),
+            (
+                "FREQ=DAILY;UNTIL=20200101T000000Z;BYHOUR=9;BYMINUTE=0",
+                "never end",
+            ),
+            ("FREQ=SECONDLY", "SECONDLY"),
+        ];
+        for (rrule_string, error) in errors {
+            let message = convert_to_rrule(rrule_string)
+                .unwrap()
+                .to_on_calendar()
+                .unwrap_err()
+                .to_string();
+            assert!(message.contains(error), "{}", message);
+        }
+
+        let (code, stdout, _) = run_cli(&["from-oncalendar", "Mon..Fri", "09:30"]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=9;BYMINUTE=30;BYSECOND=0\n",
+            stdout
+        );
+        let (code, stdout, _) = run_cli(&[
+            "to-oncalendar",
+            "FREQ=MONTHLY;BYMONTHDAY=15;BYHOUR=9;BYMINUTE=0;TZID=Australia/Sydney",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!("*-*-15 09:00:00 Australia/Sydney\n", stdout);
+    }
+
+    #[test]
+    fn we_skip_months_without_the_day() {
+        let rrule_result = convert_to_rrule(
+            "FREQ=MONTHLY;COUNT=4;BYMONTHDAY=31;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "2019-01-31T09:00:00+00:00".to_owned(),
+                "2019-03-31T09:00:00+00:00".to_owned(),
+                "2019-05-31T09:00:00+00:00".to_owned(),
+                "2019-07-31T09:00:00+00:00".to_owned(),
+            ],
+            rrule_result.get_all_iter_dates_iso8601("", "")
+        );
+    }
+
+    #[test]
+    fn we_can_edit_rules_in_the_repl() {
+        let session = "FREQ=DAILY;COUNT=3;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000
+set BYHOUR=10;BYMINUTE=30
+unset COUNT
+set BYHOUR=25
+next 2
+quit
+";
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sundial"))
+            .args(["repl", "--count", "4"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(session.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(Some(0), output.status.code());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let expected = "  \"count\": \"3\",
+  \"byHour\": [
+    \"9\"
+  ],
+  \"byMinute\": [
+    \"0\"
+  ],
+  \"bySecond\": [
+    \"0\"
+  ]
+}
+2019-01-01T09:00:00+00:00
+2019-01-02T09:00:00+00:00
+2019-01-03T09:00:00+00:00
+FREQ=DAILY;COUNT=3;BYHOUR=10;BYMINUTE=30;BYSECOND=0;DTSTART=20190101T000000
+- 2019-01-01T09:00:00+00:00
++ 2019-01-01T10:30:00+00:00
+- 2019-01-02T09:00:00+00:00
++ 2019-01-02T10:30:00+00:00
+- 2019-01-03T09:00:00+00:00
++ 2019-01-03T10:30:00+00:00
+3 added, 3 removed
+FREQ=DAILY;BYHOUR=10;BYMINUTE=30;BYSECOND=0;DTSTART=20190101T000000
+  2019-01-01T10:30:00+00:00
+  2019-01-02T10:30:00+00:00
+  2019-01-03T10:30:00+00:00
++ 2019-01-04T10:30:00+00:00
+1 added, 0 removed
+error: RRule validation errors encountered: BYHOUR can only be in range 0-23 | Provided value [\"25\"]
+2019-01-01T10:30:00+00:00
+2019-01-02T10:30:00+00:00
+";
+        assert!(stdout.starts_with("{\n  \"dtstart\": \"2019-01-01 00:00:00\",\n"));
+        assert!(stdout.ends_with(expected), "{}", stdout);
+    }
+
+    #[test]
+    fn we_can_shift_occurrences_off_holidays() {
+        // Good Friday and Easter Monday in Victoria
+        let calendar = InMemoryHolidayCalendar::parse_csv(
+            "date,region,name
+2019-04-19,,Good Friday
+2019-04-22,,Easter Monday
+2019-11-05,au-vic,Melbourne Cup
+",
+            Some("au-nsw"),
+        )
+        .unwrap();
+        let rrule = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,FR;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190414T000000",
+        )
+        .unwrap();
+        let test_cases = vec![
+            (
+                ShiftPolicy::Skip,
+                vec!["2019-04-15", "2019-04-26", "2019-04-29", "2019-05-03"],
+            ),
+            (
+                ShiftPolicy::NextBusinessDay,
+                vec!["2019-04-15", "2019-04-23", "2019-04-26", "2019-04-29"],
+            ),
+            (
+                ShiftPolicy::PreviousBusinessDay,
+                vec!["2019-04-15", "2019-04-18", "2019-04-26", "2019-04-29"],
+            ),
+            (
+                ShiftPolicy::Nearest,
+                vec!["2019-04-15", "2019-04-18", "2019-04-23", "2019-04-26"],
+            ),
+        ];
+        for (policy, expected) in test_cases {
+            let dates: Vec<String> = rrule
+                .shifted(&calendar, policy)
+                .get_all_iter_dates_between("4", "", None, None)
+                .iter()
+                .map(|date| {
+                    assert_eq!(9, date.hour());
+                    date.format("%Y-%m-%d").to_string()
+                })
+                .collect();
+            assert_eq!(expected, dates, "{:?}", policy);
+        }
+
+        // the Melbourne Cup is only a holiday in Victoria
+        let monday = UTC.ymd(2019, 11, 4).and_hms(0, 0, 0);
+        let cup_day = convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0").unwrap();
+        let next = cup_day
+            .shifted(&calendar, ShiftPolicy::Skip)
+            .iter_dates_from(monday)
+            .nth(1)
+            .unwrap();
+        assert_eq!("2019-11-05T09:00:00+00:00", next.to_rfc3339());
+
+        // without DTSTART the occurrences start from the clock
+        let clock = FixedClock::new(Utc.ymd(2019, 4, 18).and_hms(0, 0, 0));
+        let cup_day = cup_day.with_clock(clock);
+        let dates: Vec<String> = cup_day
+            .shifted(&calendar, ShiftPolicy::Skip)
+            .get_all_iter_dates_between("2", "", None, None)
+            .iter()
+            .map(|date| date.to_rfc3339())
+            .collect();
+        assert_eq!(
+            vec!["2019-04-18T09:00:00+00:00", "2019-04-23T09:00:00+00:00"],
+            dates
+        );
+    }
+
+    #[test]
+    fn we_can_expand_with_a_holiday_file() {
+        let path = std::env::temp_dir().join("sundial_holidays_test.ics");
+        std::fs::write(
+            &path,
+            "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART;VALUE=DATE:20191225\r
+DTEND;VALUE=DATE:20191227\r
+SUMMARY:Christmas Day and Boxing Day\r
+END:VEVENT\r
+END:VCALENDAR\r
+",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let rrule =
+            "FREQ=WEEKLY;BYDAY=WE,TH;BYHOUR=9;BYMINUTE=0;BYSECOND=0;DTSTART=20191217T000000";
+
+        let (code, stdout, _) = run_cli(&[
+            "expand",
+            rrule,
+            "--count",
+            "4",
+            "--holidays",
+            path,
+            "--shift",
+            "next",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "[\"2019-12-18T09:00:00+00:00\",\"2019-12-19T09:00:00+00:00\",\"2019-12-27T09:00:00+00:00\",\"2020-01-01T09:00:00+00:00\"]\n",
+            stdout
+        );
+        let (code, stdout, _) = run_cli(&["expand", rrule, "--count", "3", "--holidays", path]);
+        assert_eq!(Some(0), code);
+        assert!(stdout.contains("2019-12-27T09:00:00"));
+
+        let (code, _, stderr) = run_cli(&["expand", rrule, "--shift", "next"]);
+        assert_eq!(Some(1), code);
+        assert!(stderr.contains("--holidays"));
+        let (code, _, stderr) =
+            run_cli(&["expand", rrule, "--holidays", "/nonexistent/holidays.ics"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("could not read"));
+        // iCalendar files have no regions to pick from
+        let (code, _, stderr) =
+            run_cli(&["expand", rrule, "--holidays", path, "--region", "au-vic"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("has no regions"));
+        assert!(FileHolidayCalendar::open(path, Some("au-vic")).is_err());
+    }
+
+    #[test]
+    fn we_can_derive_order_cutoffs() {
+        let deliveries = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=TU,FR;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190415T000000",
+        )
+        .unwrap();
+        let format_pairs = |pairs: Vec<(DateTime<Tz>, DateTime<Tz>)>| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(cutoff, delivery)| (cutoff.to_rfc3339(), delivery.to_rfc3339()))
+                .collect()
+        };
+        let pair = |cutoff: &str, delivery: &str| (cutoff.to_owned(), delivery.to_owned());
+
+        let schedule = DerivedSchedule::new(
+            &deliveries,
+            CutoffOffset::Duration(chrono::Duration::hours(36)),
+        );
+        assert_eq!(
+            vec![
+                pair("2019-04-14T18:00:00+10:00", "2019-04-16T06:00:00+10:00"),
+                pair("2019-04-17T18:00:00+10:00", "2019-04-19T06:00:00+10:00"),
+            ],
+            format_pairs(schedule.get_all_cutoffs_between("2", None, None))
+        );
+        // a cutoff before daylight saving ends keeps the offset in effect at the time
+        let after_dst = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190405T000000",
+        )
+        .unwrap();
+        let schedule = DerivedSchedule::new(&after_dst, CutoffOffset::Duration(Duration::days(3)));
+        assert_eq!(
+            vec![pair(
+                "2019-04-06T07:00:00+11:00",
+                "2019-04-09T06:00:00+10:00"
+            )],
+            format_pairs(schedule.get_all_cutoffs_between("1", None, None))
+        );
+        // without DTSTART the deliveries start from the clock
+        let undated = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=TU,FR;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap()
+        .with_clock(FixedClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+        let schedule = DerivedSchedule::new(
+            &undated,
+            CutoffOffset::Duration(chrono::Duration::hours(36)),
+        );
+        assert_eq!(
+            vec![pair(
+                "2019-04-14T18:00:00+10:00",
+                "2019-04-16T06:00:00+10:00"
+            )],
+            format_pairs(schedule.get_all_cutoffs_between("1", None, None))
+        );
+
+        // Good Friday and Easter Monday aren't business days
+        let calendar = InMemoryHolidayCalendar::new(vec![
+            chrono::NaiveDate::from_ymd(2019, 4, 19),
+            chrono::NaiveDate::from_ymd(2019, 4, 22),
+        ]);
+        let schedule = DerivedSchedule::new(
+            &deliveries,
+            CutoffOffset::BusinessDays {
+                days: 2,
+                time: chrono::NaiveTime::from_hms(14, 0, 0),
+                calendar: &calendar,
+            },
+        );
+        assert_eq!(
+            vec![
+                pair("2019-04-12T14:00:00+10:00", "2019-04-16T06:00:00+10:00"),
+                pair("2019-04-17T14:00:00+10:00", "2019-04-19T06:00:00+10:00"),
+                pair("2019-04-17T14:00:00+10:00", "2019-04-23T06:00:00+10:00"),
+                pair("2019-04-24T14:00:00+10:00", "2019-04-26T06:00:00+10:00"),
+            ],
+            format_pairs(schedule.get_all_cutoffs_between("4", None, None))
+        );
+        // an order on Wednesday morning still makes Friday's delivery
+        let (cutoff, delivery) = schedule
+            .delivery_for_order(Utc.ymd(2019, 4, 17).and_hms(0, 0, 0))
+            .unwrap();
+        assert_eq!("2019-04-17T14:00:00+10:00", cutoff.to_rfc3339());
+        assert_eq!("2019-04-19T06:00:00+10:00", delivery.to_rfc3339());
+        let (_, delivery) = schedule
+            .delivery_for_order(Utc.ymd(2019, 4, 17).and_hms(4, 0, 1))
+            .unwrap();
+        assert_eq!("2019-04-26T06:00:00+10:00", delivery.to_rfc3339());
+
+        // orders close at the Monday and Thursday order runs
+        let order_runs = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,TH;BYHOUR=14;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let schedule =
+            DerivedSchedule::new(&deliveries, CutoffOffset::PreviousOccurrence(&order_runs));
+        assert_eq!(
+            vec![
+                pair("2019-04-15T14:00:00+10:00", "2019-04-16T06:00:00+10:00"),
+                pair("2019-04-18T14:00:00+10:00", "2019-04-19T06:00:00+10:00"),
+                pair("2019-04-22T14:00:00+10:00", "2019-04-23T06:00:00+10:00"),
+            ],
+            format_pairs(schedule.get_all_cutoffs_between("3", None, None))
+        );
+
+        // other rules more than a year apart are looked back one INTERVAL
+        let stocktakes = convert_to_rrule(
+            "FREQ=MONTHLY;INTERVAL=24;BYMONTHDAY=1;BYHOUR=14;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let schedule =
+            DerivedSchedule::new(&deliveries, CutoffOffset::PreviousOccurrence(&stocktakes));
+        assert_eq!(
+            vec![
+                pair("2019-04-01T14:00:00+11:00", "2019-04-16T06:00:00+10:00"),
+                pair("2019-04-01T14:00:00+11:00", "2019-04-19T06:00:00+10:00"),
+            ],
+            format_pairs(schedule.get_all_cutoffs_between("2", None, None))
+        );
+    }
+
+    #[test]
+    fn we_can_print_order_cutoffs() {
+        let rrule = "FREQ=WEEKLY;BYDAY=TU,FR;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190415T000000";
+        let (code, stdout, _) = run_cli(&[
+            "cutoffs",
+            rrule,
+            "--business-days",
+            "1",
+            "--at",
+            "14:00",
+            "--count",
+            "2",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "[{\"cutoff\":\"2019-04-15T14:00:00+10:00\",\"delivery\":\"2019-04-16T06:00:00+10:00\"},{\"cutoff\":\"2019-04-18T14:00:00+10:00\",\"delivery\":\"2019-04-19T06:00:00+10:00\"}]\n",
+            stdout
+        );
+        let (code, stdout, _) = run_cli(&[
+            "cutoffs",
+            rrule,
+            "--offset",
+            "1d12h",
+            "--order-at",
+            "2019-04-15 12:00:00",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "{\"cutoff\":\"2019-04-17T18:00:00+10:00\",\"delivery\":\"2019-04-19T06:00:00+10:00\"}\n",
+            stdout
+        );
+        let (code, _, stderr) = run_cli(&["cutoffs", rrule, "--offset", "two days"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("duration two days"));
+    }
+
+    #[test]
+    fn we_can_merge_schedules() {
+        let bakery = convert_to_rrule(
+            "FREQ=DAILY;COUNT=3;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190414T000000",
+        )
+        .unwrap();
+        let butcher = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,WE;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190414T000000",
+        )
+        .unwrap();
+        // 20:00 UTC is 06:00 the next day in Melbourne
+        let dairy = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO;BYHOUR=20;BYMINUTE=0;BYSECOND=0;DTSTART=20190414T000000",
+        )
+        .unwrap();
+        let after = Some(Utc.ymd(2019, 4, 14).and_hms(20, 0, 0));
+        let merged = |options: MergeOptions| -> Vec<(&str, String)> {
+            sundial::merge(
+                vec![
+                    ("bakery", bakery.iter_dates_after(after)),
+                    ("butcher", butcher.iter_dates_after(after)),
+                    ("dairy", dairy.iter_dates_after(after)),
+                ],
+                options,
+            )
+            .take(5)
+            .map(|(source, date)| (source, date.to_rfc3339()))
+            .collect()
+        };
+
+        assert_eq!(
+            vec![
+                ("bakery", "2019-04-15T06:00:00+10:00".to_owned()),
+                ("butcher", "2019-04-15T06:00:00+10:00".to_owned()),
+                ("bakery", "2019-04-16T06:00:00+10:00".to_owned()),
+                ("dairy", "2019-04-15T20:00:00+00:00".to_owned()),
+                ("butcher", "2019-04-17T06:00:00+10:00".to_owned()),
+            ],
+            merged(MergeOptions::default())
+        );
+        // the bakery's COUNT ends it after the 16th
+        assert_eq!(
+            vec![
+                ("bakery", "2019-04-15T06:00:00+10:00".to_owned()),
+                ("bakery", "2019-04-16T06:00:00+10:00".to_owned()),
+                ("butcher", "2019-04-17T06:00:00+10:00".to_owned()),
+                ("butcher", "2019-04-22T06:00:00+10:00".to_owned()),
+                ("dairy", "2019-04-23T06:00:00+10:00".to_owned()),
+            ],
+            merged(MergeOptions {
+                dedup: true,
+                timezone: Some("Australia/Melbourne".parse().unwrap()),
+            })
+        );
+
+        // without DTSTART or an after the sources start from the clock
+        let undated = convert_to_rrule("FREQ=DAILY;BYHOUR=6;BYMINUTE=0;BYSECOND=0")
+            .unwrap()
+            .with_clock(FixedClock::new(Utc.ymd(2019, 4, 14).and_hms(0, 0, 0)));
+        assert_eq!(
+            vec!["2019-04-14T06:00:00+00:00", "2019-04-15T06:00:00+00:00"],
+            undated
+                .iter_dates_after(None)
+                .take(2)
+                .map(|date| date.to_rfc3339())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn we_can_merge_schedules_from_the_cli() {
+        let (code, stdout, _) = run_cli(&[
+            "merge",
+            "--rrule",
+            "FREQ=DAILY;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190414T000000",
+            "--rrule",
+            "FREQ=WEEKLY;BYDAY=MO;BYHOUR=20;BYMINUTE=0;BYSECOND=0;DTSTART=20190414T000000",
+            "--id",
+            "bakery",
+            "--id",
+            "dairy",
+            "--after",
+            "20190415T000000",
+            "--count",
+            "3",
+            "--output-tz",
+            "UTC",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "[{\"date\":\"2019-04-15T20:00:00+00:00\",\"source\":\"bakery\"},{\"date\":\"2019-04-15T20:00:00+00:00\",\"source\":\"dairy\"},{\"date\":\"2019-04-16T20:00:00+00:00\",\"source\":\"bakery\"}]\n",
+            stdout
+        );
+        let (code, _, stderr) =
+            run_cli(&["merge", "--rrule", "FREQ=DAILY", "--id", "a", "--id", "b"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("one --id per --rrule"));
+    }
+
+    #[test]
+    fn we_can_diff_schedules() {
+        let old = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,WE;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let new = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,WE,FR;BYHOUR=8;BYMINUTE=00;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let mut window = DiffWindow::new(
+            Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+            Utc.ymd(2019, 4, 21).and_hms(0, 0, 0),
+        );
+        let format = |dates: &[DateTime<Tz>]| -> Vec<String> {
+            dates.iter().map(|date| date.to_rfc3339()).collect()
+        };
+
+        let changes = sundial::diff(&old, &new, window);
+        // BYMINUTE=00 is the same part once normalized
+        assert_eq!(
+            vec![
+                PartChange {
+                    part: "byDay".to_owned(),
+                    old: Some(serde_json::json!(["MO", "WE"])),
+                    new: Some(serde_json::json!(["MO", "WE", "FR"])),
+                },
+                PartChange {
+                    part: "byHour".to_owned(),
+                    old: Some(serde_json::json!(["6"])),
+                    new: Some(serde_json::json!(["8"])),
+                },
+            ],
+            changes.parts
+        );
+        assert_eq!(vec!["2019-04-19T08:00:00+10:00"], format(&changes.added));
+        assert!(changes.removed.is_empty());
+        assert_eq!(
+            vec![
+                (
+                    "2019-04-15T06:00:00+10:00".to_owned(),
+                    "2019-04-15T08:00:00+10:00".to_owned()
+                ),
+                (
+                    "2019-04-17T06:00:00+10:00".to_owned(),
+                    "2019-04-17T08:00:00+10:00".to_owned()
+                ),
+            ],
+            changes
+                .moved
+                .iter()
+                .map(|moved| (moved.from.to_rfc3339(), moved.to.to_rfc3339()))
+                .collect::<Vec<(String, String)>>()
+        );
+        assert!(!changes.occurrences_unchanged());
+
+        // occurrences further apart than max_move are removed and added instead
+        window.max_move = Duration::hours(1);
+        let changes = sundial::diff(&old, &new, window);
+        assert!(changes.moved.is_empty());
+        assert_eq!(
+            vec!["2019-04-15T06:00:00+10:00", "2019-04-17T06:00:00+10:00"],
+            format(&changes.removed)
+        );
+        assert_eq!(3, changes.added.len());
+
+        // a TZID change with the same instants only changes the parts
+        let utc =
+            convert_to_rrule("FREQ=WEEKLY;BYDAY=SU,TU;BYHOUR=20;BYMINUTE=0;BYSECOND=0;TZID=UTC")
+                .unwrap();
+        let changes = sundial::diff(&old, &utc, window);
+        assert!(changes.occurrences_unchanged());
+        assert_eq!(
+            vec!["byDay", "byHour", "tzid"],
+            changes
+                .parts
+                .iter()
+                .map(|change| change.part.as_str())
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn we_can_diff_schedules_from_the_cli() {
+        let (code, stdout, _) = run_cli(&[
+            "diff",
+            "FREQ=WEEKLY;BYDAY=MO;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+            "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0;COUNT=1;TZID=Australia/Melbourne",
+            "--after",
+            "20190414T000000",
+            "--before",
+            "20190428T000000",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "{\"added\":[],\"moved\":[{\"from\":\"2019-04-15T06:00:00+10:00\",\"to\":\"2019-04-16T06:00:00+10:00\"}],\"parts\":[{\"new\":[\"TU\"],\"old\":[\"MO\"],\"part\":\"byDay\"},{\"new\":\"1\",\"old\":null,\"part\":\"count\"}],\"removed\":[\"2019-04-22T06:00:00+10:00\"]}\n",
+            stdout
+        );
+        let (code, _, stderr) = run_cli(&[
+            "diff",
+            "FREQ=DAILY",
+            "FREQ=WEEKLY",
+            "--after",
+            "20190414T000000",
+            "--before",
+            "20190401T000000",
+        ]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("before has to be later than after"));
+    }
+
+    #[test]
+    fn we_can_compute_event_spans() {
+        // daylight saving ends in Melbourne at 03:00 on April 7th 2019
+        let rrule = convert_to_rrule("FREQ=WEEKLY;BYDAY=SU;BYHOUR=1;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190330T140000").unwrap();
+        let span_on_the_7th = |event: &Event| {
+            let span = event
+                .iter_spans_after(Some(Utc.ymd(2019, 4, 6).and_hms(0, 0, 0)))
+                .next()
+                .unwrap();
+            (span.start.to_rfc3339(), span.end.to_rfc3339())
+        };
+        let start = "2019-04-07T01:00:00+11:00".to_owned();
+
+        // hours are exact time, days are calendar days
+        let event = Event::with_duration(&rrule, "PT3H").unwrap();
+        assert_eq!(
+            (start.clone(), "2019-04-07T03:00:00+10:00".to_owned()),
+            span_on_the_7th(&event)
+        );
+        let event = Event::with_duration(&rrule, "P1DT1H").unwrap();
+        assert_eq!(
+            (start.clone(), "2019-04-08T02:00:00+10:00".to_owned()),
+            span_on_the_7th(&event)
+        );
+        // DTEND keeps the wall clock end of the first occurrence
+        let event = Event::with_end(&rrule, "20190330T170000").unwrap();
+        assert_eq!(EventLength::WallClock(Duration::hours(3)), event.length());
+        assert_eq!(
+            (start, "2019-04-07T04:00:00+10:00".to_owned()),
+            span_on_the_7th(&event)
+        );
+
+        // an occurrence that started before the window and is still going on intersects it
+        let spans = event.spans_between(
+            Utc.ymd(2019, 4, 6).and_hms(16, 0, 0),
+            Utc.ymd(2019, 4, 13).and_hms(0, 0, 0),
+        );
+        assert_eq!(1, spans.len());
+        assert_eq!(Duration::hours(4), spans[0].length());
+        assert!(event.contains(Utc.ymd(2019, 4, 6).and_hms(17, 30, 0)));
+        assert!(!event.contains(Utc.ymd(2019, 4, 6).and_hms(18, 0, 0)));
+        assert_eq!(None, event.span_at(Utc.ymd(2019, 4, 13).and_hms(0, 0, 0)));
+
+        assert!(EventLength::parse_duration("-PT1H").is_err());
+        assert!(EventLength::parse_duration("PT").is_err());
+        assert_eq!(
+            EventLength::Duration {
+                days: 14,
+                time: Duration::zero()
+            },
+            EventLength::parse_duration("P2W").unwrap()
+        );
+        let without_dtstart = convert_to_rrule("FREQ=DAILY;BYHOUR=9").unwrap();
+        assert!(Event::with_end(&without_dtstart, "20190330T170000").is_err());
+        // the length only depends on DTSTART and DTEND, also for a zoned DTSTART
+        let zoned = convert_to_rrule("DTSTART;TZID=Australia/Melbourne:20190330T090000;FREQ=DAILY")
+            .unwrap();
+        assert_eq!(
+            EventLength::WallClock(Duration::hours(8)),
+            EventLength::from_dtend(&zoned, "20190330T060000Z").unwrap()
+        );
+    }
+
+    #[test]
+    fn we_can_print_event_spans() {
+        let rrule = "FREQ=WEEKLY;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190401T000000";
+        let (code, stdout, _) = run_cli(&["spans", rrule, "--duration", "PT2H", "--count", "2"]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "[{\"end\":\"2019-04-02T11:00:00+11:00\",\"start\":\"2019-04-02T09:00:00+11:00\"},{\"end\":\"2019-04-09T11:00:00+10:00\",\"start\":\"2019-04-09T09:00:00+10:00\"}]\n",
+            stdout
+        );
+        let (code, stdout, _) = run_cli(&[
+            "spans",
+            rrule,
+            "--duration",
+            "PT2H",
+            "--at",
+            "20190409T000000",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "{\"end\":\"2019-04-09T11:00:00+10:00\",\"start\":\"2019-04-09T09:00:00+10:00\"}\n",
+            stdout
+        );
+        let (_, stdout, _) = run_cli(&[
+            "spans",
+            rrule,
+            "--duration",
+            "PT2H",
+            "--at",
+            "20190409T010000",
+        ]);
+        assert_eq!("null\n", stdout);
+        let (code, _, stderr) = run_cli(&["spans", rrule, "--duration", "2h"]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("invalid duration"));
+    }
+
+    #[test]
+    fn we_can_find_conflicts() {
+        let bakery =
+            convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne")
+                .unwrap();
+        let dairy = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,WE;BYHOUR=10;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let butcher =
+            convert_to_rrule("FREQ=DAILY;BYHOUR=11;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne")
+                .unwrap();
+        // Monday April 15th in Melbourne
+        let (after, before) = (
+            Utc.ymd(2019, 4, 14).and_hms(14, 0, 0),
+            Utc.ymd(2019, 4, 15).and_hms(14, 0, 0),
+        );
+        let summary =
+            |conflicts: Vec<sundial::Conflict<&'static str>>| -> Vec<(&str, &str, String, i64)> {
+                conflicts
+                    .into_iter()
+                    .map(|conflict| {
+                        (
+                            conflict.first.0,
+                            conflict.second.0,
+                            conflict.second.1.start.to_rfc3339(),
+                            conflict.overlap.num_minutes(),
+                        )
+                    })
+                    .collect()
+            };
+
+        let conflicts = find_conflicts(
+            &[
+                ("bakery", Event::with_duration(&bakery, "PT2H").unwrap()),
+                ("dairy", Event::with_duration(&dairy, "PT3H").unwrap()),
+                ("butcher", Event::with_duration(&butcher, "PT1H").unwrap()),
+            ],
+            after,
+            before,
+        );
+        // the bakery leaves at 11:00 as the butcher arrives, which isn't a conflict
+        assert_eq!(
+            vec![
+                (
+                    "bakery",
+                    "dairy",
+                    "2019-04-15T10:00:00+10:00".to_owned(),
+                    60
+                ),
+                (
+                    "dairy",
+                    "butcher",
+                    "2019-04-15T11:00:00+10:00".to_owned(),
+                    60
+                ),
+            ],
+            summary(conflicts)
+        );
+
+        // occurrences of one event can conflict with each other too
+        let overnight =
+            convert_to_rrule("FREQ=DAILY;BYHOUR=22;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne")
+                .unwrap();
+        let conflicts = find_conflicts(
+            &[(
+                "overnight",
+                Event::with_duration(&overnight, "PT25H").unwrap(),
+            )],
+            after,
+            before,
+        );
+        assert_eq!(
+            vec![(
+                "overnight",
+                "overnight",
+                "2019-04-15T22:00:00+10:00".to_owned(),
+                60
+            )],
+            summary(conflicts)
+        );
+    }
+
+    #[test]
+    fn we_can_print_conflicts() {
+        let (code, stdout, _) = run_cli(&[
+            "conflicts",
+            "--rrule",
+            "FREQ=WEEKLY;BYDAY=TU;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+            "--rrule",
+            "FREQ=DAILY;BYHOUR=10;BYMINUTE=30;BYSECOND=0;TZID=Australia/Melbourne",
+            "--duration",
+            "PT2H",
+            "--after",
+            "20190414T000000",
+            "--before",
+            "20190421T000000",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "[{\"first\":{\"end\":\"2019-04-16T11:00:00+10:00\",\"source\":\"1\",\"start\":\"2019-04-16T09:00:00+10:00\"},\"overlap\":\"PT30M\",\"second\":{\"end\":\"2019-04-16T12:30:00+10:00\",\"source\":\"2\",\"start\":\"2019-04-16T10:30:00+10:00\"}}]\n",
+            stdout
+        );
+        let (code, _, stderr) = run_cli(&[
+            "conflicts",
+            "--rrule",
+            "FREQ=DAILY",
+            "--rrule",
+            "FREQ=DAILY",
+            "--rrule",
+            "FREQ=DAILY",
+            "--duration",
+            "PT1H",
+            "--duration",
+            "PT2H",
+            "--before",
+            "20190421T000000",
+        ]);
+        assert_eq!(Some(3), code);
+        assert!(stderr.contains("one --duration for all of them or one per --rrule"));
+    }
+
+    #[test]
+    fn we_can_compute_free_busy_time() {
+        let deliveries =
+            convert_to_rrule("FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne")
+                .unwrap();
+        let stocktake = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO;BYHOUR=11;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let busy = [
+            Event::with_duration(&deliveries, "PT3H").unwrap(),
+            Event::with_duration(&stocktake, "PT2H").unwrap(),
+        ];
+        let format = |periods: &[Period]| -> Vec<(String, String)> {
+            periods
+                .iter()
+                .map(|period| (period.start.to_rfc3339(), period.end.to_rfc3339()))
+                .collect()
+        };
+        let period = |start: &str, end: &str| (start.to_owned(), end.to_owned());
+
+        // Monday and Tuesday in Melbourne, the stocktake runs on from Monday's delivery
+        let periods = free_busy(
+            &busy,
+            Utc.ymd(2019, 4, 14).and_hms(14, 0, 0),
+            Utc.ymd(2019, 4, 16).and_hms(14, 0, 0),
+        );
+        assert_eq!(
+            vec![
+                period("2019-04-14T23:00:00+00:00", "2019-04-15T03:00:00+00:00"),
+                period("2019-04-15T23:00:00+00:00", "2019-04-16T02:00:00+00:00"),
+            ],
+            format(&periods.busy)
+        );
+        assert_eq!(
+            vec![
+                period("2019-04-14T14:00:00+00:00", "2019-04-14T23:00:00+00:00"),
+                period("2019-04-15T03:00:00+00:00", "2019-04-15T23:00:00+00:00"),
+                period("2019-04-16T02:00:00+00:00", "2019-04-16T14:00:00+00:00"),
+            ],
+            format(&periods.free)
+        );
+        assert_eq!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//ordermentum//sundial//EN\r\nBEGIN:VFREEBUSY\r\nUID:20190414T140000Z-20190416T140000Z-freebusy@sundial\r\nDTSTAMP:20190401T000000Z\r\nDTSTART:20190414T140000Z\r\nDTEND:20190416T140000Z\r\nFREEBUSY;FBTYPE=BUSY:20190414T230000Z/PT4H\r\nFREEBUSY;FBTYPE=BUSY:20190415T230000Z/PT3H\r\nEND:VFREEBUSY\r\nEND:VCALENDAR\r\n",
+            periods.to_ics(Utc.ymd(2019, 4, 1).and_hms(0, 0, 0))
+        );
+
+        // within working hours from 08:00 to 17:00 on weekdays
+        let weekdays = convert_to_rrule(
+            "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=8;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+        )
+        .unwrap();
+        let working_hours = Event::with_duration(&weekdays, "PT9H").unwrap();
+        let saturday = Utc.ymd(2019, 4, 13).and_hms(0, 0, 0);
+        let slot = |duration: Duration, constraints: SlotConstraints| {
+            next_free_slot(&busy, saturday, duration, constraints)
+                .map(|slot| format(&[slot]).remove(0))
+        };
+        let constraints = SlotConstraints {
+            working_hours: Some(&working_hours),
+            before: None,
+        };
+        assert_eq!(
+            Some(period(
+                "2019-04-15T03:00:00+00:00",
+                "2019-04-15T07:00:00+00:00"
+            )),
+            slot(Duration::hours(4), constraints)
+        );
+        // Monday's stocktake leaves 4 hours, Tuesday afternoon has 5
+        assert_eq!(
+            Some(period(
+                "2019-04-16T02:00:00+00:00",
+                "2019-04-16T07:00:00+00:00"
+            )),
+            slot(Duration::hours(5), constraints)
+        );
+        assert_eq!(None, slot(Duration::hours(6), constraints));
+        // without working hours the night is free too, once the delivery going on ends
+        assert_eq!(
+            Some(period(
+                "2019-04-13T02:00:00+00:00",
+                "2019-04-13T14:00:00+00:00"
+            )),
+            slot(Duration::hours(12), SlotConstraints::default())
+        );
+        assert_eq!(
+            None,
+            slot(
+                Duration::hours(24),
+                SlotConstraints {
+                    working_hours: None,
+                    before: Some(Utc.ymd(2019, 5, 1).and_hms(0, 0, 0)),
+                }
+            )
+        );
+
+        assert_eq!("PT4H", format_ics_duration(Duration::hours(4)));
+        assert_eq!(
+            "P1DT2H30M",
+            format_ics_duration(Duration::minutes(26 * 60 + 30))
+        );
+        assert_eq!("P2D", format_ics_duration(Duration::days(2)));
+        assert_eq!("PT0S", format_ics_duration(Duration::zero()));
+    }
+
+    #[test]
+    fn we_can_print_free_busy_time() {
+        let (code, stdout, _) = run_cli(&[
+            "freebusy",
+            "--rrule",
+            "FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+            "--duration",
+            "PT3H",
+            "--after",
+            "20190414T140000",
+            "--before",
+            "20190415T140000",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "{\"busy\":[{\"end\":\"2019-04-15T02:00:00Z\",\"start\":\"2019-04-14T23:00:00Z\"}],\"free\":[{\"end\":\"2019-04-14T23:00:00Z\",\"start\":\"2019-04-14T14:00:00Z\"},{\"end\":\"2019-04-15T14:00:00Z\",\"start\":\"2019-04-15T02:00:00Z\"}]}\n",
+            stdout
+        );
+
+        let (code, stdout, _) = run_cli(&[
+            "next-slot",
+            "--rrule",
+            "FREQ=DAILY;BYHOUR=9;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+            "--duration",
+            "PT3H",
+            "--length",
+            "PT2H",
+            "--working-hours",
+            "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;BYHOUR=8;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+            "--working-duration",
+            "PT9H",
+            "--after",
+            "20190413T000000",
+            "--output-tz",
+            "Australia/Melbourne",
+        ]);
+        assert_eq!(Some(0), code);
+        assert_eq!(
+            "{\"end\":\"2019-04-15T14:00:00+10:00\",\"start\":\"2019-04-15T12:00:00+10:00\"}\n",
+            stdout
+        );
+    }
+
+    #[cfg(feature = "scheduler")]
+    #[tokio::test(start_paused = true)]
+    async fn we_can_schedule_jobs() {
+        use std::sync::{Arc, Mutex};
+        use sundial::{JobRun, MissedRunPolicy, Scheduler, TokioClock};
+
+        let scheduler =
+            Scheduler::with_clock(TokioClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+        let runs: Arc<Mutex<Vec<JobRun>>> = Arc::default();
+        let add_job = |policy: MissedRunPolicy| {
+            let runs = runs.clone();
+            scheduler
+                .add_job("FREQ=HOURLY;BYMINUTE=0;BYSECOND=0", policy, move |run| {
+                    runs.lock().unwrap().push(run)
+                })
+                .unwrap()
+        };
+        let take_runs = || -> Vec<(String, bool)> {
+            runs.lock()
+                .unwrap()
+                .drain(..)
+                .map(|run| (run.scheduled.to_rfc3339(), run.missed))
+                .collect()
+        };
+        let run = |scheduled: &str, missed: bool| (scheduled.to_owned(), missed);
+        let hours = |hours: u64| std::time::Duration::from_secs(hours * 3600 + 1);
+
+        // the paused clock jumps ahead to each timer while the test sleeps
+        let fire_all = add_job(MissedRunPolicy::FireAll);
+        assert_eq!(
+            "2019-04-15T01:00:00+00:00",
+            scheduler.next_run(fire_all).unwrap().to_rfc3339()
+        );
+        tokio::time::sleep(hours(2)).await;
+        assert_eq!(
+            vec![
+                run("2019-04-15T01:00:00+00:00", false),
+                run("2019-04-15T02:00:00+00:00", false),
+            ],
+            take_runs()
+        );
+
+        scheduler.pause();
+        tokio::time::sleep(hours(2)).await;
+        assert!(take_runs().is_empty());
+        scheduler.resume();
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        assert_eq!(
+            vec![
+                run("2019-04-15T03:00:00+00:00", true),
+                run("2019-04-15T04:00:00+00:00", true),
+            ],
+            take_runs()
+        );
+
+        // the other policies
+        scheduler.remove_job(fire_all);
+        add_job(MissedRunPolicy::FireOnce);
+        add_job(MissedRunPolicy::Skip);
+        scheduler.pause();
+        tokio::time::sleep(hours(3)).await;
+        scheduler.resume();
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        assert_eq!(vec![run("2019-04-15T07:00:00+00:00", true)], take_runs());
+        tokio::time::sleep(hours(1)).await;
+        assert_eq!(
+            vec![
+                run("2019-04-15T08:00:00+00:00", false),
+                run("2019-04-15T08:00:00+00:00", false),
+            ],
+            take_runs()
+        );
+    }
+
+    #[cfg(feature = "scheduler")]
+    #[tokio::test(start_paused = true)]
+    async fn we_keep_firing_jobs_after_a_callback_panics() {
+        use std::sync::{Arc, Mutex};
+        use sundial::{MissedRunPolicy, Scheduler, TokioClock};
+
+        let scheduler =
+            Scheduler::with_clock(TokioClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+        let runs: Arc<Mutex<Vec<String>>> = Arc::default();
+        let panicking = scheduler
+            .add_job(
+                "FREQ=HOURLY;BYMINUTE=0;BYSECOND=0",
+                MissedRunPolicy::FireAll,
+                |_| panic!("the delivery service is down"),
+            )
+            .unwrap();
+        let job_runs = runs.clone();
+        scheduler
+            .add_job(
+                "FREQ=HOURLY;BYMINUTE=0;BYSECOND=0",
+                MissedRunPolicy::FireAll,
+                move |run| job_runs.lock().unwrap().push(run.scheduled.to_rfc3339()),
+            )
+            .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_secs(2 * 3600 + 1)).await;
+        assert_eq!(
+            vec!["2019-04-15T01:00:00+00:00", "2019-04-15T02:00:00+00:00"],
+            *runs.lock().unwrap()
+        );
+        // the panicking job moved on too
+        assert_eq!(
+            "2019-04-15T03:00:00+00:00",
+            scheduler.next_run(panicking).unwrap().to_rfc3339()
+        );
+    }
+
+    #[cfg(feature = "scheduler")]
+    #[tokio::test(start_paused = true)]
+    async fn we_fire_the_latest_missed_runs_after_a_long_pause() {
+        use std::sync::{Arc, Mutex};
+        use sundial::{JobRun, MissedRunPolicy, Scheduler, TokioClock};
+
+        let scheduler =
+            Scheduler::with_clock(TokioClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+        let runs: Arc<Mutex<Vec<JobRun>>> = Arc::default();
+        let add_job = |rule: &str, policy: MissedRunPolicy| {
+            let runs = runs.clone();
+            scheduler
+                .add_job(rule, policy, move |run| runs.lock().unwrap().push(run))
+                .unwrap()
+        };
+        let fire_all = add_job("FREQ=MINUTELY;BYSECOND=0", MissedRunPolicy::FireAll);
+        let skip = add_job("FREQ=MINUTELY;COUNT=5;BYSECOND=0", MissedRunPolicy::Skip);
+
+        scheduler.pause();
+        tokio::time::sleep(std::time::Duration::from_secs(2000 * 60 + 30)).await;
+        scheduler.resume();
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        let fired: Vec<JobRun> = runs.lock().unwrap().drain(..).collect();
+        // only the last 1000 of the 2000 missed runs are fired, and the skipped ones
+        // used up the COUNT of the other job
+        assert_eq!(1000, fired.len());
+        assert!(fired.iter().all(|run| run.job == fire_all && run.missed));
+        assert_eq!("2019-04-15T16:41:00+00:00", fired[0].scheduled.to_rfc3339());
+        assert_eq!(
+            "2019-04-16T09:20:00+00:00",
+            fired[999].scheduled.to_rfc3339()
+        );
+        assert_eq!(
+            "2019-04-16T09:21:00+00:00",
+            scheduler.next_run(fire_all).unwrap().to_rfc3339()
+        );
+        assert_eq!(None, scheduler.next_run(skip));
+    }
+
+    #[cfg(feature = "scheduler")]
+    #[tokio::test(start_paused = true)]
+    async fn we_can_swap_the_rule_of_a_job() {
+        use std::sync::{Arc, Mutex};
+        use sundial::{MissedRunPolicy, Scheduler, TokioClock};
+
+        let scheduler =
+            Scheduler::with_clock(TokioClock::new(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0)));
+        let runs: Arc<Mutex<Vec<String>>> = Arc::default();
+        let job_runs = runs.clone();
+        let job = scheduler
+            .add_job(
+                "FREQ=HOURLY;COUNT=2;BYMINUTE=0;BYSECOND=0",
+                MissedRunPolicy::FireAll,
+                move |run| job_runs.lock().unwrap().push(run.scheduled.to_rfc3339()),
+            )
+            .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_secs(90 * 60)).await;
+        scheduler
+            .update_rule(
+                job,
+                "FREQ=HOURLY;COUNT=2;BYMINUTE=45;BYSECOND=0;TZID=Australia/Melbourne",
+            )
+            .unwrap();
+        // like any rule without a DTSTART, it starts from now, 11:30 in Melbourne, so
+        // 11:45 is its first run
+        assert_eq!(
+            "2019-04-15T11:45:00+10:00",
+            scheduler.next_run(job).unwrap().to_rfc3339()
+        );
+        tokio::time::sleep(std::time::Duration::from_secs(24 * 3600)).await;
+        assert_eq!(
+            vec![
+                "2019-04-15T01:00:00+00:00",
+                "2019-04-15T11:45:00+10:00",
+                "2019-04-15T12:45:00+10:00",
+            ],
+            *runs.lock().unwrap()
+        );
+        // COUNT ended it
+        assert_eq!(None, scheduler.next_run(job));
+
+        assert!(scheduler
+            .update_rule(job, "FREQ=HOURLY;BYMINUTE=61")
+            .unwrap_err()
+            .to_string()
+            .starts_with("scheduler error"));
+    }
+
+    /// A store file of its own for each test, removed before and after it
+    fn store_path(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("sundial-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(sibling(&path, ".log"));
+        path
+    }
+
+    /// The path of the store file with `suffix` appended, such as its log
+    fn sibling(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(suffix);
+        sibling.into()
+    }
+
+    #[test]
+    fn we_can_keep_schedules_in_a_store() {
+        let path = store_path("store");
+        let after = Utc.ymd(2019, 4, 14).and_hms(0, 0, 0);
+        let ids = |schedules: Vec<StoredSchedule>| -> Vec<(String, String)> {
+            schedules
+                .into_iter()
+                .map(|schedule| (schedule.id, schedule.next_due.unwrap().to_rfc3339()))
+                .collect()
+        };
+        let due = |id: &str, next_due: &str| (id.to_owned(), next_due.to_owned());
+
+        let mut store = FileScheduleStore::open(&path).unwrap();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("supplier".to_owned(), "Bakery".to_owned());
+        let bakery = store
+            .put(
+                "bakery",
+                "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+                metadata.clone(),
+                after,
+            )
+            .unwrap();
+        assert_eq!(
+            Some(Utc.ymd(2019, 4, 15).and_hms(20, 0, 0)),
+            bakery.next_due
+        );
+        store
+            .put(
+                "dairy",
+                "FREQ=DAILY;BYHOUR=7;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+                BTreeMap::new(),
+                after,
+            )
+            .unwrap();
+        store
+            .put(
+                "trial",
+                "FREQ=DAILY;COUNT=2;BYHOUR=12;BYMINUTE=0;BYSECOND=0",
+                BTreeMap::new(),
+                after,
+            )
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                due("trial", "2019-04-14T12:00:00+00:00"),
+                due("dairy", "2019-04-14T21:00:00+00:00"),
+            ],
+            ids(store
+                .due_before(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0))
+                .unwrap())
+        );
+        // strictly before
+        assert_eq!(
+            vec![due("trial", "2019-04-14T12:00:00+00:00")],
+            ids(store
+                .due_before(Utc.ymd(2019, 4, 14).and_hms(21, 0, 0))
+                .unwrap())
+        );
+
+        let dairy_due = Utc.ymd(2019, 4, 14).and_hms(21, 0, 0);
+        let next_due = Some(Utc.ymd(2019, 4, 15).and_hms(21, 0, 0));
+        assert_eq!(next_due, store.acknowledge("dairy", dairy_due).unwrap());
+        // acknowledging it again changes nothing
+        assert_eq!(next_due, store.acknowledge("dairy", dairy_due).unwrap());
+        assert!(store
+            .acknowledge("dairy", Utc.ymd(2019, 4, 16).and_hms(21, 0, 0))
+            .unwrap_err()
+            .to_string()
+            .starts_with("store error: schedule dairy is due at 2019-04-15T21:00:00+00:00 first"));
+
+        // the COUNT of a rule without DTSTART holds across acknowledgements
+        let trial_due = store
+            .acknowledge("trial", Utc.ymd(2019, 4, 14).and_hms(12, 0, 0))
+            .unwrap();
+        assert_eq!(Some(Utc.ymd(2019, 4, 15).and_hms(12, 0, 0)), trial_due);
+        assert_eq!(
+            None,
+            store.acknowledge("trial", trial_due.unwrap()).unwrap()
+        );
+        assert_eq!(None, store.get("trial").unwrap().unwrap().next_due);
+
+        // everything is still there when the file is opened again
+        let mut store = FileScheduleStore::open(&path).unwrap();
+        assert_eq!(Some(bakery), store.get("bakery").unwrap());
+        assert_eq!(
+            vec![
+                due("bakery", "2019-04-15T20:00:00+00:00"),
+                due("dairy", "2019-04-15T21:00:00+00:00"),
+            ],
+            ids(store
+                .due_before(Utc.ymd(2019, 4, 20).and_hms(0, 0, 0))
+                .unwrap())
+        );
+
+        // a new rule replaces the old one and is due from when it's put
+        store
+            .put(
+                "dairy",
+                "FREQ=DAILY;BYHOUR=8;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne",
+                BTreeMap::new(),
+                Utc.ymd(2019, 4, 16).and_hms(0, 0, 0),
+            )
+            .unwrap();
+        assert!(store.remove("bakery").unwrap());
+        assert!(!store.remove("bakery").unwrap());
+        assert_eq!(
+            vec![due("dairy", "2019-04-16T22:00:00+00:00")],
+            ids(store
+                .due_before(Utc.ymd(2019, 4, 20).and_hms(0, 0, 0))
+                .unwrap())
+        );
+        assert!(store
+            .put("bad", "FREQ=DAILY;BYHOUR=25", BTreeMap::new(), after)
+            .is_err());
+        assert!(store
+            .acknowledge("bakery", dairy_due)
+            .unwrap_err()
+            .to_string()
+            .contains("there is no schedule bakery"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn we_log_acknowledgements_and_fold_them_into_the_store() {
+        let path = store_path("store-log");
+        let log = sibling(&path, ".log");
+        let mut store = FileScheduleStore::open(&path).unwrap();
+        store
+            .put(
+                "dairy",
+                "FREQ=DAILY;BYHOUR=7;BYMINUTE=0;BYSECOND=0",
+                BTreeMap::new(),
+                Utc.ymd(2019, 4, 14).and_hms(0, 0, 0),
+            )
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+
+        // acknowledgements go to the log and the file stays as it is
+        let mut due = store.get("dairy").unwrap().unwrap().next_due.unwrap();
+        for _ in 0..99 {
+            due = store.acknowledge("dairy", due).unwrap().unwrap();
+        }
+        assert_eq!(written, std::fs::read_to_string(&path).unwrap());
+        assert_eq!(99, std::fs::read_to_string(&log).unwrap().lines().count());
+        let reopened = FileScheduleStore::open(&path).unwrap();
+        assert_eq!(Some(due), reopened.get("dairy").unwrap().unwrap().next_due);
+        assert_eq!(Utc.ymd(2019, 7, 22).and_hms(7, 0, 0), due);
+
+        // a line cut short by a crash is dropped
+        let mut contents = std::fs::read_to_string(&log).unwrap();
+        contents.push_str("{\"generation\":1,\"id\":\"dai");
+        std::fs::write(&log, contents).unwrap();
+        let mut store = FileScheduleStore::open(&path).unwrap();
+        assert_eq!(Some(due), store.get("dairy").unwrap().unwrap().next_due);
+        assert_eq!(99, std::fs::read_to_string(&log).unwrap().lines().count());
+
+        // the hundredth acknowledgement folds the log into the file
+        due = store.acknowledge("dairy", due).unwrap().unwrap();
+        assert!(!log.exists());
+        assert_ne!(written, std::fs::read_to_string(&path).unwrap());
+        let store = FileScheduleStore::open(&path).unwrap();
+        assert_eq!(Some(due), store.get("dairy").unwrap().unwrap().next_due);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn we_keep_the_store_as_it_was_written_when_a_change_fails() {
+        let path = store_path("store-failure");
+        let after = Utc.ymd(2019, 4, 14).and_hms(0, 0, 0);
+        let mut store = FileScheduleStore::open(&path).unwrap();
+        let dairy = store
+            .put(
+                "dairy",
+                "FREQ=DAILY;BYHOUR=7;BYMINUTE=0;BYSECOND=0",
+                BTreeMap::new(),
+                after,
+            )
+            .unwrap();
+
+        // directories in the way of the temporary file and the log make writes fail
+        let (temporary, log) = (sibling(&path, ".tmp"), sibling(&path, ".log"));
+        std::fs::create_dir(&temporary).unwrap();
+        std::fs::create_dir(&log).unwrap();
+        assert!(store
+            .put(
+                "bakery",
+                "FREQ=WEEKLY;BYDAY=TU;BYHOUR=6;BYMINUTE=0;BYSECOND=0",
+                BTreeMap::new(),
+                after,
+            )
+            .unwrap_err()
+            .to_string()
+            .starts_with("store error: can't write"));
+        assert_eq!(None, store.get("bakery").unwrap());
+        assert!(store.remove("dairy").is_err());
+        assert!(store.acknowledge("dairy", dairy.next_due.unwrap()).is_err());
+        assert_eq!(Some(dairy.clone()), store.get("dairy").unwrap());
+        assert_eq!(
+            1,
+            store
+                .due_before(Utc.ymd(2019, 4, 15).and_hms(0, 0, 0))
+                .unwrap()
+                .len()
+        );
+
+        std::fs::remove_dir(&temporary).unwrap();
+        std::fs::remove_dir(&log).unwrap();
+        assert!(store.remove("dairy").unwrap());
+        assert_eq!(
+            None,
+            FileScheduleStore::open(&path)
+                .unwrap()
+                .get("dairy")
+                .unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn we_cant_open_an_invalid_store() {
+        let path = store_path("invalid-store");
+        std::fs::write(&path, "{\"schedules\":[{\"id\":\"dairy\"}]}").unwrap();
+        let err = FileScheduleStore::open(&path).err().unwrap().to_string();
+        assert!(err.starts_with("store error: invalid store file"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn we_can_materialize_a_schedule() {
+        let rule = "FREQ=WEEKLY;BYDAY=MO,TH;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20190101T000000";
+        let rrule = convert_to_rrule(rule).unwrap();
+        let mut schedule = MaterializedSchedule::new(rule, Duration::weeks(4)).unwrap();
+
+        // a week at a time, across the end of daylight saving, each extension covers the
+        // week asked for and four more
+        let mut after = Utc.ymd(2019, 3, 24).and_hms(0, 0, 0);
+        for _ in 0..10 {
+            let before = after + Duration::weeks(1);
+            let expected: Vec<DateTime<Tz>> = rrule
+                .iter_dates_after(Some(after))
+                .take_while(|date| date.with_timezone(&Utc) < before)
+                .collect();
+            assert_eq!(2, expected.len());
+            assert_eq!(expected, schedule.dates_between(after, before));
+            after = before;
+        }
+        assert_eq!(CacheStats { hits: 8, misses: 2 }, schedule.stats());
+
+        let expected: Vec<DateTime<Tz>> = rrule.iter_dates_after(Some(after)).take(52).collect();
+        assert_eq!(expected, schedule.next_dates(after, 52));
+        assert_eq!(expected, schedule.next_dates(after, 52));
+        assert_eq!(CacheStats { hits: 9, misses: 3 }, schedule.stats());
+
+        // going back before the cache starts works the occurrences out again
+        let earlier = Utc.ymd(2019, 1, 1).and_hms(0, 0, 0);
+        assert_eq!(
+            "2019-01-03T06:00:00+11:00",
+            schedule.next_dates(earlier, 1)[0].to_rfc3339()
+        );
+        assert_eq!(4, schedule.stats().misses);
+
+        // a COUNT ends it
+        let mut schedule = MaterializedSchedule::new(
+            "FREQ=DAILY;COUNT=3;BYHOUR=6;BYMINUTE=0;BYSECOND=0;DTSTART=20190101T000000",
+            Duration::days(1),
+        )
+        .unwrap();
+        let dates: Vec<String> = schedule
+            .next_dates(earlier, 52)
+            .iter()
+            .map(|date| date.to_rfc3339())
+            .collect();
+        assert_eq!(
+            vec![
+                "2019-01-01T06:00:00+00:00",
+                "2019-01-02T06:00:00+00:00",
+                "2019-01-03T06:00:00+00:00",
+            ],
+            dates
+        );
+        assert_eq!(
+            2,
+            schedule
+                .dates_between(earlier + Duration::days(1), earlier + Duration::days(30))
+                .len()
+        );
+        assert_eq!(CacheStats { hits: 1, misses: 1 }, schedule.stats());
+
+        assert!(
+            MaterializedSchedule::new("FREQ=DAILY;BYHOUR=25", Duration::weeks(1))
+                .err()
+                .unwrap()
+                .to_string()
+                .starts_with("materialize error")
+        );
+        assert!(MaterializedSchedule::new("FREQ=DAILY", Duration::zero()).is_err());
+    }
+
+    /// A calendar that can change while schedules hold it
+    struct ChangingCalendar(std::sync::Mutex<InMemoryHolidayCalendar>);
+
+    impl HolidayCalendar for ChangingCalendar {
+        fn is_holiday(&self, date: NaiveDate) -> bool {
+            self.0.lock().unwrap().is_holiday(date)
+        }
+
+        fn version(&self) -> u64 {
+            self.0.lock().unwrap().version()
+        }
+    }
+
+    #[test]
+    fn we_can_invalidate_a_materialized_schedule() {
+        let rule = "FREQ=DAILY;BYHOUR=6;BYMINUTE=0;BYSECOND=0;TZID=Australia/Melbourne;DTSTART=20191201T000000";
+        let calendar = Arc::new(ChangingCalendar(std::sync::Mutex::new(
+            InMemoryHolidayCalendar::new(vec![NaiveDate::from_ymd(2019, 12, 25)]),
+        )));
+        let mut schedule = MaterializedSchedule::new(rule, Duration::weeks(4))
+            .unwrap()
+            .with_holidays(calendar.clone(), ShiftPolicy::Skip);
+        let (after, before) = (
+            Utc.ymd(2019, 12, 22).and_hms(0, 0, 0),
+            Utc.ymd(2019, 12, 29).and_hms(0, 0, 0),
+        );
+        let days = |schedule: &mut MaterializedSchedule| -> Vec<u32> {
+            schedule
+                .dates_between(after, before)
+                .iter()
+                .map(|date| date.day())
+                .collect()
+        };
+
+        // weekends and Christmas are skipped, like with RRule::shifted
+        let rrule = convert_to_rrule(rule).unwrap();
+        let shifted = rrule
+            .shifted(calendar.as_ref(), ShiftPolicy::Skip)
+            .get_all_iter_dates_between("", "", Some(after), Some(before));
+        assert_eq!(shifted, schedule.dates_between(after, before));
+        assert_eq!(vec![23, 24, 26, 27], days(&mut schedule));
+        assert_eq!(CacheStats { hits: 1, misses: 1 }, schedule.stats());
+
+        calendar
+            .0
+            .lock()
+            .unwrap()
+            .add(NaiveDate::from_ymd(2019, 12, 26));
+        assert_eq!(vec![23, 24, 27], days(&mut schedule));
+        assert_eq!(CacheStats { hits: 1, misses: 2 }, schedule.stats());
+
+        // the same rule again keeps the cache, another one drops it
+        schedule.set_rule(rule).unwrap();
+        assert_eq!(vec![23, 24, 27], days(&mut schedule));
+        schedule
+            .set_rule(&rule.replace("FREQ=DAILY", "FREQ=WEEKLY;BYDAY=MO,FR"))
+            .unwrap();
+        assert_eq!(vec![23, 27], days(&mut schedule));
+        assert_eq!(CacheStats { hits: 2, misses: 3 }, schedule.stats());
+
+        schedule.set_tz_version("");
+        schedule.set_tz_version("2019c");
+        assert_eq!("2019c", schedule.tz_version());
+        assert_eq!(vec![23, 27], days(&mut schedule));
+        schedule.set_holidays(None);
+        assert_eq!(vec![23, 27], days(&mut schedule));
+        assert_eq!(CacheStats { hits: 2, misses: 5 }, schedule.stats());
+    }
 }